@@ -1,6 +1,6 @@
 use std::{collections::HashMap, io::Read};
 
-use byteorder::{ReadBytesExt, LE};
+use ser_hex::TracedReadExt;
 use tracing::instrument;
 
 type Result<R> = std::result::Result<R, Box<dyn std::error::Error>>;
@@ -20,20 +20,20 @@ mod test {
 
 #[instrument(skip_all)]
 pub fn read<R: Read>(reader: &mut R) -> Result<Element> {
-    let _size = reader.read_u32::<LE>()?;
+    let _size = reader.traced_u32_le()?;
     let mut map = HashMap::default();
     loop {
-        let type_ = reader.read_i8()?;
+        let type_ = reader.traced_i8()?;
         if type_ == 0 {
             break;
         }
-        let name = read_cstr(reader)?;
+        let name = reader.traced_cstring()?.into_string()?;
         let element = match type_ {
-            1 => read_double(reader)?,
+            1 => Element::Double(reader.traced_f64_le()?),
             2 => read_string(reader)?,
             3 => read(reader)?,
             4 => read(reader)?,
-            16 => read_i32(reader)?,
+            16 => Element::I32(reader.traced_i32_le()?),
             _ => todo!("type {type_}"),
         };
         map.insert(name, element);
@@ -50,32 +50,9 @@ pub enum Element {
     Array(HashMap<String, Element>),
 }
 
-#[instrument(skip_all)]
-fn read_double<R: Read>(reader: &mut R) -> Result<Element> {
-    Ok(Element::Double(reader.read_f64::<LE>()?))
-}
-
-#[instrument(skip_all)]
-fn read_i32<R: Read>(reader: &mut R) -> Result<Element> {
-    Ok(Element::I32(reader.read_i32::<LE>()?))
-}
-
-#[instrument(skip_all)]
-fn read_cstr<R: Read>(reader: &mut R) -> Result<String> {
-    let mut buf = vec![];
-    loop {
-        let next = reader.read_u8()?;
-        if next == 0 {
-            break;
-        }
-        buf.push(next);
-    }
-    Ok(String::from_utf8(buf)?)
-}
-
 #[instrument(skip_all)]
 fn read_string<R: Read>(reader: &mut R) -> Result<Element> {
-    let length = reader.read_u32::<LE>()?;
+    let length = reader.traced_u32_le()?;
     let mut buf = vec![0; length as usize];
     reader.read_exact(&mut buf)?;
     Ok(Element::String(String::from_utf8(
@@ -22,13 +22,35 @@ mod test {
         let mut input = std::io::Cursor::new(include_bytes!("../level.nbt"));
         let mut tracer = ser_hex_tracer::TracerReader::new_options(
             &mut input,
-            ser_hex_tracer::TracerOptions { skip_frames: 3 }, // depends on amount of inlining for build config
+            ser_hex_tracer::TracerOptions {
+                include_symbols: vec![ser_hex_tracer::SymbolPattern::glob("nbt::*")],
+                ..Default::default()
+            },
         );
         let res = read(&mut tracer);
         tracer.trace().save("trace_tracer.json").unwrap();
         println!("{res:#?}");
         Ok(())
     }
+
+    #[test]
+    fn test_nbt_tracer_span_path() -> Result<()> {
+        let mut input = std::io::Cursor::new(include_bytes!("../level.nbt"));
+        let res = ser_hex::read_incremental("trace_tracing_span_path.json", &mut input, |s| {
+            let mut tracer = ser_hex_tracer::TracerReader::new_options(
+                s,
+                ser_hex_tracer::TracerOptions {
+                    span_path: true,
+                    ..Default::default()
+                },
+            );
+            let res = read(&mut tracer);
+            tracer.trace().save("trace_tracer_span_path.json").unwrap();
+            res
+        })?;
+        println!("{res:#?}");
+        Ok(())
+    }
 }
 
 type TagByte = i8;
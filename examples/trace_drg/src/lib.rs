@@ -124,15 +124,17 @@ fn main() {
                     READER_STACK.with_borrow_mut(|s| {
                         if let Some(last) = s.last_mut() {
                             if last.is_none() {
-                                *last = Some((reader, Tracer::new()));
+                                let mut tracer = Tracer::new();
+                                // record seeks when the offset is changed from somewhere else
+                                let address = reader as usize;
+                                tracer.set_position_provider(move || {
+                                    (*(address as *const FMemoryReader)).offset
+                                });
+                                *last = Some((reader, tracer));
                             }
                             let (r, tracer) = last.as_mut().unwrap();
                             if *r == reader {
                                 tracer.read(std::slice::from_raw_parts(out.cast(), len as usize));
-                                let reader = &*(reader as *mut FMemoryReader);
-
-                                // verify offset hasn't been changed on us from somewhere else
-                                assert_eq!(reader.offset, tracer.data().len() as u64);
                             }
                         }
                     });
@@ -46,16 +46,9 @@ struct StreamProxy {
 impl Drop for StreamProxy {
     fn drop(&mut self) {
         println!("finalizing...");
-        use sha2::{Digest, Sha256};
-        use std::fmt::Write;
-
-        let trace = self.tracer.trace();
-        let hash: String = Sha256::digest(trace.data)
-            .iter()
-            .fold(String::new(), |mut s, b| {
-                write!(s, "{b:02x}").unwrap();
-                s
-            });
+        let mut trace = self.tracer.trace();
+        trace.update_checksum();
+        let hash = trace.metadata.data_sha256.as_deref().unwrap();
         let path = format!("traces/trace-{hash}.json");
         println!("saving to {path}");
         std::fs::create_dir("traces").ok();
@@ -128,6 +121,7 @@ impl StreamProxy {
             owns_stream,
             tracer: ser_hex_tracer::Tracer::new_options(ser_hex_tracer::TracerOptions {
                 skip_frames: 4,
+                ..Default::default()
             }),
         }
     }
@@ -0,0 +1,46 @@
+/* C API for building ser-hex traces from native code */
+#ifndef SER_HEX_H
+#define SER_HEX_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SerHexTrace SerHexTrace;
+
+/* Return codes of the functions below */
+#define SERHEX_OK 0
+/* Null handle or string argument which is not valid UTF-8 */
+#define SERHEX_ERROR_ARGUMENT -1
+/* Failed to serialize or write the trace */
+#define SERHEX_ERROR_IO -2
+/* Panic inside the library, the handle should only be freed afterwards */
+#define SERHEX_ERROR_PANIC -3
+
+/* Create an empty trace with an open root span, returns NULL on failure */
+SerHexTrace *serhex_new(void);
+void serhex_free(SerHexTrace *trace);
+
+/* Open a child span of the innermost open span */
+int serhex_begin_span(SerHexTrace *trace, const char *name);
+/* Close the innermost open span */
+int serhex_end_span(SerHexTrace *trace);
+
+/* Record a read of len bytes at the current offset */
+int serhex_read(SerHexTrace *trace, const uint8_t *data, size_t len);
+/* Record a seek to an absolute offset */
+int serhex_seek(SerHexTrace *trace, uint64_t offset);
+/* Annotate the innermost open span with a decoded value */
+int serhex_value(SerHexTrace *trace, const char *type_name, const char *value);
+
+/* Save everything recorded so far, closing open spans in the saved copy */
+int serhex_save(const SerHexTrace *trace, const char *path);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
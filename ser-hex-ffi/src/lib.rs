@@ -0,0 +1,219 @@
+//! C API for building traces from native code, see `include/ser_hex.h`
+
+use std::{
+    ffi::{c_char, c_int, CStr},
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::Path,
+};
+
+use ser_hex::{TraceBuilder, Value};
+
+pub const SERHEX_OK: c_int = 0;
+/// Null handle or string argument which is not valid UTF-8
+pub const SERHEX_ERROR_ARGUMENT: c_int = -1;
+/// Failed to serialize or write the trace
+pub const SERHEX_ERROR_IO: c_int = -2;
+/// Panic inside the library, the handle should only be freed afterwards
+pub const SERHEX_ERROR_PANIC: c_int = -3;
+
+/// Opaque handle to a trace being built
+pub struct SerHexTrace(TraceBuilder);
+
+/// Convert a C string, returning None for null pointers
+///
+/// # Safety
+/// `s` must be null or a valid nul terminated string
+unsafe fn c_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// Run `f` on the builder behind `trace`, keeping panics from unwinding into C
+///
+/// # Safety
+/// `trace` must be null or a live handle
+unsafe fn with_trace(trace: *mut SerHexTrace, f: impl FnOnce(&mut TraceBuilder)) -> c_int {
+    let Some(trace) = trace.as_mut() else {
+        return SERHEX_ERROR_ARGUMENT;
+    };
+    match catch_unwind(AssertUnwindSafe(|| f(&mut trace.0))) {
+        Ok(()) => SERHEX_OK,
+        Err(_) => SERHEX_ERROR_PANIC,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn serhex_new() -> *mut SerHexTrace {
+    catch_unwind(|| Box::into_raw(Box::new(SerHexTrace(TraceBuilder::new()))))
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// # Safety
+/// `trace` must be null or a pointer returned by [`serhex_new`] which has not been freed
+#[no_mangle]
+pub unsafe extern "C" fn serhex_free(trace: *mut SerHexTrace) {
+    if !trace.is_null() {
+        // nothing to report a panic in a destructor to
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(trace))));
+    }
+}
+
+/// # Safety
+/// `trace` must be a live handle and `name` a valid nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn serhex_begin_span(trace: *mut SerHexTrace, name: *const c_char) -> c_int {
+    let Some(name) = c_str(name) else {
+        return SERHEX_ERROR_ARGUMENT;
+    };
+    with_trace(trace, |trace| trace.begin_span(name.to_string()))
+}
+
+/// # Safety
+/// `trace` must be a live handle
+#[no_mangle]
+pub unsafe extern "C" fn serhex_end_span(trace: *mut SerHexTrace) -> c_int {
+    with_trace(trace, |trace| trace.end_span())
+}
+
+/// # Safety
+/// `trace` must be a live handle and `data` must point to `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn serhex_read(
+    trace: *mut SerHexTrace,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    let data = if len == 0 {
+        &[]
+    } else if data.is_null() {
+        return SERHEX_ERROR_ARGUMENT;
+    } else {
+        std::slice::from_raw_parts(data, len)
+    };
+    with_trace(trace, |trace| trace.read(data))
+}
+
+/// # Safety
+/// `trace` must be a live handle
+#[no_mangle]
+pub unsafe extern "C" fn serhex_seek(trace: *mut SerHexTrace, offset: u64) -> c_int {
+    with_trace(trace, |trace| trace.seek(offset as usize))
+}
+
+/// # Safety
+/// `trace` must be a live handle and `type_name` and `value` valid nul terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn serhex_value(
+    trace: *mut SerHexTrace,
+    type_name: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    let (Some(type_name), Some(value)) = (c_str(type_name), c_str(value)) else {
+        return SERHEX_ERROR_ARGUMENT;
+    };
+    with_trace(trace, |trace| {
+        trace.value(Value {
+            type_name: type_name.to_string(),
+            value: value.to_string(),
+        })
+    })
+}
+
+/// Save everything recorded so far
+///
+/// # Safety
+/// `trace` must be a live handle and `path` a valid nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn serhex_save(trace: *const SerHexTrace, path: *const c_char) -> c_int {
+    let (Some(trace), Some(path)) = (trace.as_ref(), c_str(path)) else {
+        return SERHEX_ERROR_ARGUMENT;
+    };
+    let saved = catch_unwind(AssertUnwindSafe(|| {
+        let trace = ser_hex::Trace {
+            metadata: trace
+                .0
+                .metadata
+                .clone()
+                .with_defaults("ser-hex-ffi", env!("CARGO_PKG_VERSION")),
+            ..trace.0.trace()
+        };
+        trace.save(Path::new(path))
+    }));
+    match saved {
+        Ok(Ok(())) => SERHEX_OK,
+        Ok(Err(_)) => SERHEX_ERROR_IO,
+        Err(_) => SERHEX_ERROR_PANIC,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::ffi::CString;
+
+    use ser_hex::{Action, Trace};
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("ser-hex-ffi-{}.json", std::process::id()));
+        let c_path = CString::new(path.to_str().unwrap())?;
+        let name = CString::new("header")?;
+        let (type_name, value) = (CString::new("u16")?, CString::new("513")?);
+
+        unsafe {
+            let trace = serhex_new();
+            assert!(!trace.is_null());
+            assert_eq!(serhex_begin_span(trace, name.as_ptr()), SERHEX_OK);
+            assert_eq!(serhex_read(trace, [1, 2].as_ptr(), 2), SERHEX_OK);
+            assert_eq!(
+                serhex_value(trace, type_name.as_ptr(), value.as_ptr()),
+                SERHEX_OK
+            );
+            assert_eq!(serhex_end_span(trace), SERHEX_OK);
+            assert_eq!(serhex_seek(trace, 4), SERHEX_OK);
+            assert_eq!(serhex_read(trace, [5].as_ptr(), 1), SERHEX_OK);
+            assert_eq!(serhex_save(trace, c_path.as_ptr()), SERHEX_OK);
+            serhex_free(trace);
+        }
+
+        let loaded = Trace::<Vec<u8>>::load(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(loaded.data, [1, 2, 0, 0, 5]);
+        assert_eq!(loaded.metadata.tool.as_deref(), Some("ser-hex-ffi"));
+        let Action::Span(root) = &loaded.root else {
+            panic!("root is not a span");
+        };
+        let Some(Action::Span(header)) = root.0.actions.first() else {
+            panic!("missing header span");
+        };
+        assert_eq!(header.0.name, "header");
+        assert_eq!(
+            header.0.value.as_ref().map(|v| v.value.as_str()),
+            Some("513")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        let missing = CString::new("/nonexistent/dir/trace.json").unwrap();
+        unsafe {
+            assert_eq!(serhex_end_span(std::ptr::null_mut()), SERHEX_ERROR_ARGUMENT);
+            assert_eq!(
+                serhex_save(std::ptr::null(), missing.as_ptr()),
+                SERHEX_ERROR_ARGUMENT
+            );
+
+            let trace = serhex_new();
+            assert_eq!(
+                serhex_begin_span(trace, std::ptr::null()),
+                SERHEX_ERROR_ARGUMENT
+            );
+            assert_eq!(serhex_save(trace, missing.as_ptr()), SERHEX_ERROR_IO);
+            serhex_free(trace);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Ident, ItemFn, LitStr};
+
+/// Wrap a function in a span named after the function
+///
+/// - `name = "..."` overrides the span name
+/// - `args(a, b)` appends the values of the given arguments to the recorded span name
+///
+/// The span of an `async fn` is entered each time its future is polled rather than held across
+/// `.await` points
+#[proc_macro_attribute]
+pub fn instrument(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name: Option<LitStr> = None;
+    let mut args: Vec<Ident> = vec![];
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("args") {
+            meta.parse_nested_meta(|meta| {
+                args.push(meta.path.require_ident()?.clone());
+                Ok(())
+            })
+        } else {
+            Err(meta.error("unsupported ser_hex::instrument argument"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+
+    let mut func = parse_macro_input!(item as ItemFn);
+    let name =
+        name.unwrap_or_else(|| LitStr::new(&func.sig.ident.to_string(), func.sig.ident.span()));
+
+    let span = if args.is_empty() {
+        quote! { ::ser_hex::__private::tracing::info_span!(#name) }
+    } else {
+        quote! { ::ser_hex::__private::tracing::info_span!(#name, #(ser_hex.arg.#args = ?#args),*) }
+    };
+    let block = &func.block;
+    func.block = if func.sig.asyncness.is_some() {
+        parse_quote!({
+            ::ser_hex::__private::tracing::Instrument::instrument(async move #block, #span).await
+        })
+    } else {
+        parse_quote!({
+            let __ser_hex_span = #span.entered();
+            #block
+        })
+    };
+    quote!(#func).into()
+}
+
+/// No-op variant of [`instrument`] used when ser-hex is built without the `instrument` feature
+#[proc_macro_attribute]
+pub fn instrument_disabled(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    item
+}
+
+/// Wrap each field of a `#[derive(BinRead)]` struct in a span named after the field
+///
+/// Must be placed above the derive. Fields which already use `parse_with` or are not read from
+/// the stream (`calc`, `ignore`, `default`) as well as those using `map`/`try_map` are left as is.
+#[proc_macro_attribute]
+pub fn binrw_spans(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut item = parse_macro_input!(item as syn::ItemStruct);
+    if !matches!(item.fields, syn::Fields::Named(_)) {
+        return syn::Error::new_spanned(&item, "ser_hex::binrw::spans requires named fields")
+            .to_compile_error()
+            .into();
+    }
+    let syn::Fields::Named(fields) = &mut item.fields else {
+        unreachable!()
+    };
+
+    const SKIP: &[&str] = &["parse_with", "calc", "ignore", "default", "map", "try_map"];
+    let mut helpers = vec![];
+    for field in &mut fields.named {
+        let options = match attr_options(&field.attrs, &["br", "brw"]) {
+            Ok(options) => options,
+            Err(err) => return err.to_compile_error().into(),
+        };
+        if options.iter().any(|(key, _)| SKIP.contains(&key.as_str())) {
+            continue;
+        }
+        let ident = field.ident.as_ref().unwrap();
+        let name = LitStr::new(&ident.to_string(), ident.span());
+        let helper = quote::format_ident!("__ser_hex_{}_{}", item.ident, ident);
+        field.attrs.push(parse_quote!(#[br(parse_with = #helper)]));
+        helpers.push(quote! {
+            #[doc(hidden)]
+            #[allow(non_snake_case)]
+            fn #helper<R, T>(
+                reader: &mut R,
+                endian: ::ser_hex::__private::binrw::Endian,
+                args: <T as ::ser_hex::__private::binrw::BinRead>::Args<'_>,
+            ) -> ::ser_hex::__private::binrw::BinResult<T>
+            where
+                R: ::std::io::Read + ::std::io::Seek,
+                T: ::ser_hex::__private::binrw::BinRead,
+            {
+                ::ser_hex::binrw::read_named(reader, endian, args, #name)
+            }
+        });
+    }
+    quote!(#item #(#helpers)*).into()
+}
+
+/// Wrap each field of a `#[derive(DekuRead)]` struct in a span named after the field
+///
+/// Must be placed above the derive. Fields are read with the `endian` of the field or struct.
+/// Fields which already use a custom `reader`, are not read from the stream (`skip`, `cond`) or
+/// need context beyond endianness (`bits`, `bytes`, `count`, `until`, `ctx`, `map`, ...) are left
+/// as is.
+#[proc_macro_attribute]
+pub fn deku_spans(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut item = parse_macro_input!(item as syn::ItemStruct);
+    if !matches!(item.fields, syn::Fields::Named(_)) {
+        return syn::Error::new_spanned(&item, "ser_hex::deku::spans requires named fields")
+            .to_compile_error()
+            .into();
+    }
+    let struct_endian = match attr_options(&item.attrs, &["deku"]) {
+        Ok(options) => options
+            .into_iter()
+            .find_map(|(key, value)| (key == "endian").then_some(value).flatten()),
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let syn::Fields::Named(fields) = &mut item.fields else {
+        unreachable!()
+    };
+
+    const SKIP: &[&str] = &[
+        "reader",
+        "skip",
+        "cond",
+        "default",
+        "bits",
+        "bytes",
+        "count",
+        "bits_read",
+        "bytes_read",
+        "until",
+        "read_all",
+        "ctx",
+        "map",
+        "bit_order",
+    ];
+    for field in &mut fields.named {
+        let options = match attr_options(&field.attrs, &["deku"]) {
+            Ok(options) => options,
+            Err(err) => return err.to_compile_error().into(),
+        };
+        if options.iter().any(|(key, _)| SKIP.contains(&key.as_str())) {
+            continue;
+        }
+        let endian = options
+            .into_iter()
+            .find_map(|(key, value)| (key == "endian").then_some(value).flatten())
+            .or_else(|| struct_endian.clone());
+        let ctx = match endian.as_ref().map(LitStr::value).as_deref() {
+            None => "()".to_string(),
+            Some("big") => "::ser_hex::__private::deku::ctx::Endian::Big".to_string(),
+            Some("little") => "::ser_hex::__private::deku::ctx::Endian::Little".to_string(),
+            // expression e.g. an endian passed in through the struct's ctx
+            Some(expr) => expr.to_string(),
+        };
+        let ident = field.ident.as_ref().unwrap();
+        let reader = LitStr::new(
+            &format!(
+                "::ser_hex::deku::read_named(deku::reader, {ctx}, {:?})",
+                ident.to_string()
+            ),
+            ident.span(),
+        );
+        field.attrs.push(parse_quote!(#[deku(reader = #reader)]));
+    }
+    quote!(#item).into()
+}
+
+/// Keys and string literal values of the `#[name(...)]` attributes with any of the given names.
+/// Other values such as `count = len as usize` or `args(a, b)` are parsed but not returned
+fn attr_options(
+    attrs: &[syn::Attribute],
+    names: &[&str],
+) -> syn::Result<Vec<(String, Option<LitStr>)>> {
+    let mut options = vec![];
+    for attr in attrs {
+        if !names.iter().any(|name| attr.path().is_ident(name)) {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            let key = meta
+                .path
+                .get_ident()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            let value = if meta.input.peek(syn::Token![=]) {
+                match meta.value()?.parse()? {
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(value),
+                        ..
+                    }) => Some(value),
+                    _ => None,
+                }
+            } else {
+                if meta.input.peek(syn::token::Paren) || meta.input.peek(syn::token::Brace) {
+                    meta.input.parse::<proc_macro2::Group>()?;
+                }
+                None
+            };
+            options.push((key, value));
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
@@ -0,0 +1,196 @@
+//! Python bindings for reading and analyzing traces
+//!
+//! ```python
+//! import ser_hex
+//!
+//! trace = ser_hex.Trace.load("trace.json")
+//! for span in trace.spans():
+//!     print("  " * span.depth, span.name, trace.slice(span.start, span.end).hex())
+//! print(f"{trace.coverage():.1%} read, gaps: {trace.uncovered_ranges()}")
+//! ```
+
+use std::{ops::Range, path::PathBuf};
+
+use pyo3::{exceptions::PyIOError, prelude::*, types::PyBytes};
+use ser_hex::{Action, TreeSpan};
+
+#[pyclass(name = "Trace", frozen)]
+struct PyTrace(ser_hex::Trace);
+
+/// A span along with the byte range touched by its actions
+#[pyclass(get_all, frozen)]
+struct Span {
+    name: String,
+    /// Indices starting from the children of the root span
+    path: Vec<usize>,
+    depth: usize,
+    start: usize,
+    end: usize,
+    value: Option<String>,
+    type_name: Option<String>,
+}
+
+/// A read along with the name of the span it was performed in
+#[pyclass(get_all, frozen)]
+struct Read {
+    start: usize,
+    end: usize,
+    path: Vec<usize>,
+    span: String,
+}
+
+#[pymethods]
+impl PyTrace {
+    #[staticmethod]
+    fn load(path: PathBuf) -> PyResult<Self> {
+        ser_hex::Trace::load(path)
+            .map(Self)
+            .map_err(|e| PyIOError::new_err(format!("{e:#}")))
+    }
+    #[getter]
+    fn version(&self) -> u32 {
+        self.0.version
+    }
+    #[getter]
+    fn start_index(&self) -> usize {
+        self.0.start_index
+    }
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new_bound(py, &self.0.data)
+    }
+    #[getter]
+    fn metadata(&self) -> Vec<(String, String)> {
+        self.0.metadata.entries()
+    }
+    fn __len__(&self) -> usize {
+        self.0.data.len()
+    }
+    /// Bytes of the data in `start..end`, clamped to the data length
+    fn slice<'py>(&self, py: Python<'py>, start: usize, end: usize) -> Bound<'py, PyBytes> {
+        let len = self.0.data.len();
+        PyBytes::new_bound(
+            py,
+            &self.0.data[start.min(len)..end.clamp(start.min(len), len)],
+        )
+    }
+    /// All spans in depth first order
+    fn spans(&self) -> Vec<Span> {
+        fn walk(
+            action: &Action<TreeSpan>,
+            offset: &mut usize,
+            path: &mut Vec<usize>,
+            spans: &mut Vec<Span>,
+        ) -> Range<usize> {
+            let start = *offset;
+            let Action::Span(span) = action else {
+                // non-span actions flatten to exactly one action ending at the new offset
+                let range = ser_hex::FlatIter::new(action, start)
+                    .next()
+                    .map_or(start..start, |flat| flat.range);
+                *offset = range.end;
+                return range;
+            };
+            let index = spans.len();
+            spans.push(Span {
+                name: span.0.name.to_string(),
+                path: path.clone(),
+                depth: path.len(),
+                start,
+                end: start,
+                value: span.0.value.as_ref().map(|v| v.value.clone()),
+                type_name: span.0.value.as_ref().map(|v| v.type_name.clone()),
+            });
+            let mut range = start..start;
+            for (i, child) in span.0.actions.iter().enumerate() {
+                path.push(i);
+                let child = walk(child, offset, path, spans);
+                path.pop();
+                range = range.start.min(child.start)..range.end.max(child.end);
+            }
+            spans[index].start = range.start;
+            spans[index].end = range.end;
+            range
+        }
+        let mut spans = vec![];
+        walk(
+            &self.0.root,
+            &mut { self.0.start_index },
+            &mut vec![],
+            &mut spans,
+        );
+        spans
+    }
+    /// All reads in order
+    fn reads(&self) -> Vec<Read> {
+        self.0
+            .iter_flat()
+            .filter(|flat| flat.action.read_size().is_some())
+            .map(|flat| Read {
+                start: flat.range.start,
+                end: flat.range.end,
+                path: flat.index_path(),
+                span: flat
+                    .path
+                    .last()
+                    .map(|s| s.span.0.name.to_string())
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+    /// Fraction of the data read at least once
+    fn coverage(&self) -> f64 {
+        self.0.coverage()
+    }
+    fn covered_ranges(&self) -> Vec<(usize, usize)> {
+        self.0
+            .covered_ranges()
+            .into_iter()
+            .map(|r| (r.start, r.end))
+            .collect()
+    }
+    fn uncovered_ranges(&self) -> Vec<(usize, usize)> {
+        self.0
+            .uncovered_ranges()
+            .into_iter()
+            .map(|r| (r.start, r.end))
+            .collect()
+    }
+    /// Standalone trace of the span at `path` and the bytes it covers
+    fn extract(&self, path: Vec<usize>) -> Option<Self> {
+        self.0.extract(&path).map(Self)
+    }
+    fn validate(&self) -> Vec<String> {
+        self.0.validate().iter().map(|p| p.to_string()).collect()
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "ser_hex")]
+fn ser_hex_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyTrace>()?;
+    m.add_class::<Span>()?;
+    m.add_class::<Read>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_coverage() {
+        let mut builder = ser_hex::TraceBuilder::new();
+        builder.read(&[1, 2]);
+        builder.seek(3);
+        builder.read(&[4]);
+        let trace = PyTrace(builder.trace());
+
+        assert_eq!(trace.__len__(), 4);
+        assert_eq!(trace.coverage(), 0.75);
+        assert_eq!(trace.covered_ranges(), [(0, 2), (3, 4)]);
+        assert_eq!(trace.uncovered_ranges(), [(2, 3)]);
+        let reads: Vec<_> = trace.reads().iter().map(|r| (r.start, r.end)).collect();
+        assert_eq!(reads, [(0, 2), (3, 4)]);
+    }
+}
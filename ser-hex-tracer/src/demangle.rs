@@ -0,0 +1,23 @@
+//! Demangling of Rust, Itanium (GCC/Clang) and MSVC symbol names
+
+/// Demangle `raw` according to its mangling scheme, Rust names are printed without their hash.
+/// Returns `raw` unchanged if it is not mangled or fails to demangle
+pub fn demangle(raw: &str) -> String {
+    if let Ok(name) = rustc_demangle::try_demangle(raw) {
+        return format!("{name:#}");
+    }
+    if raw.starts_with("_Z") || raw.starts_with("__Z") {
+        let demangled = cpp_demangle::Symbol::new(raw)
+            .ok()
+            .and_then(|s| s.demangle(&Default::default()).ok());
+        if let Some(name) = demangled {
+            return name;
+        }
+    }
+    if raw.starts_with('?') {
+        if let Ok(name) = msvc_demangler::demangle(raw, msvc_demangler::DemangleFlags::llvm()) {
+            return name;
+        }
+    }
+    raw.to_owned()
+}
@@ -0,0 +1,69 @@
+//! Export of the recorded reads as flamegraphs, weighted by the number of bytes read
+
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    io::{self, Write},
+};
+
+use crate::{OpKind, Stack, Tracer};
+
+impl Tracer {
+    /// Write the stack of each read in the folded format of flamegraph tools, one
+    /// `root;frame;frame bytes` line per unique stack, e.g. for `inferno-flamegraph` or
+    /// speedscope. Frames are named and filtered like the spans of [`Tracer::trace`]
+    pub fn write_folded(&self, mut writer: impl Write) -> io::Result<()> {
+        for (stack, bytes) in self.folded() {
+            writeln!(writer, "{stack} {bytes}")?;
+        }
+        Ok(())
+    }
+    /// Render the stack of each read as an interactive SVG flamegraph, where the width of each
+    /// frame is the number of bytes read below it
+    #[cfg(feature = "flamegraph")]
+    pub fn write_flamegraph_svg(&self, writer: impl Write) -> io::Result<()> {
+        let lines: Vec<_> = self
+            .folded()
+            .into_iter()
+            .map(|(stack, bytes)| format!("{stack} {bytes}"))
+            .collect();
+        let mut options = inferno::flamegraph::Options::default();
+        options.title = "Bytes read".into();
+        options.count_name = "bytes".into();
+        inferno::flamegraph::from_lines(&mut options, lines.iter().map(String::as_str), writer)
+    }
+    /// Bytes read by each unique stack of frame names separated by `;`
+    fn folded(&self) -> BTreeMap<String, usize> {
+        let skip = self.common_frames();
+        let split = self.threads.len() > 1;
+
+        // bytes read and filtered stack by thread and stack id
+        let mut stacks: HashMap<(usize, usize), (usize, Stack)> = HashMap::new();
+        self.for_each_op(|kind, thread, id, stack| {
+            let OpKind::Read(count) = kind else {
+                return;
+            };
+            match stacks.entry((thread, id)) {
+                Entry::Occupied(mut entry) => entry.get_mut().0 += count,
+                Entry::Vacant(entry) => {
+                    entry.insert((count, stack.clone()));
+                }
+            }
+        });
+
+        let mut folded = BTreeMap::new();
+        for ((thread, _), (bytes, stack)) in stacks {
+            let (skip_start, skip_end) = skip[thread];
+            let frames = &stack.frames[skip_start..(stack.frames.len() - skip_end)];
+            let mut names = vec!["root".to_string()];
+            if split {
+                names.push(self.thread_name(thread));
+            }
+            for node in self.node_path(&stack.spans, frames) {
+                // `;` separates frames, e.g. of array types `[u8; 4]`
+                names.push(self.node_name(&node).0.replace(';', ":"));
+            }
+            *folded.entry(names.join(";")).or_default() += bytes;
+        }
+        folded
+    }
+}
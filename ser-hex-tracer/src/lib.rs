@@ -1,24 +1,226 @@
+mod demangle;
+mod flamegraph;
+mod module;
+pub mod offline;
+#[cfg(all(windows, feature = "pdb"))]
+pub mod pdb;
+mod spill;
+mod tree;
+
 use std::{
-    collections::BTreeMap,
-    io::Read,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
     sync::{LazyLock, Mutex},
 };
 
-use ser_hex::{Action, ReadSpan, Trace, TreeSpan};
+use base64::{prelude::BASE64_STANDARD, write::EncoderWriter};
+use regex::Regex;
+use ser_hex::{SeekAction, Trace};
 
 #[derive(Default)]
 pub struct TracerOptions {
     /// Number of frames at the top of the stack to skip: e.g. skip frames from the tracer or
-    /// other instrumentation functions
+    /// other instrumentation functions. Prefer `marker`, which does not depend on inlining
     pub skip_frames: usize,
+    /// Frames from the top of the stack down to and including the outermost frame of the first
+    /// run matching this pattern are removed, e.g. a hook function. Defaults to the tracer's own
+    /// functions so only frames of the reading code remain. Stacks without a match are kept
+    pub marker: Option<SymbolPattern>,
+    /// Name of the traced file or stream, stored in the trace metadata and matched by `sampler`
+    pub source: Option<String>,
+    /// Only trace invocations selected by the sampler. Reads of unselected tracers are not
+    /// recorded, check [`Tracer::is_sampled`] before saving the trace
+    pub sampler: Option<&'static ser_hex::Sampler>,
+    /// Drop frames from these modules, e.g. `libstdc++` or the hook DLL, so the tree only shows
+    /// functions of the target binary. A name matches a module file name ignoring case and any
+    /// extension or version suffix, or the leading path segment of a symbol (`alloc` drops
+    /// `alloc::vec::Vec<T>::push`)
+    pub exclude_modules: Vec<String>,
+    /// If non-empty, only keep frames whose symbol matches one of these patterns. Unlike
+    /// `skip_frames` this does not depend on the amount of inlining of the build
+    pub include_symbols: Vec<SymbolPattern>,
+    /// Drop frames whose symbol matches one of these patterns
+    pub exclude_symbols: Vec<SymbolPattern>,
+    /// Name spans with the raw mangled symbols rather than demangling them. Symbol patterns are
+    /// always matched against the demangled names
+    pub raw_symbols: bool,
+    /// Keep at most this many frames of each stack below the common root frames, deeper frames
+    /// are collapsed into a single `...` span
+    pub max_depth: Option<usize>,
+    /// Remove at most this many outermost frames common to all ops of a thread, e.g. `main` and
+    /// the runtime's entry point, or all of them if None. `Some(0)` keeps the full stacks
+    pub max_trim_root: Option<usize>,
+    /// Remove at most this many innermost frames common to all ops of a thread, e.g. functions
+    /// of the reader, or all of them if None
+    pub max_trim_leaf: Option<usize>,
+    /// Collapse consecutive frames of the same function, e.g. of recursive parsers, into a
+    /// single span named with the repeat count
+    pub collapse_recursion: bool,
+    /// Expand functions inlined into each frame, resolved from debug info, into separate spans.
+    /// Makes optimized builds readable at the cost of resolving every frame
+    pub inline_frames: bool,
+    /// Append recorded ops to a temporary file in this directory rather than keeping them in
+    /// memory, for captures with too many ops to fit. Unique stacks are still kept in memory.
+    /// The file is read back by [`Tracer::trace`] and removed when the tracer is dropped
+    pub spill_dir: Option<PathBuf>,
+    /// Handling of ops recorded from more than one thread
+    pub threads: ThreadMode,
+    /// When to capture the stack of an op rather than reusing the previous stack
+    pub stack_capture: StackCapture,
+    /// Place ops below the spans entered on the thread's ser-hex trace
+    /// ([`ser_hex::current_span_path`]), e.g. of `#[instrument]`ed functions, with the frames
+    /// of the stack filling in the structure below the innermost span. Frames down to the
+    /// deepest one of a function named like a span are dropped as the span already stands for
+    /// them
+    pub span_path: bool,
+    /// Do not resolve symbols in the traced process, which is slow and can crash some targets.
+    /// Frames are named `module+0xoffset` of their instruction and the path of each module is
+    /// stored in [`TraceMetadata::modules`](ser_hex::TraceMetadata::modules) to resolve them
+    /// later with [`offline::symbolize`] or `ser-hex-tracer symbolize`. Options matching symbols
+    /// and `inline_frames` have no effect, use `skip_frames` rather than `marker`
+    pub offline_symbols: bool,
+    /// Called with each frame left after filtering to keep, rename or drop it, e.g. to strip
+    /// template arguments or give thunks readable names. Not called for `offline_symbols`
+    pub frame_transform: Option<Box<FrameTransform>>,
+}
+
+/// Callback of [`TracerOptions::frame_transform`]
+pub type FrameTransform = dyn Fn(&ResolvedFrame) -> FrameAction + Send + Sync;
+
+/// Frame passed to [`TracerOptions::frame_transform`]
+#[derive(Debug, Clone, Copy)]
+pub struct ResolvedFrame<'a> {
+    /// Address of the frame's instruction
+    pub address: u64,
+    /// Demangled name of the function
+    pub name: &'a str,
+    /// Name as found in the symbol table
+    pub raw: &'a str,
+    /// File name of the module containing the frame
+    pub module: Option<&'a str>,
+    pub location: Option<&'a ser_hex::Location>,
+}
+
+/// What [`TracerOptions::frame_transform`] does with a frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameAction {
+    Keep,
+    /// Keep the frame under another name
+    Rename(String),
+    /// Remove the frame, its callees are placed in its caller
+    Drop,
+    /// Remove the frame along with all its callees, attributing their ops to its caller
+    CollapseIntoParent,
+}
+
+/// When a [`Tracer`] captures the full stack of an op (read or seek). Ops which do not capture
+/// their own stack are attributed to the last stack captured on the same thread, trading
+/// precision for speed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StackCapture {
+    /// Capture the stack of every op
+    #[default]
+    Every,
+    /// Capture the stack of every `n`th op
+    EveryNth(usize),
+    /// Capture the stack when the return address into the caller of the tracer changes, i.e. the
+    /// innermost frame left after removing the `marker` frames. Otherwise only the frames down
+    /// to it are walked
+    OnReturnAddressChange,
+}
+
+/// Handling of ops recorded from threads other than the first to use a [`Tracer`], e.g. when
+/// worker threads read from the same archive
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadMode {
+    /// Build a separate subtree for each thread, each named after its thread. Reads of a thread
+    /// interleaved with reads of other threads are preceded by a seek to where they occurred
+    #[default]
+    Split,
+    /// Panic when an op is recorded from another thread
+    Reject,
+}
+
+/// Pattern matched against resolved symbol names, with any trailing Rust hash (`::h0123...`)
+/// removed
+#[derive(Debug, Clone)]
+pub enum SymbolPattern {
+    /// Symbol contains the string
+    Substring(String),
+    /// Symbol matches the regex anywhere, anchor it with `^` and `$` to match the whole symbol
+    Regex(Regex),
+}
+impl SymbolPattern {
+    pub fn substring(pattern: impl Into<String>) -> Self {
+        Self::Substring(pattern.into())
+    }
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(Self::Regex)
+    }
+    /// Whole symbol glob where `*` matches any sequence of characters, e.g. `core::*` or
+    /// `*::Serialize`
+    pub fn glob(pattern: &str) -> Self {
+        let pattern = pattern
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join(".*");
+        Self::Regex(Regex::new(&format!("^{pattern}$")).unwrap())
+    }
+    pub fn is_match(&self, symbol: &str) -> bool {
+        let symbol = strip_hash(symbol);
+        match self {
+            Self::Substring(pattern) => symbol.contains(pattern.as_str()),
+            Self::Regex(regex) => regex.is_match(symbol),
+        }
+    }
+}
+
+/// Remove the hash suffix of legacy mangled Rust symbols
+fn strip_hash(symbol: &str) -> &str {
+    match symbol.rsplit_once("::h") {
+        Some((rest, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            rest
+        }
+        _ => symbol,
+    }
 }
 
 #[derive(Default)]
 pub struct Tracer {
     data: Vec<u8>,
+    /// Offset in `data` of the next read
+    position: usize,
     ops: Vec<Op>,
+    /// Unique stacks referenced by [`Op::stack`]
+    stacks: Vec<Stack>,
+    /// Indexes in `stacks` by hash of the stack
+    stack_ids: HashMap<u64, Vec<usize>>,
+    /// Id and name of each thread which recorded ops, indexed by [`Op::thread`]
+    threads: Vec<(std::thread::ThreadId, Option<String>)>,
+    /// Replaces `ops` if [`TracerOptions::spill_dir`] is set
+    spill: Option<spill::SpillFile>,
     options: TracerOptions,
+    /// Set if the invocation was not selected by the sampler
+    skipped: bool,
+    /// Thread and stack id of the last op with a captured stack, see
+    /// [`TracerOptions::stack_capture`]
+    last_stack: Option<(usize, usize)>,
+    /// Ops which reused `last_stack` since it was captured
+    ops_since_capture: usize,
+    /// Innermost frame of `last_stack` outside the tracer, see
+    /// [`StackCapture::OnReturnAddressChange`]
+    last_caller: Option<u64>,
+    /// See [`Tracer::set_position_provider`]
+    position_provider: Option<Box<dyn FnMut() -> u64 + Send>>,
 }
+/// Records reads and seeks of the inner stream with a [`Tracer`]
+///
+/// Seek destinations are absolute, so the stream is assumed to be at its start when wrapped.
 pub struct TracerReader<R: Read> {
     tracer: Tracer,
     inner: R,
@@ -50,172 +252,970 @@ impl<R: Read> Read for TracerReader<R> {
             .inspect(|count| self.tracer.read(&buf[..*count]))
     }
 }
+impl<R: Read + Seek> Seek for TracerReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos).inspect(|&to| {
+            self.tracer.seek_action(SeekAction {
+                to: to as usize,
+                from: Some(pos.into()),
+            })
+        })
+    }
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        // does not move the stream so is not recorded
+        self.inner.stream_position()
+    }
+}
+
+/// Records writes and seeks of the inner stream with a [`Tracer`], e.g. to trace saving
+///
+/// Written bytes become the trace data and each write is recorded as a read of those bytes.
+pub struct TracerWriter<W: Write> {
+    tracer: Tracer,
+    inner: W,
+}
+impl<W: Write> TracerWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            tracer: Tracer::new(),
+            inner,
+        }
+    }
+    pub fn new_options(inner: W, options: TracerOptions) -> Self {
+        Self {
+            tracer: Tracer::new_options(options),
+            inner,
+        }
+    }
+    pub fn tracer(&self) -> &Tracer {
+        &self.tracer
+    }
+    pub fn trace(&self) -> Trace<&[u8]> {
+        self.tracer.trace()
+    }
+}
+impl<W: Write> Write for TracerWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner
+            .write(buf)
+            .inspect(|count| self.tracer.write(&buf[..*count]))
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+impl<W: Write + Seek> Seek for TracerWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos).inspect(|&to| {
+            self.tracer.seek_action(SeekAction {
+                to: to as usize,
+                from: Some(pos.into()),
+            })
+        })
+    }
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        // does not move the stream so is not recorded
+        self.inner.stream_position()
+    }
+}
 
 impl Tracer {
     pub fn new() -> Self {
         Self::default()
     }
     pub fn new_options(options: TracerOptions) -> Self {
+        let skipped = options
+            .sampler
+            .is_some_and(|sampler| !sampler.sample(options.source.as_deref().unwrap_or_default()));
+        let spill = options
+            .spill_dir
+            .as_deref()
+            .map(|dir| spill::SpillFile::create(dir).expect("failed to create tracer spill file"));
         Self {
             options,
+            skipped,
+            spill,
             ..Default::default()
         }
     }
+    /// Register a callback returning the position of the source stream after each recorded read
+    /// or write, e.g. the offset field of a hooked engine reader. When the read did not start
+    /// where the previous one ended a seek to its start is recorded, leaving any skipped bytes
+    /// zeroed in the data
+    pub fn set_position_provider(&mut self, provider: impl FnMut() -> u64 + Send + 'static) {
+        self.position_provider = Some(Box::new(provider));
+    }
+    /// Whether reads are being recorded, i.e. the invocation was selected by the sampler
+    pub fn is_sampled(&self) -> bool {
+        !self.skipped
+    }
     pub fn data(&self) -> &[u8] {
         &self.data
     }
     pub fn read(&mut self, bytes: &[u8]) {
-        let mut stack = vec![];
-        let mut i = 0;
-        backtrace::trace(|frame| {
-            i += 1;
-            if i > self.options.skip_frames {
-                stack.push(frame.clone());
+        self.record(bytes);
+    }
+    /// Record bytes written at the current position, stored in the trace like a read
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.record(bytes);
+    }
+    // inlined so reads and writes have the same frame depth
+    #[inline(always)]
+    fn record(&mut self, bytes: &[u8]) {
+        if self.skipped {
+            return;
+        }
+        if let Some(provider) = &mut self.position_provider {
+            let start = (provider() as usize).saturating_sub(bytes.len());
+            if start != self.position {
+                self.push_op(OpKind::Seek(SeekAction::from(start)));
+                self.position = start;
             }
-            true
-        });
-        stack.reverse();
-
-        self.ops.push(Op {
-            count: bytes.len(),
-            stack,
-        });
+        }
+        self.push_op(OpKind::Read(bytes.len()));
 
-        self.data.extend(bytes);
+        let end = self.position + bytes.len();
+        if self.data.len() < end {
+            self.data.resize(end, 0);
+        }
+        self.data[self.position..end].copy_from_slice(bytes);
+        self.position = end;
     }
-    pub fn trace(&self) -> Trace<&[u8]> {
-        #[derive(Debug)]
-        enum TreeNode {
-            Frame(Frame),
-            Read { count: usize },
-        }
-        impl TreeNode {
-            fn convert(self) -> Action<TreeSpan> {
-                match self {
-                    TreeNode::Frame(frame) => Action::Span(TreeSpan(ReadSpan {
-                        name: symbolize(frame.ip, frame.id).name.into(),
-                        actions: frame.children.into_iter().map(|c| c.convert()).collect(),
-                    })),
-                    TreeNode::Read { count } => Action::Read(count),
-                }
-            }
+    /// Record a jump of the stream to the absolute offset `to`, subsequent reads are placed there
+    // inlined so recording a seek has the same frame depth as recording a read
+    #[inline(always)]
+    pub fn seek(&mut self, to: u64) {
+        self.seek_action(SeekAction::from(to as usize));
+    }
+    fn seek_action(&mut self, seek: SeekAction) {
+        if self.skipped {
+            return;
         }
-        #[derive(Debug)]
-        struct Frame {
-            id: u64,
-            ip: u64,
-            children: Vec<TreeNode>,
-        }
-        impl Frame {
-            fn new(id: u64, ip: u64) -> Self {
-                Frame {
-                    id,
-                    ip,
-                    children: Vec::new(),
-                }
+        self.push_op(OpKind::Seek(seek));
+        self.position = seek.to;
+    }
+    // inlined so it does not add a frame counted by skip_frames
+    #[inline(always)]
+    fn push_op(&mut self, kind: OpKind) {
+        let thread = self.thread_index();
+        let spans = match self.options.span_path {
+            true => ser_hex::current_span_path(),
+            false => vec![],
+        };
+        let reuse = match (self.options.stack_capture, &self.last_stack) {
+            (_, Some((last, _))) if *last != thread => false,
+            (StackCapture::Every, _) | (_, None) => false,
+            (StackCapture::EveryNth(n), _) => self.ops_since_capture + 1 < n,
+            (StackCapture::OnReturnAddressChange, Some(_)) => {
+                let mut i = 0;
+                let mut search = CallerSearch::default();
+                backtrace::trace(|frame| {
+                    i += 1;
+                    if i <= self.options.skip_frames {
+                        return true;
+                    }
+                    let frame = StackFrame {
+                        ip: frame.ip() as u64,
+                        id: frame.symbol_address() as u64,
+                        inline: 0,
+                    };
+                    search.visit(frame.ip, self.is_marker(&frame))
+                });
+                search.caller() == self.last_caller
             }
-            fn insert(&mut self, path: &[backtrace::Frame], count: usize) {
-                if path.is_empty() {
-                    self.children.push(TreeNode::Read { count });
-                    return;
+        };
+        // spans entered or exited since the last capture invalidate it
+        let reuse = reuse
+            && self
+                .last_stack
+                .is_some_and(|(_, s)| self.stacks[s].spans == spans);
+        let stack = if let (true, Some((_, stack))) = (reuse, self.last_stack) {
+            self.ops_since_capture += 1;
+            stack
+        } else {
+            let mut frames = vec![];
+            let mut i = 0;
+            backtrace::trace(|frame| {
+                i += 1;
+                if i > self.options.skip_frames {
+                    frames.push(StackFrame {
+                        ip: frame.ip() as u64,
+                        id: frame.symbol_address() as u64,
+                        inline: 0,
+                    });
                 }
-                let rest = &path[1..];
-                match self.children.last_mut() {
-                    Some(TreeNode::Frame(frame)) if frame.id == path[0].symbol_address() as u64 => {
-                        frame.insert(rest, count);
-                    }
-                    _ => {
-                        let mut new_child =
-                            Frame::new(path[0].symbol_address() as u64, path[0].ip() as u64);
-                        new_child.insert(rest, count);
-                        self.children.push(TreeNode::Frame(new_child));
+                true
+            });
+            frames.reverse();
+            let stack = self.intern_stack(Stack { spans, frames });
+            if self.options.stack_capture != StackCapture::Every {
+                self.last_stack = Some((thread, stack));
+                self.ops_since_capture = 0;
+            }
+            if self.options.stack_capture == StackCapture::OnReturnAddressChange {
+                let mut search = CallerSearch::default();
+                for frame in self.stacks[stack].frames.iter().rev() {
+                    if !search.visit(frame.ip, self.is_marker(frame)) {
+                        break;
                     }
                 }
+                self.last_caller = search.caller();
             }
-        }
+            stack
+        };
 
-        // find common frames at bottom of stack
-        let mut skip_start = 0;
-        if let Some(first) = self.ops.first() {
-            'find_start: while let Some(start) = first.stack.get(skip_start) {
-                let start_addr = start.symbol_address();
+        match &self.spill {
+            Some(spill) => spill
+                .write(kind, thread, stack)
+                .expect("failed to write tracer spill file"),
+            None => self.ops.push(Op {
+                kind,
+                thread,
+                stack,
+            }),
+        }
+    }
+    /// Id of `stack` in `stacks`, adding it if it was not seen before
+    fn intern_stack(&mut self, stack: Stack) -> usize {
+        let mut hasher = DefaultHasher::new();
+        stack.hash(&mut hasher);
+        let ids = self.stack_ids.entry(hasher.finish()).or_default();
+        if let Some(&id) = ids.iter().find(|&&id| self.stacks[id] == stack) {
+            return id;
+        }
+        let id = self.stacks.len();
+        ids.push(id);
+        self.stacks.push(stack);
+        id
+    }
+    fn thread_index(&mut self) -> usize {
+        let current = std::thread::current();
+        if let Some(index) = self.threads.iter().position(|(id, _)| *id == current.id()) {
+            return index;
+        }
+        if let (ThreadMode::Reject, Some((first, _))) = (self.options.threads, self.threads.first())
+        {
+            panic!(
+                "tracer used from {:?} after {first:?}, use ThreadMode::Split to trace both",
+                current.id()
+            );
+        }
+        let name = current.name().map(String::from);
+        self.threads.push((current.id(), name));
+        self.threads.len() - 1
+    }
+    /// Call `f` with each recorded op, the index of its thread, the id of its stack and the stack
+    /// excluding filtered frames
+    fn for_each_op(&self, mut f: impl FnMut(OpKind, usize, usize, &Stack)) {
+        // each unique stack is only filtered once
+        let mut filtered_stacks: Vec<Option<Stack>> = vec![None; self.stacks.len()];
+        let mut filter = |kind, thread, stack: usize| {
+            let filtered = filtered_stacks[stack].get_or_insert_with(|| self.filter_stack(stack));
+            f(kind, thread, stack, filtered);
+        };
+        match &self.spill {
+            Some(spill) => spill
+                .for_each(filter)
+                .expect("failed to read tracer spill file"),
+            None => {
                 for op in &self.ops {
-                    let c = op.stack.get(skip_start);
-                    if c.is_none() || c.unwrap().symbol_address() != start_addr {
-                        break 'find_start;
-                    }
+                    filter(op.kind, op.thread, op.stack);
                 }
-                skip_start += 1;
             }
         }
-
-        // find common frames at top of stack
-        let mut skip_end = 0;
-        if let Some(first) = self.ops.first() {
-            let s = &first.stack[skip_start..];
-            'find_end: while let Some(end) =
-                s.len().checked_sub(skip_end + 1).and_then(|i| s.get(i))
-            {
-                let start_addr = end.symbol_address();
-                for op in &self.ops {
-                    let s = &op.stack[skip_start..];
-                    let c = s.len().checked_sub(skip_end + 1).and_then(|i| s.get(i));
-                    if c.is_none() || c.unwrap().symbol_address() != start_addr {
-                        break 'find_end;
-                    }
+    }
+    fn filter_stack(&self, stack: usize) -> Stack {
+        let Stack { spans, frames } = &self.stacks[stack];
+        if self.options.offline_symbols {
+            // only modules are known without symbolizing
+            let frames = frames
+                .iter()
+                .filter(|frame| {
+                    let module = module::module_name(frame.ip);
+                    !self.options.exclude_modules.iter().any(|name| {
+                        module
+                            .as_deref()
+                            .is_some_and(|module| module::matches(module, name))
+                    })
+                })
+                .copied()
+                .collect();
+            return Stack {
+                spans: spans.clone(),
+                frames,
+            };
+        }
+        let marker = marker_index(frames, |frame| self.is_marker(frame));
+        let frames = &frames[..marker.unwrap_or(frames.len())];
+        let mut filtered = vec![];
+        'frames: for frame in frames {
+            let expanded = match self.options.inline_frames {
+                false => vec![*frame],
+                true => symbols(frame.ip, frame.id)
+                    .iter()
+                    .enumerate()
+                    .map(|(inline, symbol)| StackFrame {
+                        inline,
+                        id: match inline {
+                            0 => frame.id,
+                            _ => inline_id(frame.id, &symbol.raw),
+                        },
+                        ..*frame
+                    })
+                    .collect(),
+            };
+            for frame in expanded {
+                if !self.keep_frame(&frame) {
+                    continue;
+                }
+                match self.transform_frame(&frame) {
+                    FrameAction::Keep | FrameAction::Rename(_) => filtered.push(frame),
+                    FrameAction::Drop => {}
+                    FrameAction::CollapseIntoParent => break 'frames,
                 }
-                skip_end += 1;
             }
         }
-
-        let root = self.ops.first().map(|root| {
-            let stack = &root.stack[skip_start..(root.stack.len() - skip_end)];
-            let mut root = Frame::new(stack[0].symbol_address() as u64, stack[0].ip() as u64);
-            for op in &self.ops {
-                root.insert(&op.stack[skip_start..(op.stack.len() - skip_end)], op.count);
-            }
-            TreeNode::Frame(root).convert()
-        });
+        if let Some(i) = filtered
+            .iter()
+            .rposition(|frame| spans.iter().any(|span| is_span_function(frame, span)))
+        {
+            filtered.drain(..=i);
+        }
+        Stack {
+            spans: spans.clone(),
+            frames: filtered,
+        }
+    }
+    /// Result of [`TracerOptions::frame_transform`] for a frame
+    fn transform_frame(&self, frame: &StackFrame) -> FrameAction {
+        let Some(transform) = &self.options.frame_transform else {
+            return FrameAction::Keep;
+        };
+        let symbol = symbolize(frame);
+        transform(&ResolvedFrame {
+            address: frame.ip,
+            name: &symbol.name,
+            raw: &symbol.raw,
+            module: symbol.module.as_deref(),
+            location: symbol.location.as_ref(),
+        })
+    }
+    /// Build the tree of all recorded ops. Frame names repeat throughout the tree, save it with
+    /// [`Trace::save_interned`] to store each name once
+    pub fn trace(&self) -> Trace<&[u8]> {
+        let mut builder = tree::TreeBuilder::default();
+        self.build_tree(&mut builder).unwrap();
         Trace {
+            version: ser_hex::TRACE_VERSION,
             data: &self.data,
             start_index: 0,
-            root: Action::Span(TreeSpan(ReadSpan {
-                name: "root".into(),
-                actions: root.into_iter().collect(),
-            })),
+            root: builder.finish(),
+            metadata: self.metadata(),
         }
     }
+    /// Write the trace to `path` like saving [`Tracer::trace`] with [`Trace::save_interned`], but
+    /// symbolizing and writing the tree as it is built rather than holding all of it in memory
+    pub fn trace_into(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        write!(
+            writer,
+            r#"{{"version":{},"start_index":0,"metadata":"#,
+            ser_hex::INTERNED_TRACE_VERSION
+        )?;
+        serde_json::to_writer(&mut writer, &self.metadata())?;
+        writer.write_all(br#","root":"#)?;
+        let mut tree = tree::TreeWriter::new(&mut writer);
+        self.build_tree(&mut tree)?;
+        let strings = tree.finish();
+        writer.write_all(br#","strings":"#)?;
+        serde_json::to_writer(&mut writer, &strings)?;
+        writer.write_all(br#","data":""#)?;
+        {
+            let mut encoder = EncoderWriter::new(&mut writer, &BASE64_STANDARD);
+            encoder.write_all(&self.data)?;
+            encoder.finish()?;
+        }
+        writer.write_all(br#""}"#)?;
+        writer.flush()
+    }
+    fn metadata(&self) -> ser_hex::TraceMetadata {
+        let mut modules = BTreeMap::new();
+        if self.options.offline_symbols {
+            let mut bases = HashSet::new();
+            for frame in self.stacks.iter().flat_map(|stack| &stack.frames) {
+                let Some(base) = module::module_base(frame.ip) else {
+                    continue;
+                };
+                if !bases.insert(base) {
+                    continue;
+                }
+                if let (Some(name), Some(path)) =
+                    (module::module_name(base), module::module_file(base))
+                {
+                    modules.insert(name, path.to_string_lossy().into_owned());
+                }
+            }
+        }
+        ser_hex::TraceMetadata {
+            source: self.options.source.clone(),
+            modules,
+            ..Default::default()
+        }
+        .with_defaults("ser-hex-tracer", env!("CARGO_PKG_VERSION"))
+    }
+    /// Whether `frame` matches [`TracerOptions::marker`]. Never for `offline_symbols` as frames
+    /// are not symbolized
+    fn is_marker(&self, frame: &StackFrame) -> bool {
+        if self.options.offline_symbols {
+            return false;
+        }
+        let name = symbolize(frame).name;
+        match &self.options.marker {
+            Some(pattern) => pattern.is_match(&name),
+            None => name.starts_with("ser_hex_tracer::") || name.starts_with("<ser_hex_tracer::"),
+        }
+    }
+    fn keep_frame(&self, frame: &StackFrame) -> bool {
+        let options = &self.options;
+        if options.exclude_modules.is_empty()
+            && options.include_symbols.is_empty()
+            && options.exclude_symbols.is_empty()
+        {
+            return true;
+        }
+        let symbol = symbolize(frame);
+        if !options.include_symbols.is_empty()
+            && !options
+                .include_symbols
+                .iter()
+                .any(|p| p.is_match(&symbol.name))
+        {
+            return false;
+        }
+        if options
+            .exclude_symbols
+            .iter()
+            .any(|p| p.is_match(&symbol.name))
+        {
+            return false;
+        }
+        !options.exclude_modules.iter().any(|name| {
+            symbol
+                .module
+                .as_deref()
+                .is_some_and(|module| module::matches(module, name))
+                || symbol
+                    .name
+                    .strip_prefix(name.as_str())
+                    .is_some_and(|rest| rest.starts_with("::"))
+        })
+    }
+}
+
+/// Index of the outermost frame of the first run of marker frames from the top of `stack`
+fn marker_index(
+    stack: &[StackFrame],
+    mut is_marker: impl FnMut(&StackFrame) -> bool,
+) -> Option<usize> {
+    let mut found = None;
+    for (i, frame) in stack.iter().enumerate().rev() {
+        if is_marker(frame) {
+            found = Some(i);
+        } else if found.is_some() {
+            break;
+        }
+    }
+    found
+}
+
+/// Finds the innermost frame kept by [`marker_index`] trimming, the caller of the tracer, among
+/// frames visited innermost first
+#[derive(Debug, Default)]
+struct CallerSearch {
+    /// Innermost frame, the caller if the stack has no marker frames
+    first: Option<u64>,
+    in_marker: bool,
+    /// First frame after the first run of marker frames
+    caller: Option<u64>,
+}
+impl CallerSearch {
+    /// Visit the next outer frame, returns whether the caller is still unknown
+    fn visit(&mut self, ip: u64, is_marker: bool) -> bool {
+        self.first.get_or_insert(ip);
+        if is_marker {
+            self.in_marker = true;
+        } else if self.in_marker {
+            self.caller = Some(ip);
+        }
+        self.caller.is_none()
+    }
+    fn caller(&self) -> Option<u64> {
+        match self.in_marker {
+            true => self.caller,
+            false => self.first,
+        }
+    }
+}
+
+/// Frames of an op along with the spans it was recorded in, see [`TracerOptions::span_path`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Stack {
+    spans: Vec<Cow<'static, str>>,
+    /// Outermost frame first
+    frames: Vec<StackFrame>,
+}
+
+/// Whether `frame` is of the function a span was presumably entered by, i.e. a function with the
+/// same name as the span, ignoring any arguments appended to the span name
+fn is_span_function(frame: &StackFrame, span: &str) -> bool {
+    let span = span.split_once('(').map_or(span, |(name, _)| name);
+    let name = symbolize(frame).name;
+    let name = strip_hash(&name);
+    name == span
+        || name
+            .strip_suffix(span)
+            .is_some_and(|rest| rest.ends_with("::"))
 }
 
 struct Op {
-    count: usize,
-    stack: Vec<backtrace::Frame>,
+    kind: OpKind,
+    /// Index of the recording thread in [`Tracer::threads`]
+    thread: usize,
+    /// Index in [`Tracer::stacks`]
+    stack: usize,
+}
+/// Address of a frame's instruction and of the function containing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct StackFrame {
+    ip: u64,
+    id: u64,
+    /// Index into the [`symbols`] of `ip`, non-zero for functions inlined into the frame
+    inline: usize,
+}
+
+/// Identifies an inlined function within the function `id` it was inlined into
+fn inline_id(id: u64, raw: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (id, raw).hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OpKind {
+    Read(usize),
+    Seek(SeekAction),
 }
 
-fn symbolize(ip: u64, id: u64) -> Symbol {
+/// Symbol of the function containing `ip` followed by the symbols of any functions inlined into
+/// it, outermost first
+fn symbols(ip: u64, id: u64) -> Vec<Symbol> {
     SYMBOLS
         .lock()
         .unwrap()
         .entry(ip)
         .or_insert_with(|| {
-            let mut name = None;
-            let mut addr = None;
+            let module = module::module_name(ip);
+            let mut symbols = vec![];
+            #[cfg(all(windows, feature = "pdb"))]
+            if let Some(raw) = pdb::resolve(ip) {
+                // PDB publics have no line info or inline frames but are preferred over the
+                // nearest export dbghelp falls back to without the PDB
+                return vec![Symbol {
+                    name: demangle::demangle(&raw),
+                    raw,
+                    module,
+                    location: None,
+                }];
+            }
             backtrace::resolve(ip as *mut _, |symbol| {
-                name = symbol.name().map(|n| n.to_string());
-                addr = symbol.addr()
+                let raw = symbol
+                    .name()
+                    .map(|n| String::from_utf8_lossy(n.as_bytes()).into_owned())
+                    .unwrap_or_else(|| unresolved_name(ip, id, module.as_deref()));
+                symbols.push(Symbol {
+                    //ptr: symbol.addr().map(|a| a as u64).unwrap_or(0),
+                    name: demangle::demangle(&raw),
+                    raw,
+                    module: module.clone(),
+                    location: symbol.filename().map(|file| ser_hex::Location {
+                        file: file.to_string_lossy().into_owned(),
+                        line: symbol.lineno(),
+                    }),
+                });
             });
-            Symbol {
-                //ptr: addr.map(|a| a as u64).unwrap_or(0),
-                //name: format!("0x{ip:X?} {addr:X?} {}", name.unwrap_or_default()),
-                name: name.unwrap_or_else(|| format!("0x{id:X?}")),
+            if symbols.is_empty() {
+                let raw = unresolved_name(ip, id, module.as_deref());
+                symbols.push(Symbol {
+                    name: raw.clone(),
+                    raw,
+                    module,
+                    location: None,
+                });
             }
+            // resolved innermost first
+            symbols.reverse();
+            symbols
         })
         .clone()
 }
+/// `module+0xoffset` of the instruction `ip`, see [`TracerOptions::offline_symbols`]
+fn offline_name(ip: u64) -> String {
+    match (module::module_name(ip), module::module_base(ip)) {
+        (Some(module), Some(base)) if ip >= base => format!("{module}+0x{:X}", ip - base),
+        _ => format!("0x{ip:X}"),
+    }
+}
+/// `module+0xoffset` of the function `id`, stable across runs unlike its address
+fn unresolved_name(ip: u64, id: u64, module: Option<&str>) -> String {
+    match (module, module::module_base(ip)) {
+        (Some(module), Some(base)) if id >= base => format!("{module}+0x{:X}", id - base),
+        _ => format!("0x{id:X?}"),
+    }
+}
+fn symbolize(frame: &StackFrame) -> Symbol {
+    let mut symbols = symbols(frame.ip, frame.id);
+    let index = frame.inline.min(symbols.len() - 1);
+    symbols.swap_remove(index)
+}
 #[derive(Debug, Clone)]
 pub struct Symbol {
     //ptr: u64,
+    /// Demangled name
     name: String,
+    /// Name as found in the symbol table
+    raw: String,
+    /// File name of the module containing the frame
+    module: Option<String>,
+    /// Source location of the frame's instruction pointer, if debug info is available
+    location: Option<ser_hex::Location>,
 }
-pub static SYMBOLS: LazyLock<Mutex<BTreeMap<u64, Symbol>>> =
+pub static SYMBOLS: LazyLock<Mutex<BTreeMap<u64, Vec<Symbol>>>> =
     LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frames(ips: &[u64]) -> Vec<StackFrame> {
+        ips.iter()
+            .map(|&ip| StackFrame {
+                ip,
+                id: ip,
+                inline: 0,
+            })
+            .collect()
+    }
+
+    /// Frame of a function named `name`, resolved from the symbol cache rather than debug info.
+    /// Addresses in the upper half are never code of the process so do not clash with real frames
+    fn named_frame(name: &str) -> StackFrame {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        let ip = hasher.finish() | 1 << 63;
+        SYMBOLS.lock().unwrap().entry(ip).or_insert_with(|| {
+            vec![Symbol {
+                name: name.into(),
+                raw: name.into(),
+                module: None,
+                location: None,
+            }]
+        });
+        StackFrame {
+            ip,
+            id: ip,
+            inline: 0,
+        }
+    }
+
+    fn stack(spans: &[&'static str], names: &[&str]) -> Stack {
+        Stack {
+            spans: spans.iter().map(|&span| span.into()).collect(),
+            frames: names.iter().map(|name| named_frame(name)).collect(),
+        }
+    }
+
+    /// Tracer with a one byte read at each stack
+    fn synthetic_tracer(options: TracerOptions, stacks: Vec<Stack>) -> Tracer {
+        let mut tracer = Tracer::new_options(options);
+        tracer.threads.push((std::thread::current().id(), None));
+        for stack in stacks {
+            let stack = tracer.intern_stack(stack);
+            tracer.ops.push(Op {
+                kind: OpKind::Read(1),
+                thread: 0,
+                stack,
+            });
+            tracer.data.push(0);
+        }
+        tracer
+    }
+
+    /// Function names of the stack of each op after filtering
+    fn filtered(tracer: &Tracer) -> Vec<Vec<String>> {
+        let mut stacks = vec![];
+        tracer.for_each_op(|_, _, _, stack| {
+            stacks.push(stack.frames.iter().map(|f| symbolize(f).name).collect());
+        });
+        stacks
+    }
+
+    /// Tree of span names with reads as their size, e.g. `root(main(1 1))`
+    fn tree(action: &ser_hex::Action<ser_hex::TreeSpan>) -> String {
+        match action {
+            ser_hex::Action::Span(span) => {
+                let actions: Vec<_> = span.0.actions.iter().map(tree).collect();
+                format!("{}({})", span.0.name, actions.join(" "))
+            }
+            ser_hex::Action::Read(size) => size.to_string(),
+            _ => "?".into(),
+        }
+    }
+
+    #[inline(never)]
+    fn read_a(tracer: &mut Tracer) {
+        tracer.read(&[1]);
+    }
+    #[inline(never)]
+    fn read_b(tracer: &mut Tracer) {
+        tracer.read(&[2]);
+    }
+
+    #[test]
+    fn test_marker_index() {
+        // outermost first: the reader (1, 2), the tracer (10, 11) and backtrace (20, 21)
+        let stack = frames(&[1, 2, 10, 11, 20, 21]);
+        let is_marker = |frame: &StackFrame| (10..20).contains(&frame.ip);
+        assert_eq!(marker_index(&stack, is_marker), Some(2));
+        // only the innermost run is trimmed
+        let stack = frames(&[1, 10, 2, 11, 20]);
+        assert_eq!(marker_index(&stack, is_marker), Some(3));
+        assert_eq!(marker_index(&frames(&[1, 2, 20]), is_marker), None);
+        assert_eq!(marker_index(&frames(&[10, 11]), is_marker), Some(0));
+    }
+
+    #[test]
+    fn test_caller_search() {
+        let is_marker = |ip: u64| (10..20).contains(&ip);
+        let caller = |ips: &[u64]| {
+            let mut search = CallerSearch::default();
+            for &ip in ips {
+                if !search.visit(ip, is_marker(ip)) {
+                    break;
+                }
+            }
+            search.caller()
+        };
+        // innermost first, agreeing with marker_index
+        assert_eq!(caller(&[21, 20, 11, 10, 2, 1]), Some(2));
+        assert_eq!(caller(&[20, 2, 1]), Some(20));
+        // all frames are trimmed
+        assert_eq!(caller(&[11, 10]), None);
+    }
+
+    #[test]
+    fn test_every_nth() {
+        let mut tracer = Tracer::new_options(TracerOptions {
+            stack_capture: StackCapture::EveryNth(2),
+            ..Default::default()
+        });
+        read_a(&mut tracer);
+        read_b(&mut tracer);
+        read_b(&mut tracer);
+        let stacks: Vec<_> = tracer.ops.iter().map(|op| op.stack).collect();
+        // the second op reuses the stack of the first, the third captures its own
+        assert_eq!(stacks[0], stacks[1]);
+        assert_ne!(stacks[1], stacks[2]);
+        assert_eq!(tracer.data(), [1, 2, 2]);
+    }
+
+    #[test]
+    fn test_return_address_change() {
+        let mut tracer = Tracer::new_options(TracerOptions {
+            stack_capture: StackCapture::OnReturnAddressChange,
+            // functions of this module are ser_hex_tracer:: too, only trim the tracer itself
+            marker: Some(SymbolPattern::glob("ser_hex_tracer::Tracer::*")),
+            ..Default::default()
+        });
+        read_a(&mut tracer);
+        read_a(&mut tracer);
+        read_b(&mut tracer);
+        let stacks: Vec<_> = tracer.ops.iter().map(|op| op.stack).collect();
+        // the calls of read_a are on different lines so a recaptured stack would differ
+        assert_eq!(stacks[0], stacks[1]);
+        assert_ne!(stacks[1], stacks[2]);
+    }
+
+    #[test]
+    fn test_no_frames_left() {
+        let mut tracer = Tracer::new_options(TracerOptions {
+            include_symbols: vec![SymbolPattern::glob("no_such_symbol")],
+            ..Default::default()
+        });
+        read_a(&mut tracer);
+        read_b(&mut tracer);
+        let trace = tracer.trace();
+        let ser_hex::Action::Span(root) = &trace.root else {
+            panic!("root is not a span");
+        };
+        // the reads are placed directly below the root
+        let reads: Vec<_> = root
+            .0
+            .actions
+            .iter()
+            .map(|action| matches!(action, ser_hex::Action::Read(1)))
+            .collect();
+        assert_eq!(reads, [true, true]);
+    }
+
+    #[test]
+    fn test_symbol_filters() {
+        let stacks = || {
+            vec![
+                stack(
+                    &[],
+                    &["main", "app::load", "app::header", "std::io::read_exact"],
+                ),
+                stack(&[], &["main", "app::load", "log::debug"]),
+            ]
+        };
+        let tracer = synthetic_tracer(
+            TracerOptions {
+                include_symbols: vec![SymbolPattern::glob("app::*")],
+                exclude_symbols: vec![SymbolPattern::substring("header")],
+                ..Default::default()
+            },
+            stacks(),
+        );
+        assert_eq!(filtered(&tracer), [["app::load"], ["app::load"]]);
+
+        let tracer = synthetic_tracer(
+            TracerOptions {
+                exclude_modules: vec!["std".into(), "log".into()],
+                ..Default::default()
+            },
+            stacks(),
+        );
+        assert_eq!(
+            filtered(&tracer),
+            [
+                vec!["main", "app::load", "app::header"],
+                vec!["main", "app::load"]
+            ]
+        );
+        assert_eq!(
+            tree(&tracer.trace().root),
+            "root(app::load(app::load(app::header(1) 1)))"
+        );
+
+        // no frames left at all, the reads are placed directly below the root
+        let tracer = synthetic_tracer(
+            TracerOptions {
+                include_symbols: vec![SymbolPattern::glob("no_such_symbol")],
+                ..Default::default()
+            },
+            stacks(),
+        );
+        assert!(filtered(&tracer).iter().all(Vec::is_empty));
+        assert_eq!(tree(&tracer.trace().root), "root(1 1)");
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let stacks = || {
+            vec![
+                stack(&[], &["main", "a", "b", "c"]),
+                stack(&[], &["main", "a", "d"]),
+                stack(&[], &["main", "e"]),
+            ]
+        };
+        let tracer = synthetic_tracer(
+            TracerOptions {
+                max_depth: Some(2),
+                max_trim_root: Some(0),
+                ..Default::default()
+            },
+            stacks(),
+        );
+        assert_eq!(
+            tree(&tracer.trace().root),
+            "root(main(main(a(...(1 1)) e(1))))"
+        );
+
+        // every frame is collapsed
+        let tracer = synthetic_tracer(
+            TracerOptions {
+                max_depth: Some(0),
+                ..Default::default()
+            },
+            stacks(),
+        );
+        assert_eq!(tree(&tracer.trace().root), "root(...(...(1 1 1)))");
+    }
+
+    #[test]
+    fn test_collapse_recursion() {
+        let stacks = || {
+            vec![
+                stack(&[], &["main", "parse", "parse", "parse", "leaf"]),
+                stack(&[], &["main", "parse", "parse", "leaf"]),
+            ]
+        };
+        let options = |collapse_recursion| TracerOptions {
+            collapse_recursion,
+            max_trim_root: Some(0),
+            ..Default::default()
+        };
+        let tracer = synthetic_tracer(options(false), stacks());
+        assert_eq!(
+            tree(&tracer.trace().root),
+            "root(main(main(parse(parse(parse(leaf(1)) leaf(1))))))"
+        );
+        // different recursion depths are different spans
+        let tracer = synthetic_tracer(options(true), stacks());
+        assert_eq!(
+            tree(&tracer.trace().root),
+            "root(main(main(parse (x3)(leaf(1)) parse (x2)(leaf(1)))))"
+        );
+    }
+
+    #[test]
+    fn test_span_path() {
+        let tracer = synthetic_tracer(
+            TracerOptions {
+                span_path: true,
+                ..Default::default()
+            },
+            vec![
+                stack(
+                    &["header"],
+                    &["main", "app::load", "app::header", "app::read_u32"],
+                ),
+                // arguments appended to the span name are ignored when matching functions
+                stack(
+                    &["header", "magic(0)"],
+                    &[
+                        "main",
+                        "app::load",
+                        "app::header",
+                        "app::magic",
+                        "app::read_u8",
+                    ],
+                ),
+                stack(&[], &["main", "app::load"]),
+            ],
+        );
+        // frames down to the function of the innermost span are dropped
+        assert_eq!(
+            filtered(&tracer),
+            [
+                vec!["app::read_u32"],
+                vec!["app::read_u8"],
+                vec!["main", "app::load"]
+            ]
+        );
+        assert_eq!(
+            tree(&tracer.trace().root),
+            "root(header(app::read_u32(1) magic(0)(app::read_u8(1))) main(app::load(1)))"
+        );
+    }
+}
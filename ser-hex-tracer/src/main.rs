@@ -0,0 +1,33 @@
+use std::{error::Error, path::PathBuf};
+
+use ser_hex_tracer::offline::{symbolize, SymbolizeOptions};
+
+const USAGE: &str =
+    "usage: ser-hex-tracer symbolize <TRACE PATH> [-o <OUTPUT PATH>] [--search <DIR>]... [--raw]";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() != Some("symbolize") {
+        return Err(USAGE.into());
+    }
+    let mut trace_path = None;
+    let mut output = None;
+    let mut options = SymbolizeOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(PathBuf::from(args.next().ok_or(USAGE)?)),
+            "--search" => options.search_dirs.push(args.next().ok_or(USAGE)?.into()),
+            "--raw" => options.raw_symbols = true,
+            _ if trace_path.is_none() => trace_path = Some(PathBuf::from(arg)),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let trace_path = trace_path.ok_or(USAGE)?;
+
+    let mut trace = ser_hex::Trace::<Vec<u8>>::load(&trace_path)?;
+    for (module, error) in symbolize(&mut trace, &options) {
+        eprintln!("warning: frames of {module} were not symbolized: {error}");
+    }
+    trace.save_interned(output.unwrap_or(trace_path))?;
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! File name and base address of the loaded module (executable or shared library) containing
+//! an address
+
+#[cfg(unix)]
+#[repr(C)]
+struct DlInfo {
+    dli_fname: *const std::ffi::c_char,
+    dli_fbase: *mut std::ffi::c_void,
+    dli_sname: *const std::ffi::c_char,
+    dli_saddr: *mut std::ffi::c_void,
+}
+
+#[cfg(unix)]
+fn dladdr(addr: u64) -> Option<DlInfo> {
+    use std::ffi::{c_int, c_void};
+
+    extern "C" {
+        fn dladdr(addr: *const c_void, info: *mut DlInfo) -> c_int;
+    }
+
+    let mut info = std::mem::MaybeUninit::<DlInfo>::zeroed();
+    // SAFETY: dladdr only inspects the address and fills in info on success
+    if unsafe { dladdr(addr as *const c_void, info.as_mut_ptr()) } == 0 {
+        return None;
+    }
+    Some(unsafe { info.assume_init() })
+}
+
+#[cfg(unix)]
+pub fn module_name(addr: u64) -> Option<String> {
+    let info = dladdr(addr)?;
+    if info.dli_fname.is_null() {
+        return None;
+    }
+    let path = unsafe { std::ffi::CStr::from_ptr(info.dli_fname) };
+    Some(file_name(&path.to_string_lossy()))
+}
+
+/// Base address of the module containing `addr`
+#[cfg(unix)]
+pub fn module_base(addr: u64) -> Option<u64> {
+    let info = dladdr(addr)?;
+    (!info.dli_fbase.is_null()).then_some(info.dli_fbase as u64)
+}
+
+/// Path of the module containing `addr`
+#[cfg(unix)]
+pub fn module_file(addr: u64) -> Option<std::path::PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let info = dladdr(addr)?;
+    if info.dli_fname.is_null() {
+        return None;
+    }
+    let path = unsafe { std::ffi::CStr::from_ptr(info.dli_fname) };
+    Some(std::ffi::OsStr::from_bytes(path.to_bytes()).into())
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetModuleHandleExW(flags: u32, name: *const u16, module: *mut *mut std::ffi::c_void) -> i32;
+    fn GetModuleFileNameW(module: *mut std::ffi::c_void, name: *mut u16, size: u32) -> u32;
+}
+
+#[cfg(windows)]
+pub fn module_name(addr: u64) -> Option<String> {
+    let path = module_path(module_base(addr)?)?;
+    Some(file_name(&path.to_string_lossy()))
+}
+
+/// Base address of the module containing `addr`
+#[cfg(windows)]
+pub fn module_base(addr: u64) -> Option<u64> {
+    const GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT: u32 = 0x2;
+    const GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS: u32 = 0x4;
+
+    let mut module = std::ptr::null_mut();
+    // SAFETY: with FROM_ADDRESS the name is interpreted as an address inside the module
+    let found = unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+            addr as *const u16,
+            &mut module,
+        )
+    };
+    (found != 0).then_some(module as u64)
+}
+
+/// Path of the module loaded at `base`
+#[cfg(windows)]
+pub fn module_path(base: u64) -> Option<std::path::PathBuf> {
+    use std::os::windows::ffi::OsStringExt;
+
+    let mut buf = [0; 1024];
+    let len = unsafe { GetModuleFileNameW(base as *mut _, buf.as_mut_ptr(), buf.len() as u32) };
+    if len == 0 {
+        return None;
+    }
+    Some(std::ffi::OsString::from_wide(&buf[..len as usize]).into())
+}
+
+/// Path of the module containing `addr`
+#[cfg(windows)]
+pub fn module_file(addr: u64) -> Option<std::path::PathBuf> {
+    module_path(module_base(addr)?)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn module_name(_addr: u64) -> Option<String> {
+    None
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn module_base(_addr: u64) -> Option<u64> {
+    None
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn module_file(_addr: u64) -> Option<std::path::PathBuf> {
+    None
+}
+
+#[cfg(any(unix, windows))]
+fn file_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_name()
+        .map_or_else(|| path.into(), |name| name.to_string_lossy().into_owned())
+}
+
+/// Whether the module file name `module` is `name`, ignoring case and any extension or version
+/// suffix, e.g. `libstdc++` matches `libstdc++.so.6` and `hook` matches `Hook.dll`
+pub fn matches(module: &str, name: &str) -> bool {
+    let (module, name) = (module.to_lowercase(), name.to_lowercase());
+    module
+        .strip_prefix(&name)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
@@ -0,0 +1,176 @@
+//! Symbolization of traces captured with [`TracerOptions::offline_symbols`], e.g. on the
+//! developer machine rather than inside the hooked process
+//!
+//! ```ignore
+//! let mut trace = ser_hex::Trace::<Vec<u8>>::load("capture.json")?;
+//! let options = ser_hex_tracer::offline::SymbolizeOptions {
+//!     search_dirs: vec!["game/bin".into()],
+//!     ..Default::default()
+//! };
+//! for (module, error) in ser_hex_tracer::offline::symbolize(&mut trace, &options) {
+//!     eprintln!("{module} was not symbolized: {error}");
+//! }
+//! trace.save_interned("capture.json")?;
+//! ```
+//!
+//! Modules are read with their DWARF debug info or else their symbol table, PDBs are not used.
+//!
+//! [`TracerOptions::offline_symbols`]: crate::TracerOptions::offline_symbols
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use ser_hex::{Action, Location, Trace, TreeSpan};
+
+use crate::demangle;
+
+#[derive(Debug, Default, Clone)]
+pub struct SymbolizeOptions {
+    /// Directories searched for modules by file name, tried after the path a module was loaded
+    /// from when captured
+    pub search_dirs: Vec<PathBuf>,
+    /// Name spans with the raw mangled symbols rather than demangling them
+    pub raw_symbols: bool,
+}
+
+/// Rename spans named `module+0xoffset` after the function containing the instruction, using
+/// the modules listed in [`TraceMetadata::modules`](ser_hex::TraceMetadata::modules)
+///
+/// Returns the modules which could not be loaded along with the reason, their spans are left
+/// unchanged as are spans of instructions without a symbol
+pub fn symbolize<D: AsRef<[u8]>>(
+    trace: &mut Trace<D>,
+    options: &SymbolizeOptions,
+) -> Vec<(String, String)> {
+    let mut symbolizer = Symbolizer {
+        options,
+        paths: &trace.metadata.modules,
+        modules: HashMap::new(),
+    };
+    if let Action::Span(span) = &mut trace.root {
+        symbolizer.span(span);
+    }
+    let mut failed: Vec<_> = symbolizer
+        .modules
+        .into_iter()
+        .filter_map(|(name, module)| module.err().map(|error| (name, error)))
+        .collect();
+    failed.sort();
+    failed
+}
+
+struct Module {
+    loader: addr2line::Loader,
+    /// Resolved name and location by offset
+    symbols: HashMap<u64, Option<(String, Option<Location>)>>,
+}
+
+struct Symbolizer<'a> {
+    options: &'a SymbolizeOptions,
+    paths: &'a BTreeMap<String, String>,
+    /// Loaded modules by name, or the reason they could not be loaded
+    modules: HashMap<String, Result<Module, String>>,
+}
+impl Symbolizer<'_> {
+    fn span(&mut self, span: &mut TreeSpan) {
+        if let Some((name, location)) = self.resolve(&span.0.name) {
+            span.0.name = name.into();
+            span.0.location = location;
+        }
+        for action in &mut span.0.actions {
+            if let Action::Span(span) = action {
+                self.span(span);
+            }
+        }
+    }
+    /// Name and location for a span name of the form `module+0xoffset`, keeping any
+    /// ` (xN)` suffix of collapsed recursion
+    fn resolve(&mut self, name: &str) -> Option<(String, Option<Location>)> {
+        let (address, suffix) = match name.rfind(" (x") {
+            Some(i) if name.ends_with(')') => name.split_at(i),
+            _ => (name, ""),
+        };
+        let (module, offset) = address.rsplit_once("+0x")?;
+        let offset = u64::from_str_radix(offset, 16).ok()?;
+        let path = self.paths.get(module)?;
+
+        let module = self
+            .modules
+            .entry(module.to_string())
+            .or_insert_with(|| load(Path::new(path), module, &self.options.search_dirs))
+            .as_mut()
+            .ok()?;
+        let (name, location) = module
+            .symbols
+            .entry(offset)
+            .or_insert_with(|| lookup(&module.loader, offset, self.options.raw_symbols))
+            .clone()?;
+        Some((format!("{name}{suffix}"), location))
+    }
+}
+
+/// Load a module from where it was captured or else from one of the search directories
+fn load(path: &Path, name: &str, search_dirs: &[PathBuf]) -> Result<Module, String> {
+    let candidates = std::iter::once(path.to_path_buf()).chain(
+        search_dirs
+            .iter()
+            .map(|dir| dir.join(path.file_name().unwrap_or(name.as_ref()))),
+    );
+    let mut error = format!("{} not found", path.display());
+    for candidate in candidates {
+        if !candidate.is_file() {
+            continue;
+        }
+        match addr2line::Loader::new(&candidate) {
+            Ok(loader) => {
+                return Ok(Module {
+                    loader,
+                    symbols: HashMap::new(),
+                })
+            }
+            Err(e) => error = format!("failed to load {}: {e}", candidate.display()),
+        }
+    }
+    Err(error)
+}
+
+/// Name of the outermost function containing the instruction at `offset` from the module base
+/// and its location
+fn lookup(
+    loader: &addr2line::Loader,
+    offset: u64,
+    raw: bool,
+) -> Option<(String, Option<Location>)> {
+    // return addresses point past the call, look up the call instruction itself
+    let probe = loader.relative_address_base() + offset.saturating_sub(1);
+    let mut outermost = None;
+    if let Ok(mut frames) = loader.find_frames(probe) {
+        while let Ok(Some(frame)) = frames.next() {
+            let Some(name) = frame
+                .function
+                .and_then(|f| f.raw_name().ok().map(String::from))
+            else {
+                continue;
+            };
+            let location = frame.location.and_then(|l| {
+                Some(Location {
+                    file: l.file?.to_string(),
+                    line: l.line,
+                })
+            });
+            // frames are innermost first
+            outermost = Some((name, location));
+        }
+    }
+    let (raw_name, location) = match outermost {
+        Some(found) => found,
+        None => (loader.find_symbol(probe)?.to_string(), None),
+    };
+    let name = match raw {
+        true => raw_name,
+        false => demangle::demangle(&raw_name),
+    };
+    Some((name, location))
+}
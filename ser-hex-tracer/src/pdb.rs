@@ -0,0 +1,225 @@
+//! Symbolization of Windows modules from PDBs found on a symbol path, for binaries whose PDBs
+//! `backtrace` cannot locate
+//!
+//! The symbol path uses the `_NT_SYMBOL_PATH` syntax: `;` separated directories or symbol
+//! stores, and `srv*<cache>*<url>` symbol servers whose PDBs are downloaded into `<cache>`.
+//!
+//! ```ignore
+//! ser_hex_tracer::pdb::set_symbol_path(r"C:\Game\Binaries;srv*C:\symbols*https://symbols.example.com");
+//! ```
+
+use std::{
+    collections::HashMap,
+    ffi::{c_char, CStr},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use ::pdb::FallibleIterator;
+
+static SYMBOL_PATH: Mutex<Option<String>> = Mutex::new(None);
+/// Symbols of each module by base address, `None` if its PDB was not found
+static MODULES: LazyLock<Mutex<HashMap<u64, Option<Arc<ModuleSymbols>>>>> =
+    LazyLock::new(Default::default);
+
+/// Set the symbol path searched for PDBs, overriding the `_NT_SYMBOL_PATH` environment variable.
+/// Only affects modules which have not been symbolized yet
+pub fn set_symbol_path(path: impl Into<String>) {
+    *SYMBOL_PATH.lock().unwrap() = Some(path.into());
+}
+
+fn symbol_path() -> String {
+    SYMBOL_PATH
+        .lock()
+        .unwrap()
+        .clone()
+        .or_else(|| std::env::var("_NT_SYMBOL_PATH").ok())
+        .unwrap_or_default()
+}
+
+/// Raw name of the public function containing `ip`, if the PDB of its module was found
+pub(crate) fn resolve(ip: u64) -> Option<String> {
+    let base = crate::module::module_base(ip)?;
+    let module = MODULES
+        .lock()
+        .unwrap()
+        .entry(base)
+        .or_insert_with(|| {
+            // SAFETY: base is the address of a loaded module which starts with its PE headers
+            let codeview = unsafe { codeview(base) }?;
+            let module = crate::module::module_path(base)?;
+            ModuleSymbols::load(&codeview, &module).map(Arc::new)
+        })
+        .clone()?;
+    module.find(ip - base).map(String::from)
+}
+
+/// PDB reference from the CodeView debug directory entry of a PE
+struct CodeView {
+    guid: [u8; 16],
+    age: u32,
+    /// Path of the PDB when the module was linked
+    path: PathBuf,
+}
+impl CodeView {
+    fn file_name(&self) -> Option<&Path> {
+        // linked on Windows so the path may use either separator
+        let path = self.path.to_str()?;
+        Some(Path::new(path.rsplit(['\\', '/']).next()?))
+    }
+    /// Directory of a PDB in a symbol store, e.g. `Game.pdb/1F0E3C9A5B7D4E2F8A6B0C1D2E3F4A5B1`
+    fn store_key(&self) -> String {
+        let g = &self.guid;
+        format!(
+            "{:08X}{:04X}{:04X}{}{:X}",
+            u32::from_le_bytes([g[0], g[1], g[2], g[3]]),
+            u16::from_le_bytes([g[4], g[5]]),
+            u16::from_le_bytes([g[6], g[7]]),
+            g[8..]
+                .iter()
+                .map(|b| format!("{b:02X}"))
+                .collect::<String>(),
+            self.age
+        )
+    }
+    fn matches(&self, info: &::pdb::PDBInformation) -> bool {
+        let g = &self.guid;
+        let (d1, d2, d3, d4) = info.guid.as_fields();
+        d1 == u32::from_le_bytes([g[0], g[1], g[2], g[3]])
+            && d2 == u16::from_le_bytes([g[4], g[5]])
+            && d3 == u16::from_le_bytes([g[6], g[7]])
+            && d4[..] == g[8..]
+    }
+}
+
+/// Read the CodeView entry of the PE headers mapped at `base`
+unsafe fn codeview(base: u64) -> Option<CodeView> {
+    unsafe fn read<T: Copy>(address: u64) -> T {
+        std::ptr::read_unaligned(address as *const T)
+    }
+    const IMAGE_DIRECTORY_ENTRY_DEBUG: u64 = 6;
+    const IMAGE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+
+    let nt = base + read::<u32>(base + 0x3c) as u64;
+    if read::<u32>(nt) != u32::from_le_bytes(*b"PE\0\0") {
+        return None;
+    }
+    let optional = nt + 24;
+    let directories = optional
+        + match read::<u16>(optional) {
+            0x10b => 96,
+            0x20b => 112,
+            _ => return None,
+        };
+    let debug = directories + IMAGE_DIRECTORY_ENTRY_DEBUG * 8;
+    let (rva, size) = (read::<u32>(debug) as u64, read::<u32>(debug + 4) as u64);
+    for entry in (0..size / 28).map(|i| base + rva + i * 28) {
+        if read::<u32>(entry + 12) != IMAGE_DEBUG_TYPE_CODEVIEW {
+            continue;
+        }
+        let data = base + read::<u32>(entry + 20) as u64;
+        if read::<u32>(data) != u32::from_le_bytes(*b"RSDS") {
+            continue;
+        }
+        let path = CStr::from_ptr((data + 24) as *const c_char);
+        return Some(CodeView {
+            guid: read(data + 4),
+            age: read(data + 20),
+            path: PathBuf::from(path.to_string_lossy().into_owned()),
+        });
+    }
+    None
+}
+
+/// Public functions of a module sorted by RVA
+struct ModuleSymbols(Vec<(u32, String)>);
+impl ModuleSymbols {
+    fn load(codeview: &CodeView, module: &Path) -> Option<Self> {
+        let file = find_pdb(codeview, module)?;
+        let mut pdb = ::pdb::PDB::open(fs::File::open(file).ok()?).ok()?;
+        if !codeview.matches(&pdb.pdb_information().ok()?) {
+            return None;
+        }
+        let address_map = pdb.address_map().ok()?;
+        let mut functions = vec![];
+        let symbol_table = pdb.global_symbols().ok()?;
+        let mut symbols = symbol_table.iter();
+        while let Ok(Some(symbol)) = symbols.next() {
+            if let Ok(::pdb::SymbolData::Public(data)) = symbol.parse() {
+                if let (true, Some(rva)) = (data.function, data.offset.to_rva(&address_map)) {
+                    functions.push((rva.0, data.name.to_string().into_owned()));
+                }
+            }
+        }
+        functions.sort_by_key(|(rva, _)| *rva);
+        Some(Self(functions))
+    }
+    fn find(&self, rva: u64) -> Option<&str> {
+        let index = self.0.partition_point(|(start, _)| *start as u64 <= rva);
+        index.checked_sub(1).map(|i| self.0[i].1.as_str())
+    }
+}
+
+/// Find the PDB next to the module, at its link time path or on the symbol path
+fn find_pdb(codeview: &CodeView, module: &Path) -> Option<PathBuf> {
+    let name = codeview.file_name()?;
+    let key = codeview.store_key();
+    let mut candidates = vec![codeview.path.clone()];
+    if let Some(dir) = module.parent() {
+        candidates.push(dir.join(name));
+    }
+    for element in symbol_path().split(';').filter(|e| !e.is_empty()) {
+        let parts: Vec<&str> = element.split('*').collect();
+        match parts[..] {
+            [srv, ref rest @ ..] if srv.eq_ignore_ascii_case("srv") => {
+                // srv*<cache>*<store>*... checks each store in turn, caching downloads
+                let (cache, stores) = match rest {
+                    [] => continue,
+                    [_] | ["", ..] => (default_cache(), &rest[rest.len() - 1..]),
+                    [cache, stores @ ..] => (PathBuf::from(cache), stores),
+                };
+                let cache = cache.join(name).join(&key).join(name);
+                if cache.exists() {
+                    return Some(cache);
+                }
+                for store in stores {
+                    if download(store, name, &key, &cache).is_ok() {
+                        return Some(cache);
+                    }
+                }
+            }
+            [cache, ..] if cache.eq_ignore_ascii_case("cache") => {}
+            _ => {
+                let dir = Path::new(element);
+                candidates.push(dir.join(name));
+                candidates.push(dir.join(name).join(&key).join(name));
+            }
+        }
+    }
+    candidates.into_iter().find(|c| c.is_file())
+}
+
+/// Downstream store used by `srv*<url>` without a cache directory
+fn default_cache() -> PathBuf {
+    std::env::temp_dir().join("SymbolCache")
+}
+
+/// Fetch a PDB from an HTTP symbol server or a symbol store directory into `dest`
+fn download(store: &str, name: &Path, key: &str, dest: &Path) -> io::Result<()> {
+    let name_str = name.to_string_lossy();
+    let mut tmp = dest.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::create_dir_all(dest.parent().unwrap())?;
+    if store.starts_with("http://") || store.starts_with("https://") {
+        let url = format!(
+            "{}/{name_str}/{key}/{name_str}",
+            store.trim_end_matches('/')
+        );
+        let response = ureq::get(&url).call().map_err(io::Error::other)?;
+        io::copy(&mut response.into_reader(), &mut fs::File::create(&tmp)?)?;
+    } else {
+        fs::copy(Path::new(store).join(name).join(key).join(name), &tmp)?;
+    }
+    fs::rename(tmp, dest)
+}
@@ -0,0 +1,139 @@
+//! Temporary file storage of recorded ops for captures too large to keep in memory
+
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use ser_hex::{SeekAction, SeekOrigin};
+
+use crate::OpKind;
+
+/// Ops appended to a file in the order they were recorded, removed when dropped
+pub struct SpillFile {
+    // declared before the path so the file is closed before it is removed
+    writer: Mutex<BufWriter<fs::File>>,
+    path: TempPath,
+}
+struct TempPath(PathBuf);
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+impl SpillFile {
+    pub fn create(dir: &Path) -> io::Result<Self> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = dir.join(format!(
+            "ser-hex-tracer-{}-{}.ops",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = fs::File::create_new(&path)?;
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+            path: TempPath(path),
+        })
+    }
+    pub fn write(&self, kind: OpKind, thread: usize, stack: usize) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        match kind {
+            OpKind::Read(count) => {
+                writer.write_all(&[0])?;
+                writer.write_all(&(count as u64).to_le_bytes())?;
+            }
+            OpKind::Seek(seek) => {
+                let (tag, offset) = match seek.from {
+                    None => (1, 0),
+                    Some(SeekOrigin::Start(offset)) => (2, offset as i64),
+                    Some(SeekOrigin::End(offset)) => (3, offset),
+                    Some(SeekOrigin::Current(offset)) => (4, offset),
+                };
+                writer.write_all(&[tag])?;
+                writer.write_all(&(seek.to as u64).to_le_bytes())?;
+                writer.write_all(&offset.to_le_bytes())?;
+            }
+        }
+        writer.write_all(&(thread as u32).to_le_bytes())?;
+        writer.write_all(&(stack as u32).to_le_bytes())?;
+        Ok(())
+    }
+    /// Read back all ops written so far, with the index of their thread and stack
+    pub fn for_each(&self, mut f: impl FnMut(OpKind, usize, usize)) -> io::Result<()> {
+        self.writer.lock().unwrap().flush()?;
+        let mut reader = BufReader::new(fs::File::open(&self.path.0)?);
+        loop {
+            let mut tag = [0];
+            match reader.read_exact(&mut tag) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                r => r?,
+            }
+            let kind = match tag[0] {
+                0 => OpKind::Read(read_u64(&mut reader)? as usize),
+                tag => {
+                    let to = read_u64(&mut reader)? as usize;
+                    let offset = read_u64(&mut reader)? as i64;
+                    let from = match tag {
+                        1 => None,
+                        2 => Some(SeekOrigin::Start(offset as u64)),
+                        3 => Some(SeekOrigin::End(offset)),
+                        _ => Some(SeekOrigin::Current(offset)),
+                    };
+                    OpKind::Seek(SeekAction { to, from })
+                }
+            };
+            let thread = read_u32(&mut reader)? as usize;
+            let stack = read_u32(&mut reader)? as usize;
+            f(kind, thread, stack);
+        }
+        Ok(())
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() -> io::Result<()> {
+        let spill = SpillFile::create(&std::env::temp_dir())?;
+        let seek = |to, from| OpKind::Seek(SeekAction { to, from });
+        let ops = [
+            (OpKind::Read(3), 0, 0),
+            (seek(7, None), 1, 2),
+            (seek(1, Some(SeekOrigin::Start(1))), 0, 1),
+            (seek(2, Some(SeekOrigin::End(-4))), 0, 3),
+            (seek(5, Some(SeekOrigin::Current(3))), 1, 4),
+            (OpKind::Read(usize::MAX), 0, 0),
+        ];
+        for (kind, thread, stack) in ops {
+            spill.write(kind, thread, stack)?;
+        }
+        let mut read = vec![];
+        spill.for_each(|kind, thread, stack| read.push((kind, thread, stack)))?;
+        assert_eq!(read, ops);
+
+        let path = spill.path.0.clone();
+        assert!(path.exists());
+        drop(spill);
+        assert!(!path.exists());
+        Ok(())
+    }
+}
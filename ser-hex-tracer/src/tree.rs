@@ -0,0 +1,368 @@
+//! Construction of the span tree from recorded ops, shared by [`Tracer::trace`] which builds it
+//! in memory and [`Tracer::trace_into`] which streams it into a file
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use ser_hex::{Action, Location, ReadSpan, TreeSpan};
+
+use crate::{offline_name, symbolize, FrameAction, OpKind, StackFrame, Tracer};
+
+/// Receives the spans and leaves of the tree depth first
+pub(crate) trait TreeSink {
+    fn open(&mut self, name: Cow<'static, str>, location: Option<Location>) -> io::Result<()>;
+    fn close(&mut self) -> io::Result<()>;
+    fn leaf(&mut self, kind: OpKind) -> io::Result<()>;
+}
+
+/// Node on the path from the root frame of a thread to an op
+#[derive(Debug, Clone)]
+pub(crate) enum Node {
+    /// Span entered on the thread's ser-hex trace, see [`TracerOptions::span_path`]
+    ///
+    /// [`TracerOptions::span_path`]: crate::TracerOptions::span_path
+    Span(Cow<'static, str>),
+    /// Frame along with the number of consecutive frames of the same function collapsed into it
+    Frame(StackFrame, usize),
+    /// Frames beyond `max_depth`
+    Collapsed,
+}
+impl Node {
+    /// Whether an op at `other` continues in the span opened for `self`
+    fn matches(&self, other: &Node) -> bool {
+        match (self, other) {
+            (Node::Frame(a, a_repeat), Node::Frame(b, b_repeat)) => {
+                a.id == b.id && a_repeat == b_repeat
+            }
+            (Node::Span(a), Node::Span(b)) => a == b,
+            (Node::Collapsed, Node::Collapsed) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Tracer {
+    /// Emit the tree of all ops into `sink` below a span named `root`
+    pub(crate) fn build_tree(&self, sink: &mut impl TreeSink) -> io::Result<()> {
+        let skip = self.common_frames();
+        let split = self.threads.len() > 1;
+        sink.open("root".into(), None)?;
+        for (thread, &skip) in skip.iter().enumerate() {
+            if split {
+                sink.open(self.thread_name(thread).into(), None)?;
+            }
+            self.build_thread(sink, thread, skip, split)?;
+            if split {
+                sink.close()?;
+            }
+        }
+        sink.close()
+    }
+    /// Name of the span of a thread's ops when ops were recorded from more than one thread
+    pub(crate) fn thread_name(&self, thread: usize) -> String {
+        match &self.threads[thread].1 {
+            Some(name) => format!("thread {name}"),
+            None => format!("thread {thread}"),
+        }
+    }
+    /// Number of frames at the bottom and top of the stack common to all ops of each thread to
+    /// remove, see [`TracerOptions::max_trim_root`]
+    ///
+    /// [`TracerOptions::max_trim_root`]: crate::TracerOptions::max_trim_root
+    pub(crate) fn common_frames(&self) -> Vec<(usize, usize)> {
+        struct Common {
+            start: Vec<u64>,
+            end: Vec<u64>,
+            min_len: usize,
+        }
+
+        let mut common: Vec<Option<Common>> = self.threads.iter().map(|_| None).collect();
+        self.for_each_op(|_, thread, _, stack| {
+            let stack = &stack.frames;
+            let ids = || stack.iter().map(|f| f.id);
+            match &mut common[thread] {
+                Some(c) => {
+                    let n = c
+                        .start
+                        .iter()
+                        .zip(ids())
+                        .take_while(|(a, b)| **a == *b)
+                        .count();
+                    c.start.truncate(n);
+                    let n = c
+                        .end
+                        .iter()
+                        .zip(ids().rev())
+                        .take_while(|(a, b)| **a == *b)
+                        .count();
+                    c.end.truncate(n);
+                    c.min_len = c.min_len.min(stack.len());
+                }
+                slot => {
+                    *slot = Some(Common {
+                        start: ids().collect(),
+                        end: ids().rev().collect(),
+                        min_len: stack.len(),
+                    })
+                }
+            }
+        });
+        common
+            .iter()
+            .map(|common| {
+                common.as_ref().map_or((0, 0), |c| {
+                    // at least one frame is kept, otherwise the stack of a single op would be
+                    // removed entirely
+                    let start = c.start.len().min(c.min_len.saturating_sub(1));
+                    // common frames at the top may not overlap those at the bottom
+                    let end = c.end.len().min(c.min_len.saturating_sub(start + 1));
+                    (
+                        start.min(self.options.max_trim_root.unwrap_or(usize::MAX)),
+                        end.min(self.options.max_trim_leaf.unwrap_or(usize::MAX)),
+                    )
+                })
+            })
+            .collect()
+    }
+    /// Emit the ops of a single thread below a span of its outermost frame
+    fn build_thread(
+        &self,
+        sink: &mut impl TreeSink,
+        thread: usize,
+        (skip_start, skip_end): (usize, usize),
+        split: bool,
+    ) -> io::Result<()> {
+        let mut result = Ok(());
+        // spans opened below the root frame, None until the first op is placed
+        let mut open: Option<Vec<Node>> = None;
+        // whether the ops are placed in a span of the outermost frame of the first op
+        let mut root_frame = false;
+        // position the thread's next op is placed at if no other thread reads in between
+        let mut expected = None;
+        let mut position = 0;
+        self.for_each_op(|kind, op_thread, _, stack| {
+            let at = position;
+            position = match kind {
+                OpKind::Read(count) => position + count,
+                OpKind::Seek(seek) => seek.to,
+            };
+            if op_thread != thread || result.is_err() {
+                return;
+            }
+            let frames = &stack.frames[skip_start..(stack.frames.len() - skip_end)];
+            result = (|| {
+                let path = self.node_path(&stack.spans, frames);
+                if open.is_none() {
+                    // ops outside of any span are placed directly below the thread, as are all
+                    // ops if the first has no frames left after filtering
+                    if let (false, Some(node)) = (self.options.span_path, path.first()) {
+                        let (name, location) = self.node_name(node);
+                        sink.open(name, location)?;
+                        root_frame = true;
+                    }
+                    open = Some(vec![]);
+                }
+                let open = open.as_mut().unwrap();
+                if split && expected != Some(at) {
+                    self.emit(sink, open, &path, OpKind::Seek(at.into()))?;
+                }
+                self.emit(sink, open, &path, kind)
+            })();
+            expected = Some(position);
+        });
+        result?;
+        if let Some(open) = open {
+            // spans below the root frame and the root frame itself
+            for _ in 0..open.len() + usize::from(root_frame) {
+                sink.close()?;
+            }
+        }
+        Ok(())
+    }
+    /// Nodes from the outermost span to the innermost frame of an op, with recursion collapsed
+    /// and deep frames cut off as configured
+    pub(crate) fn node_path(
+        &self,
+        spans: &[Cow<'static, str>],
+        frames: &[StackFrame],
+    ) -> Vec<Node> {
+        let mut path: Vec<Node> = Vec::with_capacity(spans.len() + frames.len());
+        path.extend(spans.iter().cloned().map(Node::Span));
+        for frame in frames {
+            match path.last_mut() {
+                Some(Node::Frame(last, repeat))
+                    if self.options.collapse_recursion && last.id == frame.id =>
+                {
+                    *repeat += 1
+                }
+                _ => path.push(Node::Frame(*frame, 1)),
+            }
+        }
+        if let Some(max) = self.options.max_depth {
+            if path.len() > max {
+                path.truncate(max);
+                path.push(Node::Collapsed);
+            }
+        }
+        path
+    }
+    /// Emit a leaf at `path`, closing and opening spans from the currently `open` path
+    fn emit(
+        &self,
+        sink: &mut impl TreeSink,
+        open: &mut Vec<Node>,
+        path: &[Node],
+        kind: OpKind,
+    ) -> io::Result<()> {
+        let common = open
+            .iter()
+            .zip(path)
+            .take_while(|(a, b)| a.matches(b))
+            .count();
+        for _ in common..open.len() {
+            sink.close()?;
+        }
+        open.truncate(common);
+        for node in &path[common..] {
+            let (name, location) = self.node_name(node);
+            sink.open(name, location)?;
+            open.push(node.clone());
+        }
+        sink.leaf(kind)
+    }
+    pub(crate) fn node_name(&self, node: &Node) -> (Cow<'static, str>, Option<Location>) {
+        match node {
+            Node::Frame(frame, repeat) => {
+                let (name, location) = if self.options.offline_symbols {
+                    (offline_name(frame.ip), None)
+                } else {
+                    let symbol = symbolize(frame);
+                    let name = match self.transform_frame(frame) {
+                        FrameAction::Rename(name) => name,
+                        _ if self.options.raw_symbols => symbol.raw,
+                        _ => symbol.name,
+                    };
+                    (name, symbol.location)
+                };
+                let name = match repeat {
+                    1 => name,
+                    n => format!("{name} (x{n})"),
+                };
+                (name.into(), location)
+            }
+            Node::Span(name) => (name.clone(), None),
+            Node::Collapsed => ("...".into(), None),
+        }
+    }
+}
+
+fn leaf_action(kind: OpKind) -> Action<TreeSpan> {
+    match kind {
+        OpKind::Read(count) => Action::Read(count),
+        OpKind::Seek(seek) => Action::Seek(seek),
+    }
+}
+
+/// Builds the tree in memory
+#[derive(Default)]
+pub(crate) struct TreeBuilder {
+    /// Spans which have been opened but not closed yet
+    open: Vec<ReadSpan>,
+    root: Option<ReadSpan>,
+}
+impl TreeBuilder {
+    pub(crate) fn finish(self) -> Action<TreeSpan> {
+        Action::Span(TreeSpan(self.root.expect("root span was not closed")))
+    }
+}
+impl TreeSink for TreeBuilder {
+    fn open(&mut self, name: Cow<'static, str>, location: Option<Location>) -> io::Result<()> {
+        self.open.push(ReadSpan {
+            name,
+            value: None,
+            actions: vec![],
+            timings: vec![],
+            location,
+        });
+        Ok(())
+    }
+    fn close(&mut self) -> io::Result<()> {
+        let span = self.open.pop().expect("no open span");
+        match self.open.last_mut() {
+            Some(parent) => parent.actions.push(Action::Span(TreeSpan(span))),
+            None => self.root = Some(span),
+        }
+        Ok(())
+    }
+    fn leaf(&mut self, kind: OpKind) -> io::Result<()> {
+        let span = self.open.last_mut().expect("no open span");
+        span.actions.push(leaf_action(kind));
+        Ok(())
+    }
+}
+
+/// Writes the tree as JSON of an `Action<TreeSpan>` as it is built, with span names referring to
+/// the string table returned by [`TreeWriter::finish`]
+pub(crate) struct TreeWriter<W: Write> {
+    writer: W,
+    /// Whether each open span has any actions yet, to separate them
+    has_actions: Vec<bool>,
+    strings: Vec<Cow<'static, str>>,
+    indexes: HashMap<Cow<'static, str>, usize>,
+}
+impl<W: Write> TreeWriter<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self {
+            writer,
+            has_actions: vec![],
+            strings: vec![],
+            indexes: HashMap::new(),
+        }
+    }
+    /// Names referenced by the written spans
+    pub(crate) fn finish(self) -> Vec<Cow<'static, str>> {
+        self.strings
+    }
+    fn separate(&mut self) -> io::Result<()> {
+        if let Some(has_actions) = self.has_actions.last_mut() {
+            if *has_actions {
+                self.writer.write_all(b",")?;
+            }
+            *has_actions = true;
+        }
+        Ok(())
+    }
+}
+impl<W: Write> TreeSink for TreeWriter<W> {
+    fn open(&mut self, name: Cow<'static, str>, location: Option<Location>) -> io::Result<()> {
+        self.separate()?;
+        let index = match self.indexes.get(&name) {
+            Some(&index) => index,
+            None => {
+                self.strings.push(name.clone());
+                self.indexes.insert(name, self.strings.len() - 1);
+                self.strings.len() - 1
+            }
+        };
+        write!(self.writer, r#"{{"Span":{{"name":{index}"#)?;
+        if let Some(location) = location {
+            self.writer.write_all(br#","location":"#)?;
+            serde_json::to_writer(&mut self.writer, &location)?;
+        }
+        self.writer.write_all(br#","actions":["#)?;
+        self.has_actions.push(false);
+        Ok(())
+    }
+    fn close(&mut self) -> io::Result<()> {
+        self.has_actions.pop();
+        self.writer.write_all(b"]}}")
+    }
+    fn leaf(&mut self, kind: OpKind) -> io::Result<()> {
+        self.separate()?;
+        serde_json::to_writer(&mut self.writer, &leaf_action(kind))?;
+        Ok(())
+    }
+}
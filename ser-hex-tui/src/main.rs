@@ -24,6 +24,7 @@ struct App<'trace> {
 
 struct TraceTree<'trace> {
     trace: &'trace ser_hex::Trace,
+    problems: Vec<ser_hex::Problem>,
     nodes: BTreeMap<Path, Rc<TraceNode<'trace>>>,
     root: Rc<TraceNode<'trace>>,
 }
@@ -34,6 +35,7 @@ struct TraceNode<'trace> {
     start: usize,
     end: usize,
     action: &'trace ser_hex::Action<ser_hex::TreeSpan>,
+    timing: Option<ser_hex::Timing>,
     children: Vec<Rc<TraceNode<'trace>>>,
 }
 
@@ -86,18 +88,20 @@ impl<'trace> TraceTree<'trace> {
         fn convert<'trace>(
             offset: &mut usize,
             action: &'trace ser_hex::Action<ser_hex::TreeSpan>,
+            timing: Option<ser_hex::Timing>,
             nodes: &mut BTreeMap<Path, Rc<TraceNode<'trace>>>,
             path: &mut Path,
         ) -> Rc<TraceNode<'trace>> {
             let start = *offset;
             match action {
-                ser_hex::Action::Read(r) => {
-                    *offset += r;
+                ser_hex::Action::Read(_) | ser_hex::Action::ReadData(_) => {
+                    *offset += action.read_size().unwrap();
                     let node: Rc<_> = TraceNode {
                         identifier: path.clone(),
                         start,
                         end: *offset,
                         action,
+                        timing,
                         children: vec![],
                     }
                     .into();
@@ -108,12 +112,26 @@ impl<'trace> TraceTree<'trace> {
                     let node: Rc<_> = TraceNode {
                         identifier: path.clone(),
                         start: *offset,
-                        end: *s,
+                        end: s.to,
                         action,
+                        timing,
+                        children: vec![],
+                    }
+                    .into();
+                    *offset = s.to;
+                    nodes.insert(path.clone(), node.clone());
+                    node
+                }
+                ser_hex::Action::Error(_) | ser_hex::Action::SubTrace(_) => {
+                    let node: Rc<_> = TraceNode {
+                        identifier: path.clone(),
+                        start,
+                        end: start,
+                        action,
+                        timing,
                         children: vec![],
                     }
                     .into();
-                    *offset = *s;
                     nodes.insert(path.clone(), node.clone());
                     node
                 }
@@ -123,7 +141,8 @@ impl<'trace> TraceTree<'trace> {
                     let start = *offset;
                     for (i, child) in s.0.actions.iter().enumerate() {
                         path.push(s.0.actions.len(), i);
-                        children.push(convert(offset, child, nodes, path));
+                        let timing = s.0.timings.get(i).copied();
+                        children.push(convert(offset, child, timing, nodes, path));
                         path.pop(s.0.actions.len());
                     }
 
@@ -132,6 +151,7 @@ impl<'trace> TraceTree<'trace> {
                         start,
                         end: *offset,
                         action,
+                        timing,
                         children,
                     }
                     .into();
@@ -144,9 +164,14 @@ impl<'trace> TraceTree<'trace> {
         let mut nodes = Default::default();
 
         let mut cur = trace.start_index;
-        let root = convert(&mut cur, &trace.root, &mut nodes, &mut Path::new());
+        let root = convert(&mut cur, &trace.root, None, &mut nodes, &mut Path::new());
 
-        Self { trace, nodes, root }
+        Self {
+            trace,
+            problems: trace.validate(),
+            nodes,
+            root,
+        }
     }
 }
 
@@ -191,13 +216,27 @@ impl TreeData for TraceTree<'_> {
         let node = self.nodes.get(identifier).unwrap();
         let mut line = vec![];
         match node.action {
-            ser_hex::Action::Read(_) => {
+            ser_hex::Action::Read(_) | ser_hex::Action::ReadData(_) => {
                 line.push(Span::styled(
                     format!("Read ({}) ", node.end - node.start),
                     Style::new().fg(Color::LightGreen),
                 ));
 
-                let data = &self.trace.data[node.start..node.end];
+                // data may be truncated if the trace was captured with a size cap
+                let len = self.trace.data.len();
+                let mirror = &self.trace.data[node.start.min(len)..node.end.min(len)];
+                let data = match node.action {
+                    ser_hex::Action::ReadData(data) => {
+                        if data.as_slice() != mirror {
+                            line.push(Span::styled(
+                                "(differs from mirror) ",
+                                Style::new().fg(Color::Black).bg(Color::Yellow),
+                            ));
+                        }
+                        data.as_slice()
+                    }
+                    _ => mirror,
+                };
                 let d: String = data.iter().map(|b| format!("{b:02X}")).join(" ");
 
                 line.push(Span::styled(
@@ -235,10 +274,38 @@ impl TreeData for TraceTree<'_> {
                 }
                 //write!(&mut preview, "{:?} ", String::from_utf8_lossy(data)).unwrap();
             }
-            ser_hex::Action::Seek(_) => {
+            ser_hex::Action::Seek(seek) => {
+                let (label, color) = match seek.from {
+                    Some(ser_hex::SeekOrigin::Current(delta)) => {
+                        (format!("Skip {delta:+} "), Color::Yellow)
+                    }
+                    Some(ser_hex::SeekOrigin::End(delta)) => {
+                        (format!("Seek End{delta:+} "), Color::Red)
+                    }
+                    Some(ser_hex::SeekOrigin::Start(_)) | None => ("Seek ".into(), Color::Red),
+                };
                 line.push(Span::styled(
-                    format!("Seek ({} -> {}) ", node.start, node.end),
-                    Style::new().fg(Color::Red),
+                    format!("{label}({} -> {}) ", node.start, node.end),
+                    Style::new().fg(color),
+                ));
+            }
+            ser_hex::Action::Error(e) => {
+                line.push(Span::styled(
+                    format!("Error ({} reading {}) ", e.kind, e.size),
+                    Style::new().fg(Color::Black).bg(Color::Red),
+                ));
+                line.push(Span::styled(e.message.clone(), Style::new().fg(Color::Red)));
+            }
+            ser_hex::Action::SubTrace(sub) => {
+                let label = match sub {
+                    ser_hex::SubTrace::File(path) => path.display().to_string(),
+                    ser_hex::SubTrace::Inline(trace) => {
+                        format!("inline, {} bytes", trace.data.len())
+                    }
+                };
+                line.push(Span::styled(
+                    format!("SubTrace ({label}) "),
+                    Style::new().fg(Color::LightBlue),
                 ));
             }
             ser_hex::Action::Span(s) => {
@@ -250,9 +317,26 @@ impl TreeData for TraceTree<'_> {
                     format!("{}", s.0.name),
                     Style::new().italic().fg(Color::LightCyan),
                 ));
+                if let Some(value) = &s.0.value {
+                    line.push(Span::styled(
+                        format!(" = {} ", value.value),
+                        Style::new().fg(Color::Magenta),
+                    ));
+                    line.push(Span::styled(
+                        format!("({})", value.type_name),
+                        Style::new().fg(Color::DarkGray),
+                    ));
+                }
             }
         }
 
+        if let Some(timing) = node.timing {
+            line.push(Span::styled(
+                format!(" [{:?}]", timing.duration()),
+                Style::new().fg(Color::DarkGray),
+            ));
+        }
+
         Widget::render(Line::from(line), area, buffer);
     }
 }
@@ -273,7 +357,29 @@ impl<'trace> App<'trace> {
             .block(
                 Block::bordered()
                     .title("Tree Widget")
-                    .title_bottom(format!("{:?}", self.tree_state)),
+                    .title(
+                        Line::from(
+                            self.tree_trait
+                                .trace
+                                .metadata
+                                .entries()
+                                .into_iter()
+                                .map(|(k, v)| format!("{k}: {v}"))
+                                .join(" | "),
+                        )
+                        .right_aligned(),
+                    )
+                    .title_bottom(format!("{:?}", self.tree_state))
+                    .title_bottom(
+                        Line::from(match self.tree_trait.problems.as_slice() {
+                            [] => String::new(),
+                            [first, rest @ ..] => {
+                                format!(" {} problem(s): {first} ", rest.len() + 1)
+                            }
+                        })
+                        .right_aligned()
+                        .fg(Color::Red),
+                    ),
             )
             .experimental_scrollbar(Some(Scrollbar::new(ScrollbarOrientation::VerticalRight)))
             .highlight_style(
@@ -342,6 +448,7 @@ impl StatefulWidget for HexView<'_> {
 
     fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer, state: &mut Self::State) {
         let data = &self.tree_trait.trace.data;
+        let redacted = &self.tree_trait.trace.metadata.redacted;
         let columns = state.columns;
 
         let height = area.height as usize;
@@ -349,7 +456,7 @@ impl StatefulWidget for HexView<'_> {
             let selected = &self.tree_trait.nodes[selected];
             let rows = data.len().div_ceil(columns);
             (
-                (rows * selected.start / data.len()).saturating_sub(height / 2),
+                (rows * selected.start / data.len().max(1)).saturating_sub(height / 2),
                 Some(selected.start..selected.end),
             )
         } else {
@@ -396,6 +503,7 @@ impl StatefulWidget for HexView<'_> {
                 }
                 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
                 enum ByteType {
+                    Redacted,
                     Null,
                     Other,
                     Ascii,
@@ -403,6 +511,7 @@ impl StatefulWidget for HexView<'_> {
                 impl ByteType {
                     fn color(self) -> Color {
                         match self {
+                            ByteType::Redacted => Color::Blue,
                             ByteType::Null => Color::DarkGray,
                             ByteType::Other => Color::White,
                             ByteType::Ascii => Color::Red,
@@ -411,7 +520,10 @@ impl StatefulWidget for HexView<'_> {
                 }
 
                 let style = |(j, b): (usize, &u8)| {
-                    let (byte_type, symbol) = if b.is_ascii_graphic() {
+                    let address = (i * columns) + j;
+                    let (byte_type, symbol) = if redacted.iter().any(|r| r.contains(&address)) {
+                        (ByteType::Redacted, '░')
+                    } else if b.is_ascii_graphic() {
                         (ByteType::Ascii, *b as char)
                     } else if *b == 0 {
                         (ByteType::Null, '.')
@@ -421,9 +533,7 @@ impl StatefulWidget for HexView<'_> {
                     ByteStyle {
                         byte_type,
                         symbol,
-                        highlight: range
-                            .as_ref()
-                            .is_some_and(|r| r.contains(&((i * columns) + j))),
+                        highlight: range.as_ref().is_some_and(|r| r.contains(&address)),
                     }
                 };
 
@@ -471,12 +581,8 @@ impl StatefulWidget for HexView<'_> {
 }
 
 fn main() -> std::io::Result<()> {
-    let mut deserializer = serde_json::Deserializer::from_reader(std::io::BufReader::new(
-        std::fs::File::open(std::env::args().nth(1).expect("expected path"))?,
-    ));
-    deserializer.disable_recursion_limit();
-    use serde::de::Deserialize;
-    let data = ser_hex::Trace::deserialize(&mut deserializer)?;
+    let data = ser_hex::Trace::load(std::env::args().nth(1).expect("expected path"))
+        .map_err(std::io::Error::other)?;
 
     // Terminal initialization
     crossterm::terminal::enable_raw_mode()?;
@@ -0,0 +1,126 @@
+//! Labelled byte ranges saved to a sidecar file next to the trace
+
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use eframe::egui;
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub label: String,
+    pub range: Range<usize>,
+    pub color: [u8; 3],
+}
+impl Bookmark {
+    pub fn color(&self) -> egui::Color32 {
+        let [r, g, b] = self.color;
+        egui::Color32::from_rgb(r, g, b)
+    }
+}
+
+#[derive(Debug)]
+pub struct Bookmarks {
+    /// Sidecar file, `None` for inline sub-traces which are not persisted
+    path: Option<PathBuf>,
+    list: Vec<Bookmark>,
+    new_label: String,
+    new_color: [u8; 3],
+}
+impl Default for Bookmarks {
+    fn default() -> Self {
+        Self {
+            path: None,
+            list: vec![],
+            new_label: String::new(),
+            new_color: [255, 128, 0],
+        }
+    }
+}
+impl Bookmarks {
+    /// Load the bookmarks of the trace at `trace_path`, if any have been saved
+    pub fn load(trace_path: &Path) -> Result<Self> {
+        let mut name = trace_path.file_name().unwrap_or_default().to_owned();
+        name.push(".bookmarks.json");
+        let path = trace_path.with_file_name(name);
+        let list = match path.exists() {
+            true => serde_json::from_slice(&fs::read(&path)?)?,
+            false => vec![],
+        };
+        Ok(Self {
+            path: Some(path),
+            list,
+            ..Default::default()
+        })
+    }
+    fn save(&self) -> Result<()> {
+        if let Some(path) = &self.path {
+            fs::write(path, serde_json::to_string_pretty(&self.list)?)?;
+        }
+        Ok(())
+    }
+    pub fn list(&self) -> &[Bookmark] {
+        &self.list
+    }
+    /// Bookmark `range` with the label and color entered in the bookmark list
+    pub fn add(&mut self, range: Range<usize>) {
+        self.list.push(Bookmark {
+            label: std::mem::take(&mut self.new_label),
+            range,
+            color: self.new_color,
+        });
+        if let Err(err) = self.save() {
+            eprintln!("failed to save bookmarks {err:?}");
+        }
+    }
+    /// Bookmark list and controls to bookmark `selection`, returns the range of the bookmark to
+    /// go to if one was clicked
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        selection: Option<Range<usize>>,
+    ) -> Option<Range<usize>> {
+        let mut goto = None;
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.color_edit_button_srgb(&mut self.new_color);
+            ui.add(
+                egui::TextEdit::singleline(&mut self.new_label)
+                    .hint_text("label")
+                    .desired_width(120.),
+            );
+            let add = ui.add_enabled(selection.is_some(), egui::Button::new("bookmark selection"));
+            if let (true, Some(range)) = (add.clicked(), selection) {
+                self.add(range);
+            }
+        });
+        let mut remove = None;
+        for (i, bookmark) in self.list.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                changed |= ui.color_edit_button_srgb(&mut bookmark.color).changed();
+                let range = &bookmark.range;
+                let text = format!("{} ({}..{})", bookmark.label, range.start, range.end);
+                if ui.button(text).clicked() {
+                    goto = Some(range.clone());
+                }
+                if ui.small_button("🗑").on_hover_text("remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.list.remove(i);
+            changed = true;
+        }
+        if changed {
+            if let Err(err) = self.save() {
+                eprintln!("failed to save bookmarks {err:?}");
+            }
+        }
+        goto
+    }
+}
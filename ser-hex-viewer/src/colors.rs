@@ -0,0 +1,345 @@
+//! Colors of the spans in the hex view, configured in a settings window and remembered in the
+//! user config directory
+
+use std::{
+    collections::BTreeMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+};
+
+use anyhow::{Context as _, Result};
+use eframe::{egui, epaint::Hsva};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+
+use crate::FlatSpan;
+
+/// What a span's color is picked by
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Scheme {
+    /// Name of the span
+    #[default]
+    Name,
+    /// Nesting depth of the span
+    Depth,
+    /// Name of the outermost span containing the span
+    Ancestor,
+}
+impl Scheme {
+    const ALL: [Scheme; 3] = [Scheme::Name, Scheme::Depth, Scheme::Ancestor];
+
+    fn label(self) -> &'static str {
+        match self {
+            Scheme::Name => "by name",
+            Scheme::Depth => "by depth",
+            Scheme::Ancestor => "by top-level span",
+        }
+    }
+}
+
+/// What the bytes of the hex view and minimap are colored by
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ByteColors {
+    /// Span the byte was read by
+    #[default]
+    Span,
+    /// Zero, printable ASCII, other control characters or high bytes
+    Class,
+    /// Shannon entropy of the block of bytes around the byte, high for compressed or encrypted
+    /// data
+    Entropy,
+}
+impl ByteColors {
+    const ALL: [ByteColors; 3] = [ByteColors::Span, ByteColors::Class, ByteColors::Entropy];
+
+    fn label(self) -> &'static str {
+        match self {
+            ByteColors::Span => "span",
+            ByteColors::Class => "byte class",
+            ByteColors::Entropy => "entropy",
+        }
+    }
+}
+
+/// Bytes the entropy is computed over, aligned to the size
+const ENTROPY_BLOCK: usize = 256;
+
+/// Entropy of the block containing `address` from 0 to 1
+fn entropy(data: &[u8], address: usize) -> f32 {
+    let start = address / ENTROPY_BLOCK * ENTROPY_BLOCK;
+    let block = &data[start..data.len().min(start + ENTROPY_BLOCK)];
+    let mut counts = [0u32; 256];
+    for &b in block {
+        counts[b as usize] += 1;
+    }
+    let len = block.len() as f32;
+    let bits: f32 = counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f32 / len;
+            -p * p.log2()
+        })
+        .sum();
+    bits / 8.
+}
+
+fn class_color(b: u8) -> egui::Color32 {
+    match b {
+        0 => egui::Color32::from_gray(90),
+        b if b.is_ascii_graphic() || b == b' ' => egui::Color32::from_rgb(80, 160, 255),
+        b if b.is_ascii() => egui::Color32::from_rgb(100, 200, 100),
+        0xff => egui::Color32::WHITE,
+        _ => egui::Color32::from_rgb(255, 150, 50),
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Settings {
+    #[serde(default)]
+    bytes: ByteColors,
+    scheme: Scheme,
+    /// Colors picked from, an evenly spread hue when empty
+    palette: Vec<[u8; 3]>,
+    /// Colors of specific span names, overriding the scheme
+    names: BTreeMap<String, [u8; 3]>,
+}
+
+#[derive(Debug, Default)]
+pub struct Colors {
+    settings: Settings,
+    /// Settings window is shown
+    pub open: bool,
+    new_name: String,
+    new_color: [u8; 3],
+}
+impl Colors {
+    fn file() -> Option<PathBuf> {
+        Some(
+            dirs::config_dir()?
+                .join("ser-hex-viewer")
+                .join("colors.json"),
+        )
+    }
+    fn read() -> Result<Settings> {
+        match Self::file().filter(|file| file.exists()) {
+            Some(file) => Ok(serde_json::from_slice(&fs::read(file)?)?),
+            None => Ok(Settings::default()),
+        }
+    }
+    /// Load the color settings, the defaults if none have been saved or they could not be read
+    pub fn load() -> Self {
+        let settings = Self::read().unwrap_or_else(|err| {
+            eprintln!("failed to load color settings {err:?}");
+            Settings::default()
+        });
+        Self {
+            settings,
+            new_color: [255, 128, 0],
+            ..Default::default()
+        }
+    }
+    fn save(&self) -> Result<()> {
+        let file = Self::file().context("no config directory")?;
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(file, serde_json::to_string_pretty(&self.settings)?)?;
+        Ok(())
+    }
+    /// Background color of `span`
+    pub fn span(&self, span: &FlatSpan) -> egui::Color32 {
+        let settings = &self.settings;
+        if let Some([r, g, b]) = settings.names.get(&span.name) {
+            return egui::Color32::from_rgb(*r, *g, *b);
+        }
+        let key = match settings.scheme {
+            Scheme::Name => hash(&span.name),
+            Scheme::Depth => span.depth as u64,
+            Scheme::Ancestor => hash(&span.ancestor),
+        };
+        if let Some([r, g, b]) = settings
+            .palette
+            .get((key % settings.palette.len().max(1) as u64) as usize)
+        {
+            return egui::Color32::from_rgb(*r, *g, *b);
+        }
+        let hue = match settings.scheme {
+            // consecutive depths far apart
+            Scheme::Depth => (key as f32 * 0.618).fract(),
+            _ => (key % 256) as f32 / 256.0,
+        };
+        Hsva::new(hue, 1., 0.5, 1.).into()
+    }
+    /// Color of the byte at `address` if not colored by its span
+    pub fn byte(&self, data: &[u8], address: usize) -> Option<egui::Color32> {
+        match self.settings.bytes {
+            ByteColors::Span => None,
+            ByteColors::Class => Some(class_color(data[address])),
+            // blue for uniform through to red for random
+            ByteColors::Entropy => {
+                let hue = 0.66 * (1. - entropy(data, address));
+                Some(Hsva::new(hue, 1., 1., 1.).into())
+            }
+        }
+    }
+    /// Selects what bytes are colored by
+    pub fn bytes_ui(&mut self, ui: &mut egui::Ui) {
+        let bytes = &mut self.settings.bytes;
+        let mut changed = false;
+        egui::ComboBox::from_id_salt("byte colors")
+            .selected_text(format!("color {}", bytes.label()))
+            .show_ui(ui, |ui| {
+                for mode in ByteColors::ALL {
+                    changed |= ui.selectable_value(bytes, mode, mode.label()).changed();
+                }
+            })
+            .response
+            .on_hover_text(
+                "byte class: zero gray, printable ASCII blue, control green, 0xff white, \
+                 other high bytes orange\nentropy: blue for uniform to red for random data",
+            );
+        if changed {
+            if let Err(err) = self.save() {
+                eprintln!("failed to save color settings {err:?}");
+            }
+        }
+    }
+    /// Text color of the bytes of `span`, a lighter shade of its background
+    pub fn text(&self, span: &FlatSpan) -> egui::Color32 {
+        let mut color = Hsva::from(self.span(span));
+        color.s *= 0.5;
+        color.v = 1.;
+        color.into()
+    }
+    /// Settings window, when open
+    pub fn window(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        let mut changed = false;
+        egui::Window::new("span colors")
+            .open(&mut open)
+            .show(ctx, |ui| changed = self.ui(ui));
+        self.open = open;
+        if changed {
+            if let Err(err) = self.save() {
+                eprintln!("failed to save color settings {err:?}");
+            }
+        }
+    }
+    /// Returns whether the settings changed
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let settings = &mut self.settings;
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            for scheme in Scheme::ALL {
+                changed |= ui
+                    .radio_value(&mut settings.scheme, scheme, scheme.label())
+                    .changed();
+            }
+        });
+
+        ui.separator();
+        ui.label("palette");
+        ui.horizontal_wrapped(|ui| {
+            let mut remove = None;
+            for (i, color) in settings.palette.iter_mut().enumerate() {
+                let edit = ui.color_edit_button_srgb(color);
+                changed |= edit.changed();
+                if edit.secondary_clicked() {
+                    remove = Some(i);
+                }
+            }
+            if let Some(i) = remove {
+                settings.palette.remove(i);
+                changed = true;
+            }
+            if ui.small_button("+").on_hover_text("add color").clicked() {
+                settings.palette.push(self.new_color);
+                changed = true;
+            }
+        });
+        match settings.palette.is_empty() {
+            true => ui.weak("empty, colors are spread over all hues"),
+            false => ui.weak("right click a color to remove it"),
+        };
+
+        ui.separator();
+        ui.label("span names");
+        ui.horizontal(|ui| {
+            ui.color_edit_button_srgb(&mut self.new_color);
+            ui.add(
+                egui::TextEdit::singleline(&mut self.new_name)
+                    .hint_text("span name")
+                    .desired_width(120.),
+            );
+            let add = ui.add_enabled(!self.new_name.is_empty(), egui::Button::new("add"));
+            if add.clicked() {
+                let name = std::mem::take(&mut self.new_name);
+                settings.names.insert(name, self.new_color);
+                changed = true;
+            }
+        });
+        let mut remove = None;
+        for (name, color) in &mut settings.names {
+            ui.horizontal(|ui| {
+                changed |= ui.color_edit_button_srgb(color).changed();
+                ui.label(name);
+                if ui.small_button("🗑").on_hover_text("remove").clicked() {
+                    remove = Some(name.clone());
+                }
+            });
+        }
+        if let Some(name) = remove {
+            settings.names.remove(&name);
+            changed = true;
+        }
+        changed
+    }
+}
+
+fn hash(value: &str) -> u64 {
+    let mut s = DefaultHasher::new();
+    value.hash(&mut s);
+    s.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn span(name: &str, depth: usize, ancestor: &str) -> FlatSpan {
+        FlatSpan {
+            range: 0..1,
+            name: name.into(),
+            path: vec![],
+            depth,
+            ancestor: ancestor.into(),
+        }
+    }
+
+    #[test]
+    fn test_entropy() {
+        let data: Vec<u8> = (0..=255).chain([0; 256]).collect();
+        assert_eq!(entropy(&data, 10), 1.);
+        assert_eq!(entropy(&data, 300), 0.);
+    }
+
+    #[test]
+    fn test_span_colors() {
+        let mut colors = Colors::default();
+        colors.settings.palette = vec![[1, 1, 1], [2, 2, 2]];
+        colors.settings.scheme = Scheme::Depth;
+        let color = |colors: &Colors, span| colors.span(&span).to_array();
+        assert_eq!(color(&colors, span("a", 0, "root")), [1, 1, 1, 255]);
+        assert_eq!(color(&colors, span("a", 3, "root")), [2, 2, 2, 255]);
+
+        colors.settings.scheme = Scheme::Ancestor;
+        assert_eq!(
+            color(&colors, span("a", 1, "root")),
+            color(&colors, span("b", 2, "root"))
+        );
+
+        colors.settings.names.insert("b".into(), [9, 9, 9]);
+        assert_eq!(color(&colors, span("b", 2, "root")), [9, 9, 9, 255]);
+    }
+}
@@ -0,0 +1,90 @@
+//! Copying bytes to the clipboard as text
+
+use base64::prelude::*;
+use eframe::egui;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CopyFormat {
+    Hex,
+    SpacedHex,
+    CArray,
+    RustSlice,
+    Base64,
+}
+impl CopyFormat {
+    const ALL: [CopyFormat; 5] = [
+        CopyFormat::Hex,
+        CopyFormat::SpacedHex,
+        CopyFormat::CArray,
+        CopyFormat::RustSlice,
+        CopyFormat::Base64,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            CopyFormat::Hex => "hex",
+            CopyFormat::SpacedHex => "spaced hex",
+            CopyFormat::CArray => "C array",
+            CopyFormat::RustSlice => "Rust &[u8]",
+            CopyFormat::Base64 => "base64",
+        }
+    }
+    fn format(self, bytes: &[u8]) -> String {
+        /// Comma separated `0x` bytes, 16 to a line
+        fn list(bytes: &[u8]) -> String {
+            let lines: Vec<_> = bytes
+                .chunks(16)
+                .map(|line| {
+                    let line: Vec<_> = line.iter().map(|b| format!("0x{b:02x}")).collect();
+                    format!("    {},", line.join(", "))
+                })
+                .collect();
+            lines.join("\n")
+        }
+        let hex = || bytes.iter().map(|b| format!("{b:02x}"));
+        match self {
+            CopyFormat::Hex => hex().collect(),
+            CopyFormat::SpacedHex => hex().collect::<Vec<_>>().join(" "),
+            CopyFormat::CArray => format!(
+                "unsigned char data[{}] = {{\n{}\n}};",
+                bytes.len(),
+                list(bytes)
+            ),
+            CopyFormat::RustSlice => format!("&[\n{}\n]", list(bytes)),
+            CopyFormat::Base64 => BASE64_STANDARD.encode(bytes),
+        }
+    }
+}
+
+/// Menu copying `bytes` in one of the formats
+pub fn menu(ui: &mut egui::Ui, bytes: &[u8]) {
+    ui.menu_button("copy as", |ui| {
+        for format in CopyFormat::ALL {
+            if ui.button(format.label()).clicked() {
+                ui.ctx().copy_text(format.format(bytes));
+                ui.close_menu();
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_copy_formats() {
+        let bytes = [0xde, 0xad, 0x01];
+        assert_eq!(CopyFormat::Hex.format(&bytes), "dead01");
+        assert_eq!(CopyFormat::SpacedHex.format(&bytes), "de ad 01");
+        assert_eq!(
+            CopyFormat::CArray.format(&bytes),
+            "unsigned char data[3] = {\n    0xde, 0xad, 0x01,\n};"
+        );
+        assert_eq!(
+            CopyFormat::RustSlice.format(&bytes),
+            "&[\n    0xde, 0xad, 0x01,\n]"
+        );
+        assert_eq!(CopyFormat::Base64.format(&bytes), "3q0B");
+    }
+}
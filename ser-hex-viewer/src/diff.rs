@@ -0,0 +1,246 @@
+//! Side-by-side comparison of two traces of the same data, e.g. parsed by two versions of a
+//! parser
+
+use std::{collections::HashSet, ops::Range, path::Path};
+
+use anyhow::Result;
+use eframe::{egui, Frame};
+use egui::Context;
+
+use crate::{
+    colors::Colors, loading::Progress, offsets::Offsets, span_path, Selection, Trace, TreeResponse,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Divergence {
+    /// No read of the same range within spans of the same names in the other trace
+    Structure,
+    /// Read the same way by both traces but the bytes differ
+    Data,
+}
+impl Divergence {
+    fn color(self) -> egui::Color32 {
+        match self {
+            Divergence::Structure => egui::Color32::RED,
+            Divergence::Data => egui::Color32::from_rgb(255, 140, 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Diverged {
+    range: Range<usize>,
+    /// Index path of the read, see [`ser_hex::FlatAction::index_path`]
+    path: Vec<usize>,
+    kind: Divergence,
+}
+
+/// Reads of each trace which differ from the other
+fn diverged_reads<A: AsRef<[u8]>, B: AsRef<[u8]>>(
+    a: &ser_hex::Trace<A>,
+    b: &ser_hex::Trace<B>,
+) -> [Vec<Diverged>; 2] {
+    type Read<'a> = (Range<usize>, Vec<&'a str>, Vec<usize>);
+    fn reads<D: AsRef<[u8]>>(trace: &ser_hex::Trace<D>) -> Vec<Read<'_>> {
+        trace
+            .iter_flat()
+            .filter(|flat| flat.action.read_size().is_some())
+            .map(|flat| {
+                let names = flat.path.iter().map(|s| s.span.0.name.as_ref()).collect();
+                (flat.range.clone(), names, flat.index_path())
+            })
+            .collect()
+    }
+
+    let reads = [reads(a), reads(b)];
+    let data = [a.data.as_ref(), b.data.as_ref()];
+    let keys: [HashSet<_>; 2] =
+        std::array::from_fn(|side| reads[side].iter().map(|(r, n, _)| (r, n)).collect());
+    std::array::from_fn(|side| {
+        let other = 1 - side;
+        reads[side]
+            .iter()
+            .filter_map(|(range, names, path)| {
+                let kind = if !keys[other].contains(&(range, names)) {
+                    Divergence::Structure
+                } else if data[side].get(range.clone()) != data[other].get(range.clone()) {
+                    Divergence::Data
+                } else {
+                    return None;
+                };
+                Some(Diverged {
+                    range: range.clone(),
+                    path: path.clone(),
+                    kind,
+                })
+            })
+            .collect()
+    })
+}
+
+pub struct DiffApp {
+    panes: [Trace; 2],
+    diverged: [Vec<Diverged>; 2],
+    /// Side and index of every diverged read ordered by offset
+    order: Vec<(usize, usize)>,
+    /// Index into `order` of the selected divergence
+    current: Option<usize>,
+    path_select: [Option<Vec<usize>>; 2],
+    colors: Colors,
+    offsets: Offsets,
+}
+impl DiffApp {
+    pub fn new(a: &Path, b: &Path) -> Result<Self> {
+        let traces = [ser_hex::Trace::open_mmap(a)?, ser_hex::Trace::open_mmap(b)?];
+        let diverged = diverged_reads(&traces[0], &traces[1]);
+        let [a_trace, b_trace] = traces;
+        let progress = Progress::default();
+        let mut panes = [
+            Trace::from_file(a_trace, a, &progress)?,
+            Trace::from_file(b_trace, b, &progress)?,
+        ];
+        for (pane, diverged) in panes.iter_mut().zip(&diverged) {
+            for d in diverged {
+                pane.full_tree.mark_diverged(&d.path);
+            }
+        }
+
+        let mut order: Vec<_> = (0..2)
+            .flat_map(|side| (0..diverged[side].len()).map(move |i| (side, i)))
+            .collect();
+        order.sort_by_key(|&(side, i)| (diverged[side][i].range.start, side));
+
+        Ok(Self {
+            panes,
+            diverged,
+            order,
+            current: None,
+            path_select: [None, None],
+            colors: Colors::load(),
+            offsets: Offsets::default(),
+        })
+    }
+    /// Select `range` in both traces
+    fn select(&mut self, range: Range<usize>) {
+        for (pane, path_select) in self.panes.iter_mut().zip(&mut self.path_select) {
+            pane.selection = Some(Selection::goto(&mut pane.mem_editor, range.clone()));
+            *path_select = span_path(&pane.interval_tree, range.start);
+        }
+    }
+    fn step(&mut self, forward: bool) {
+        let len = self.order.len();
+        if len == 0 {
+            return;
+        }
+        let current = match (self.current, forward) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(i), true) => (i + 1) % len,
+            (Some(i), false) => (i + len - 1) % len,
+        };
+        self.current = Some(current);
+        let (side, i) = self.order[current];
+        self.select(self.diverged[side][i].range.clone());
+    }
+}
+
+impl eframe::App for DiffApp {
+    fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
+        egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("span colors…").clicked() {
+                    self.colors.open = true;
+                }
+                self.offsets.ui(ui);
+                ui.separator();
+                if self.order.is_empty() {
+                    ui.label("no diverging reads");
+                    return;
+                }
+                let current = self.current.map(|i| (i + 1).to_string());
+                ui.label(format!(
+                    "{}/{} diverging reads",
+                    current.as_deref().unwrap_or("-"),
+                    self.order.len()
+                ));
+                if ui.button("⬆").on_hover_text("previous").clicked() {
+                    self.step(false);
+                }
+                if ui.button("⬇").on_hover_text("next").clicked() {
+                    self.step(true);
+                }
+                ui.separator();
+                for kind in [Divergence::Structure, Divergence::Data] {
+                    ui.colored_label(kind.color(), format!("{kind:?}").to_lowercase());
+                }
+            });
+        });
+
+        let mut goto = None;
+        for side in 0..2 {
+            let panel = match side {
+                0 => egui::SidePanel::left("tree a"),
+                _ => egui::SidePanel::right("tree b"),
+            };
+            let pane = &mut self.panes[side];
+            let path_select = &mut self.path_select[side];
+            panel.show(ctx, |ui| {
+                ui.label(&pane.title);
+                let tree = &pane.full_tree;
+                match pane.tree_view.ui(ui, tree, "", path_select.take(), None) {
+                    Some(TreeResponse::Goto(range)) => goto = Some(range),
+                    Some(TreeResponse::Seek { to, .. }) => goto = Some(to..to + 1),
+                    Some(TreeResponse::Export(range)) => {
+                        crate::export_bytes((*pane.data).as_ref(), range)
+                    }
+                    Some(TreeResponse::Note(range, span)) => pane.notes.write(range, span),
+                    // sub-traces are not compared
+                    Some(TreeResponse::OpenSubTrace(_)) | None => {}
+                }
+            });
+        }
+        if let Some(range) = goto {
+            self.select(range);
+        }
+
+        self.colors.window(ctx);
+        for pane in &mut self.panes {
+            pane.notes.draft_window(ctx);
+        }
+
+        let mut clicked = None;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.columns(2, |columns| {
+                for (side, ui) in columns.iter_mut().enumerate() {
+                    let pane = &mut self.panes[side];
+                    let diverged = &self.diverged[side];
+                    let colors = &self.colors;
+                    let offsets = self.offsets;
+                    ui.push_id(side, |ui| {
+                        ui.label(&pane.title);
+                        let extra = diverged
+                            .iter()
+                            .map(|d| (d.range.clone(), d.kind.color()))
+                            .collect();
+                        if pane.hex_ui(ui, colors, offsets, extra).is_some() {
+                            clicked = Some(side);
+                        }
+                    });
+                }
+            });
+        });
+        // keep the other trace in sync with the one clicked
+        if let Some(side) = clicked {
+            let range = self.panes[side].selection.map(|s| s.range());
+            let other = &mut self.panes[1 - side];
+            other.selection = range
+                .clone()
+                .map(|r| Selection::goto(&mut other.mem_editor, r));
+            for (pane, path_select) in self.panes.iter().zip(&mut self.path_select) {
+                *path_select = range
+                    .as_ref()
+                    .and_then(|r| span_path(&pane.interval_tree, r.start));
+            }
+        }
+    }
+}
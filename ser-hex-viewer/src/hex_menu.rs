@@ -0,0 +1,115 @@
+//! Context menu of the hex view, acting on the selected bytes
+
+use std::ops::Range;
+
+use eframe::egui;
+
+use crate::{inspector, offsets::Offsets};
+
+/// Actions of the menu needing more than the selected bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HexAction {
+    Bookmark(Range<usize>),
+    Note(Range<usize>),
+    /// Select the offset pointed to
+    Follow(usize),
+    /// Select the span enclosing the selection
+    SelectSpan,
+    Export(Range<usize>),
+    /// Export the selection as it looks in the hex view
+    ExportHtml(Range<usize>),
+}
+
+#[derive(Debug, Default)]
+pub struct HexMenu {
+    /// Where the menu is open
+    pos: Option<egui::Pos2>,
+}
+impl HexMenu {
+    /// Menu opened by right clicking `ui`, the panel of the hex view, acting on `selection`.
+    /// Returns the chosen action if the menu could not carry it out itself
+    pub fn ui(
+        &mut self,
+        ui: &egui::Ui,
+        data: &[u8],
+        selection: Range<usize>,
+        big_endian: bool,
+        base: usize,
+        offsets: Offsets,
+    ) -> Option<HexAction> {
+        if ui.input(|i| i.pointer.secondary_clicked()) && ui.rect_contains_pointer(ui.max_rect()) {
+            self.pos = ui.input(|i| i.pointer.interact_pos());
+        }
+        let pos = self.pos?;
+        let selected = data.get(selection.clone()).unwrap_or_default();
+        let start = selection.start;
+        let width = if selection.len() == 8 { 8 } else { 4 };
+        let bytes = data.get(start..).unwrap_or_default();
+        let target = inspector::pointer(bytes, width, big_endian, base).filter(|&t| t < data.len());
+
+        let mut action = None;
+        let mut done = false;
+        let area = egui::Area::new(egui::Id::new("hex menu"))
+            .order(egui::Order::Foreground)
+            .fixed_pos(pos)
+            .show(ui.ctx(), |ui| {
+                egui::Frame::menu(ui.style()).show(ui, |ui| {
+                    let end = selection.end - 1;
+                    ui.weak(format!(
+                        "{}..={}",
+                        offsets.format(start, None),
+                        offsets.format(end, None)
+                    ));
+                    if ui.button("copy value").clicked() {
+                        ui.ctx().copy_text(inspector::value(selected, big_endian));
+                        done = true;
+                    }
+                    if ui.button("copy offset").clicked() {
+                        ui.ctx().copy_text(offsets.format(start, None));
+                        done = true;
+                    }
+                    ui.separator();
+                    let follow = ui
+                        .add_enabled(target.is_some(), egui::Button::new("follow offset"))
+                        .on_hover_text(format!("go to the offset read as a u{}", width * 8))
+                        .on_disabled_hover_text("points outside the data");
+                    if follow.clicked() {
+                        action = target.map(HexAction::Follow);
+                    }
+                    if ui.button("select owning span").clicked() {
+                        action = Some(HexAction::SelectSpan);
+                    }
+                    ui.separator();
+                    if ui.button("add bookmark").clicked() {
+                        action = Some(HexAction::Bookmark(selection.clone()));
+                    }
+                    if ui.button("add note…").clicked() {
+                        action = Some(HexAction::Note(selection.clone()));
+                    }
+                    if ui.button("export…").clicked() {
+                        action = Some(HexAction::Export(selection.clone()));
+                    }
+                    let html = ui
+                        .button("export as HTML…")
+                        .on_hover_text("the bytes colored and labelled by span, for bug reports");
+                    if html.clicked() {
+                        action = Some(HexAction::ExportHtml(selection.clone()));
+                    }
+                });
+            });
+        let clicked_outside = ui.input(|i| {
+            i.pointer.primary_pressed()
+                && i.pointer
+                    .interact_pos()
+                    .is_some_and(|p| !area.response.rect.contains(p))
+        });
+        if done
+            || action.is_some()
+            || clicked_outside
+            || ui.input(|i| i.key_pressed(egui::Key::Escape))
+        {
+            self.pos = None;
+        }
+        action
+    }
+}
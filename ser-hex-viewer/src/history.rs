@@ -0,0 +1,93 @@
+//! Back/forward history of the ranges selected in a trace
+
+use std::ops::Range;
+
+use eframe::egui;
+
+/// Maximum number of ranges kept to go back to
+const MAX_HISTORY: usize = 1000;
+
+#[derive(Debug, Default)]
+pub struct History {
+    current: Option<Range<usize>>,
+    back: Vec<Range<usize>>,
+    forward: Vec<Range<usize>>,
+}
+impl History {
+    /// Record the current selection, called once per frame after all navigation
+    pub fn record(&mut self, selection: Option<Range<usize>>) {
+        if selection.is_none() || selection == self.current {
+            return;
+        }
+        if let Some(current) = std::mem::replace(&mut self.current, selection) {
+            if self.back.len() == MAX_HISTORY {
+                self.back.remove(0);
+            }
+            self.back.push(current);
+        }
+        self.forward.clear();
+    }
+    /// Step back or forward, returns the range to go to
+    pub fn go(&mut self, forward: bool) -> Option<Range<usize>> {
+        let (from, to) = match forward {
+            true => (&mut self.forward, &mut self.back),
+            false => (&mut self.back, &mut self.forward),
+        };
+        let target = from.pop()?;
+        if let Some(current) = self.current.replace(target.clone()) {
+            to.push(current);
+        }
+        Some(target)
+    }
+    /// Back and forward buttons, also bound to the extra mouse buttons and alt+left/right.
+    /// Returns the range to go to
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<Range<usize>> {
+        let (mut back, mut forward) = ui.input(|i| {
+            use egui::{Key, PointerButton};
+            (
+                i.pointer.button_pressed(PointerButton::Extra1)
+                    || i.modifiers.alt && i.key_pressed(Key::ArrowLeft),
+                i.pointer.button_pressed(PointerButton::Extra2)
+                    || i.modifiers.alt && i.key_pressed(Key::ArrowRight),
+            )
+        });
+        let enabled = !self.back.is_empty();
+        back |= ui
+            .add_enabled(enabled, egui::Button::new("⬅"))
+            .on_hover_text("back (alt+left)")
+            .clicked();
+        let enabled = !self.forward.is_empty();
+        forward |= ui
+            .add_enabled(enabled, egui::Button::new("➡"))
+            .on_hover_text("forward (alt+right)")
+            .clicked();
+        match (back, forward) {
+            (true, _) => self.go(false),
+            (false, true) => self.go(true),
+            (false, false) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let mut history = History::default();
+        history.record(Some(0..1));
+        history.record(Some(4..8));
+        history.record(None);
+        history.record(Some(10..11));
+        assert_eq!(history.go(false), Some(4..8));
+        assert_eq!(history.go(false), Some(0..1));
+        assert_eq!(history.go(false), None);
+        assert_eq!(history.go(true), Some(4..8));
+
+        // navigating elsewhere drops the forward history
+        history.record(Some(20..21));
+        assert_eq!(history.go(true), None);
+        assert_eq!(history.go(false), Some(4..8));
+    }
+}
@@ -0,0 +1,149 @@
+//! Excerpts of the hex view as standalone HTML, colored and labelled by span like the view, to
+//! paste into bug reports
+
+use std::{fmt::Write as _, ops::Range};
+
+use eframe::egui::Color32;
+use fs_err as fs;
+
+/// Largest excerpt exported, larger ranges are truncated
+const MAX_BYTES: usize = 64 * 1024;
+
+/// How a byte is drawn, from the span it was read by
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Style {
+    pub label: String,
+    pub background: Color32,
+    pub text: Color32,
+}
+
+fn css(color: Color32) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// HTML page of `range` of `data` in rows of `columns` bytes with the bytes styled by `style`,
+/// followed by the spans in the range
+pub fn render(
+    data: &[u8],
+    range: Range<usize>,
+    columns: usize,
+    style: impl Fn(usize) -> Option<Style>,
+) -> String {
+    let range = range.start..range.end.min(range.start + MAX_BYTES).min(data.len());
+    let mut html = String::new();
+    let title = format!("{:#x}..{:#x}", range.start, range.end);
+    writeln!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title><style>\
+         body {{ background: #1b1b1b; color: #ccc; font-family: monospace; }} \
+         td {{ padding: 0 4px; white-space: pre; }} .addr {{ color: #888; }}\
+         </style></head><body>\n<table>"
+    )
+    .unwrap();
+
+    let first_row = range.start / columns * columns;
+    let mut spans: Vec<(Style, Range<usize>)> = vec![];
+    for row in (first_row..range.end).step_by(columns) {
+        let (mut hex, mut text) = (String::new(), String::new());
+        for address in row..row + columns {
+            if !range.contains(&address) {
+                hex.push_str("   ");
+                text.push(' ');
+                continue;
+            }
+            let b = data[address];
+            let c = match b.is_ascii_graphic() {
+                true => escape(&(b as char).to_string()),
+                false => ".".into(),
+            };
+            let Some(style) = style(address) else {
+                write!(hex, "{b:02x} ").unwrap();
+                text.push_str(&c);
+                continue;
+            };
+            let attrs = format!(
+                "style=\"background: {}; color: {}\" title=\"{address:#x}: {}\"",
+                css(style.background),
+                css(style.text),
+                escape(&style.label)
+            );
+            write!(hex, "<span {attrs}>{b:02x}</span> ").unwrap();
+            write!(text, "<span {attrs}>{c}</span>").unwrap();
+            match spans.last_mut() {
+                Some((last, r)) if *last == style && r.end == address => r.end += 1,
+                _ => spans.push((style, address..address + 1)),
+            }
+        }
+        writeln!(
+            html,
+            "<tr><td class=\"addr\">{row:08x}</td><td>{hex}</td><td>{text}</td></tr>"
+        )
+        .unwrap();
+    }
+
+    html.push_str("</table>\n<ul>\n");
+    for (style, range) in spans {
+        writeln!(
+            html,
+            "<li><span style=\"background: {}\">&nbsp;&nbsp;</span> {} {:#x}..{:#x}</li>",
+            css(style.background),
+            escape(&style.label),
+            range.start,
+            range.end
+        )
+        .unwrap();
+    }
+    html.push_str("</ul>\n</body></html>\n");
+    html
+}
+
+/// Ask where to save the HTML excerpt of `range` and write it there
+pub fn export(
+    data: &[u8],
+    range: Range<usize>,
+    columns: usize,
+    style: impl Fn(usize) -> Option<Style>,
+) {
+    let name = format!("{:x}-{:x}.html", range.start, range.end);
+    let Some(path) = rfd::FileDialog::new()
+        .set_file_name(name)
+        .add_filter("HTML", &["html"])
+        .save_file()
+    else {
+        return;
+    };
+    if let Err(err) = fs::write(path, render(data, range, columns, style)) {
+        eprintln!("failed to export HTML {err:?}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let data = b"\x01\x02<a>";
+        let html = render(data, 1..5, 4, |address| {
+            (address < 3).then(|| Style {
+                label: "Vec<u8>".into(),
+                background: Color32::from_rgb(255, 0, 0),
+                text: Color32::WHITE,
+            })
+        });
+        assert!(html.contains("<td class=\"addr\">00000000</td>"));
+        assert!(html.contains("<td class=\"addr\">00000004</td>"));
+        assert!(html.contains("title=\"0x2: Vec&lt;u8&gt;\">3c</span>"));
+        assert!(html.contains(
+            "<span style=\"background: #ff0000\">&nbsp;&nbsp;</span> Vec&lt;u8&gt; 0x1..0x3"
+        ));
+        assert!(html.contains("<td>&gt;   </td>"));
+    }
+}
@@ -0,0 +1,240 @@
+//! Interpretations of the bytes at the selected address
+
+use std::ops::Range;
+
+use eframe::egui;
+
+use crate::offsets::Offsets;
+
+/// Maximum number of bytes decoded for string interpretations
+const MAX_STRING: usize = 64;
+
+/// Values at the start of `selection`, read from the selected bytes alone if more than one is
+/// selected, and the offset from `relative` origin. Returns the offset pointed to when following
+/// the selected bytes as a pointer
+pub fn ui(
+    ui: &mut egui::Ui,
+    data: &[u8],
+    selection: Range<usize>,
+    big_endian: bool,
+    relative: Option<(Offsets, usize)>,
+    base: &mut usize,
+) -> Option<usize> {
+    let order = if big_endian { "BE" } else { "LE" };
+    let start = selection.start;
+    ui.label(format!("offset {start} ({start:#x}), {order}"));
+    if let Some((offsets, origin)) = relative {
+        ui.label(format!(
+            "{} from the span at {}",
+            offsets.format(start, Some(origin)),
+            offsets.format(origin, None)
+        ));
+    }
+    let selected = data.get(selection.clone()).unwrap_or_default();
+    let bytes = if selection.len() > 1 {
+        let end = selection.end - 1;
+        ui.label(format!(
+            "selected {} bytes, {start}..={end} ({start:#x}..={end:#x})",
+            selection.len()
+        ));
+        selected
+    } else {
+        data.get(start..).unwrap_or_default()
+    };
+    ui.horizontal(|ui| {
+        crate::copy::menu(ui, selected);
+        if ui.button("export…").clicked() {
+            crate::export_bytes(data, selection);
+        }
+    });
+    let mut follow = None;
+    ui.horizontal(|ui| {
+        for width in [4, 8] {
+            let target = pointer(bytes, width, big_endian, *base).filter(|&t| t < data.len());
+            let button = ui
+                .add_enabled(
+                    target.is_some(),
+                    egui::Button::new(format!("follow u{}", width * 8)),
+                )
+                .on_hover_text(format!(
+                    "go to the offset read as a u{} in the selected byte order plus the base",
+                    width * 8
+                ))
+                .on_disabled_hover_text("points outside the data");
+            if button.clicked() {
+                follow = target;
+            }
+        }
+        ui.label("base");
+        ui.add(
+            egui::DragValue::new(base)
+                .hexadecimal(1, false, false)
+                .prefix("0x"),
+        )
+        .on_hover_text(
+            "added to pointers followed, e.g. the start of the section they are relative to",
+        );
+        if ui
+            .small_button("selected")
+            .on_hover_text("use the selected offset as the base")
+            .clicked()
+        {
+            *base = start;
+        }
+    });
+    egui::Grid::new("inspector").striped(true).show(ui, |ui| {
+        for (label, value) in interpret(bytes, big_endian) {
+            ui.label(label);
+            // selectable so values can be copied out
+            ui.add(egui::Label::new(value.unwrap_or_else(|| "-".into())).selectable(true));
+            ui.end_row();
+        }
+    });
+    follow
+}
+
+/// Value of `bytes` as copied from the hex view, an unsigned integer if 1, 2, 4 or 8 bytes long
+/// otherwise hex
+pub fn value(bytes: &[u8], big_endian: bool) -> String {
+    match bytes.len() {
+        1 | 2 | 4 | 8 => pointer(bytes, bytes.len(), big_endian, 0)
+            .map(|v| v.to_string())
+            .unwrap_or_default(),
+        _ => bytes
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Offset pointed to by the first `width` bytes read as an unsigned integer plus `base`
+pub fn pointer(bytes: &[u8], width: usize, big_endian: bool, base: usize) -> Option<usize> {
+    let bytes = bytes.get(..width)?;
+    let mut value = [0; 8];
+    let value = match big_endian {
+        true => {
+            value[8 - width..].copy_from_slice(bytes);
+            u64::from_be_bytes(value)
+        }
+        false => {
+            value[..width].copy_from_slice(bytes);
+            u64::from_le_bytes(value)
+        }
+    };
+    usize::try_from(value).ok()?.checked_add(base)
+}
+
+fn interpret(bytes: &[u8], big_endian: bool) -> Vec<(&'static str, Option<String>)> {
+    macro_rules! num {
+        ($($ty:ty),*) => {
+            [$((
+                stringify!($ty),
+                bytes.first_chunk().map(|b| {
+                    match big_endian {
+                        true => <$ty>::from_be_bytes(*b),
+                        false => <$ty>::from_le_bytes(*b),
+                    }
+                    .to_string()
+                }),
+            )),*]
+        };
+    }
+    let mut rows = num!(i8, u8, i16, u16, i32, u32, i64, u64, f32, f64).to_vec();
+    rows.push(("UTF-8", utf8(bytes)));
+    rows.push(("UTF-16", utf16(bytes, big_endian)));
+    rows.push(("GUID", guid(bytes, big_endian)));
+    rows
+}
+
+/// String up to the first nul, `None` if not valid UTF-8
+fn utf8(bytes: &[u8]) -> Option<String> {
+    let bytes = &bytes[..bytes.len().min(MAX_STRING)];
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    match std::str::from_utf8(&bytes[..end]) {
+        Ok(s) => Some(format!("{s:?}")),
+        // truncated in the middle of a character
+        Err(e) if e.error_len().is_none() => Some(format!(
+            "{:?}",
+            std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap()
+        )),
+        Err(_) => None,
+    }
+}
+
+/// String up to the first nul, `None` if not valid UTF-16
+fn utf16(bytes: &[u8], big_endian: bool) -> Option<String> {
+    let units = bytes[..bytes.len().min(MAX_STRING)]
+        .chunks_exact(2)
+        .map(|b| match big_endian {
+            true => u16::from_be_bytes([b[0], b[1]]),
+            false => u16::from_le_bytes([b[0], b[1]]),
+        })
+        .take_while(|u| *u != 0);
+    let s = char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .ok()?;
+    Some(format!("{s:?}"))
+}
+
+/// GUID with its first three fields in the selected byte order, little endian being the layout
+/// of Windows GUIDs
+fn guid(bytes: &[u8], big_endian: bool) -> Option<String> {
+    let b: &[u8; 16] = bytes.first_chunk()?;
+    let (a, b2, c) = match big_endian {
+        true => (
+            u32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+            u16::from_be_bytes([b[4], b[5]]),
+            u16::from_be_bytes([b[6], b[7]]),
+        ),
+        false => (
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            u16::from_le_bytes([b[4], b[5]]),
+            u16::from_le_bytes([b[6], b[7]]),
+        ),
+    };
+    let d: String = b[8..10].iter().map(|b| format!("{b:02x}")).collect();
+    let e: String = b[10..16].iter().map(|b| format!("{b:02x}")).collect();
+    Some(format!("{a:08x}-{b2:04x}-{c:04x}-{d}-{e}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_interpret() {
+        let bytes = [
+            0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+            0xee, 0xff,
+        ];
+        assert_eq!(
+            guid(&bytes, false).as_deref(),
+            Some("00112233-4455-6677-8899-aabbccddeeff")
+        );
+        assert_eq!(guid(&bytes[1..], false), None);
+
+        let rows = interpret(&1.5f32.to_be_bytes(), true);
+        assert!(rows.contains(&("f32", Some("1.5".into()))));
+        assert!(rows.contains(&("f64", None)));
+
+        assert_eq!(utf8(b"abc\0def").as_deref(), Some("\"abc\""));
+        assert_eq!(utf8(b"\xff").as_deref(), None);
+        assert_eq!(utf16(b"h\0i\0\0\0", false).as_deref(), Some("\"hi\""));
+    }
+
+    #[test]
+    fn test_pointer() {
+        let bytes = [0x10, 0, 0, 0, 0, 0, 0, 0x20];
+        assert_eq!(pointer(&bytes, 4, false, 0), Some(0x10));
+        assert_eq!(pointer(&bytes, 4, true, 0), Some(0x1000_0000));
+        assert_eq!(pointer(&bytes, 4, false, 0x100), Some(0x110));
+        assert_eq!(pointer(&bytes, 8, false, 0), Some(0x2000_0000_0000_0010));
+        assert_eq!(pointer(&bytes[..3], 4, false, 0), None);
+        assert_eq!(pointer(&bytes, 8, true, usize::MAX), None);
+
+        assert_eq!(value(&bytes[..2], false), "16");
+        assert_eq!(value(&bytes[..2], true), "4096");
+        assert_eq!(value(&bytes[..3], false), "10 00 00");
+    }
+}
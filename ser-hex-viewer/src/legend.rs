@@ -0,0 +1,87 @@
+//! Legend of the span names with their colors and the number of bytes they read
+
+use std::{collections::HashMap, ops::Range};
+
+use eframe::egui;
+
+use crate::{colors::Colors, FlatSpan};
+
+#[derive(Debug)]
+struct Entry {
+    /// First read of the span name, also picks the color shown
+    first: FlatSpan,
+    /// Total bytes read by spans of the name
+    bytes: usize,
+}
+
+/// Span names ordered by the number of bytes they read
+#[derive(Debug, Default)]
+pub struct Legend {
+    entries: Vec<Entry>,
+}
+impl Legend {
+    pub fn new(spans: &[FlatSpan]) -> Self {
+        let mut entries: HashMap<&str, Entry> = HashMap::new();
+        for span in spans {
+            let entry = entries.entry(&span.name).or_insert_with(|| Entry {
+                first: span.clone(),
+                bytes: 0,
+            });
+            entry.bytes += span.range.len();
+            if span.range.start < entry.first.range.start {
+                entry.first = span.clone();
+            }
+        }
+        let mut entries: Vec<_> = entries.into_values().collect();
+        entries.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.first.name.cmp(&b.first.name)));
+        Self { entries }
+    }
+    /// Number of span names
+    pub fn count(&self) -> usize {
+        self.entries.len()
+    }
+    /// Returns the first read of the clicked span name
+    pub fn ui(&self, ui: &mut egui::Ui, colors: &Colors) -> Option<Range<usize>> {
+        let mut goto = None;
+        egui::Grid::new("legend").striped(true).show(ui, |ui| {
+            for entry in &self.entries {
+                let (rect, _) = ui.allocate_exact_size(egui::vec2(12., 12.), egui::Sense::hover());
+                ui.painter()
+                    .rect_filled(rect, 2., colors.span(&entry.first));
+                if ui
+                    .link(&entry.first.name)
+                    .on_hover_text("go to the first occurrence")
+                    .clicked()
+                {
+                    goto = Some(entry.first.range.clone());
+                }
+                ui.label(format!("{} bytes", entry.bytes));
+                ui.end_row();
+            }
+        });
+        goto
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_legend() {
+        let span = |name: &str, range: Range<usize>| FlatSpan {
+            range,
+            name: name.into(),
+            path: vec![],
+            depth: 0,
+            ancestor: "root".into(),
+        };
+        let legend = Legend::new(&[span("b", 4..6), span("a", 2..3), span("b", 0..2)]);
+        let entries: Vec<_> = legend
+            .entries
+            .iter()
+            .map(|e| (e.first.name.as_str(), e.first.range.clone(), e.bytes))
+            .collect();
+        assert_eq!(entries, [("b", 0..2, 4), ("a", 2..3, 1)]);
+    }
+}
@@ -0,0 +1,94 @@
+//! Loading traces on a background thread so the window stays responsive
+
+use std::{
+    path::PathBuf,
+    sync::{mpsc, Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use eframe::egui;
+
+use crate::{remote::Remote, FileTrace};
+
+/// Stage of a load, shared with the loading thread
+#[derive(Debug, Clone)]
+pub struct Progress(Arc<Mutex<(f32, &'static str)>>);
+impl Default for Progress {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new((0., "starting"))))
+    }
+}
+impl Progress {
+    /// Report the `stage` starting at `fraction` of the whole load
+    pub fn set(&self, fraction: f32, stage: &'static str) {
+        *self.0.lock().unwrap() = (fraction, stage);
+    }
+    fn get(&self) -> (f32, &'static str) {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// Trace being loaded on a background thread
+pub struct Loading {
+    /// Canonical path of the trace
+    pub path: PathBuf,
+    /// Replaces the trace of the tab it is open in rather than opening a new tab
+    pub reload: bool,
+    progress: Progress,
+    rx: mpsc::Receiver<Result<FileTrace>>,
+}
+impl Loading {
+    pub fn spawn(path: PathBuf, reload: bool) -> Self {
+        Self::spawn_with(path.clone(), reload, move |progress| {
+            FileTrace::load(path, progress)
+        })
+    }
+    /// Read or download `remote` to its temporary file and load it from there
+    pub fn spawn_remote(remote: Remote) -> Self {
+        let path = remote.path();
+        Self::spawn_with(path.clone(), false, move |progress| {
+            progress.set(0., "downloading");
+            remote.fetch()?;
+            FileTrace::load(path, progress)
+        })
+    }
+    fn spawn_with(
+        path: PathBuf,
+        reload: bool,
+        load: impl FnOnce(&Progress) -> Result<FileTrace> + Send + 'static,
+    ) -> Self {
+        let progress = Progress::default();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn({
+            let progress = progress.clone();
+            move || {
+                let _ = tx.send(load(&progress));
+            }
+        });
+        Self {
+            path,
+            reload,
+            progress,
+            rx,
+        }
+    }
+    /// The loaded trace once done
+    pub fn poll(&self) -> Option<Result<FileTrace>> {
+        match self.rx.try_recv() {
+            Ok(result) => Some(result),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err(anyhow!("loading thread panicked"))),
+        }
+    }
+    /// Progress bar of the load, repainting until done
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        let (fraction, stage) = self.progress.get();
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        ui.add(
+            egui::ProgressBar::new(fraction)
+                .desired_width(200.)
+                .animate(true)
+                .text(format!("{name}: {stage}")),
+        );
+    }
+}
@@ -1,38 +1,92 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
+mod bookmarks;
+mod colors;
+mod copy;
+mod diff;
+mod hex_menu;
+mod history;
+mod html;
+mod inspector;
+mod legend;
+mod loading;
+mod minimap;
+mod notes;
+mod occurrences;
+mod offsets;
+mod original;
+mod overlay;
+mod palette;
+mod recent;
+mod remote;
+mod search;
+mod strings;
+mod tree_filter;
+mod tree_view;
+
 use std::{
-    collections::hash_map::DefaultHasher,
     ops::{Range, RangeBounds},
     path::{Path, PathBuf},
 };
 
 use anyhow::{bail, Context as _, Result};
+use bookmarks::Bookmarks;
+use colors::Colors;
 use eframe::{
     egui::{self},
-    epaint::Hsva,
     Frame, NativeOptions,
 };
 use egui::Context;
 use fs_err as fs;
 
 use egui_memory_editor::{MemoryEditor, RenderCtx, SpanQuery};
+use hex_menu::{HexAction, HexMenu};
+use history::History;
 use intervaltree::IntervalTree;
+use legend::Legend;
+use loading::{Loading, Progress};
+use notes::Notes;
 use notify::RecommendedWatcher;
 use notify_debouncer_mini::{DebouncedEvent, DebouncedEventKind, Debouncer};
+use occurrences::Occurrences;
+use offsets::Offsets;
+use original::Original;
+use overlay::Overlay;
+use palette::{Command, Palette};
+use recent::Recent;
+use remote::Remote;
+use search::Search;
 use ser_hex::Action;
+use strings::Strings;
+use tree_filter::TreeFilter;
+use tree_view::TreeView;
 
 pub fn main() -> Result<()> {
     let mut args = std::env::args().skip(1);
-    let Some(trace) = args.next() else {
-        bail!("usage: ser-hex-viewer <TRACE PATH>");
+    let usage =
+        "usage: ser-hex-viewer [TRACE PATH | URL | -]... | ser-hex-viewer --diff <TRACE PATH> <TRACE PATH>";
+    let app: Box<dyn eframe::App> = match args.next() {
+        Some(flag) if flag == "--diff" => {
+            let (Some(a), Some(b)) = (args.next(), args.next()) else {
+                bail!(usage);
+            };
+            Box::new(diff::DiffApp::new(a.as_ref(), b.as_ref()).context("Failed to load traces")?)
+        }
+        first => {
+            let mut app = App::new();
+            for arg in first.into_iter().chain(args) {
+                match Remote::parse(&arg) {
+                    Some(remote) => app.loading.push(Loading::spawn_remote(remote)),
+                    None => app.open(arg.as_ref()),
+                }
+            }
+            Box::new(app)
+        }
     };
-    let trace = FileTrace::new(trace).context("Failed to load trace")?;
-
-    let app = App::new(trace)?;
     let _ = eframe::run_native(
         "Ser-Hex viewer",
         NativeOptions::default(),
-        Box::new(|_cc| Ok(Box::new(app))),
+        Box::new(|_cc| Ok(app)),
     );
     Ok(())
 }
@@ -40,172 +94,381 @@ pub fn main() -> Result<()> {
 type SparseTreeAction = ser_hex::Action<ser_hex::TreeSpan>;
 
 trait SparseTreeActionTrait {
-    fn build_tree(&self) -> IntervalTree<usize, FlatSpan>;
-    fn collect_spans(
-        &self,
-        index: &mut usize,
-        path: &mut Vec<usize>,
-        spans: &mut Vec<FlatSpan>,
-        name: &str,
-    );
     fn build_full_actions(&self, index: &mut usize) -> FullAction;
 }
 
-impl SparseTreeActionTrait for SparseTreeAction {
-    fn build_tree(&self) -> IntervalTree<usize, FlatSpan> {
-        let mut index = 0;
-
-        let mut spans = vec![];
-        let mut path = vec![];
-        self.collect_spans(&mut index, &mut path, &mut spans, "root");
-        IntervalTree::from_iter(spans.into_iter().map(|s| intervaltree::Element {
-            range: s.range.clone(),
-            value: s,
-        }))
-    }
-    fn collect_spans(
-        &self,
-        index: &mut usize,
-        path: &mut Vec<usize>,
-        spans: &mut Vec<FlatSpan>,
-        name: &str,
-    ) {
-        match self {
-            Action::Read(size) => {
-                spans.push(FlatSpan {
-                    range: *index..*index + size,
-                    name: name.to_string(),
-                    path: path.clone(),
-                });
-                *index += size;
-            }
-            Action::Seek(i) => {
-                /*
-                spans.push(TreeSpan {
-                    range: *index..*index + size,
-                    name: "read".into(),
-                });
-                */
-                *index = *i;
-            }
-            Action::Span(span) => {
-                path.push(0);
-                for (i, action) in span.0.actions.iter().enumerate() {
-                    *path.last_mut().unwrap() = i;
-                    action.collect_spans(index, path, spans, &span.0.name);
-                }
-                path.pop();
-            }
+/// Spans of every read
+fn flat_spans<D: AsRef<[u8]>>(trace: &ser_hex::Trace<D>) -> Vec<FlatSpan> {
+    let spans = trace.iter_flat().filter_map(|flat| {
+        flat.action.read_size().is_some().then(|| FlatSpan {
+            range: flat.range,
+            name: flat
+                .path
+                .last()
+                .map(|s| s.span.0.name.to_string())
+                .unwrap_or_else(|| "root".into()),
+            path: flat.index_path(),
+            depth: flat.path.len(),
+            ancestor: flat
+                .path
+                .first()
+                .map(|s| s.span.0.name.to_string())
+                .unwrap_or_else(|| "root".into()),
+        })
+    });
+    spans.collect()
+}
+
+fn build_tree(spans: Vec<FlatSpan>) -> IntervalTree<usize, FlatSpan> {
+    IntervalTree::from_iter(spans.into_iter().map(|s| intervaltree::Element {
+        range: s.range.clone(),
+        value: s,
+    }))
+}
+
+/// Ranges of the data not covered by any read
+fn unread_gaps<D: AsRef<[u8]>>(trace: &ser_hex::Trace<D>) -> Vec<Range<usize>> {
+    let mut reads: Vec<_> = trace
+        .iter_flat()
+        .filter(|flat| flat.action.read_size().is_some())
+        .map(|flat| flat.range)
+        .collect();
+    reads.sort_by_key(|r| r.start);
+
+    let mut gaps = vec![];
+    let mut covered = 0;
+    for read in reads {
+        if read.start > covered {
+            gaps.push(covered..read.start);
         }
+        covered = covered.max(read.end);
+    }
+    let len = trace.data.as_ref().len();
+    if len > covered {
+        gaps.push(covered..len);
     }
+    gaps
+}
+
+/// Sorted destination offsets of the seeks
+fn seek_targets<D: AsRef<[u8]>>(trace: &ser_hex::Trace<D>) -> Vec<usize> {
+    let mut targets: Vec<_> = trace
+        .iter_flat()
+        .filter(|flat| matches!(flat.action, Action::Seek(_)))
+        .map(|flat| flat.range.start)
+        .collect();
+    targets.sort_unstable();
+    targets.dedup();
+    targets
+}
+
+/// Highlight of seek sources and destinations
+const SEEK_COLOR: egui::Color32 = egui::Color32::from_rgb(0, 200, 200);
+
+/// Ask where to save `range` of `data` and write it there
+fn export_bytes(data: &[u8], range: Range<usize>) {
+    let name = format!("{:x}-{:x}.bin", range.start, range.end);
+    let Some(path) = rfd::FileDialog::new()
+        .set_file_name(name)
+        .add_filter("binary", &["bin"])
+        .save_file()
+    else {
+        return;
+    };
+    if let Err(err) = fs::write(path, data.get(range).unwrap_or_default()) {
+        eprintln!("failed to export bytes {err:?}");
+    }
+}
+
+/// Column count setting, also changed with the +/- keys. `current` is the count in use
+fn columns_ui(ui: &mut egui::Ui, columns: &mut Option<usize>, current: usize) {
+    let mut count = columns.unwrap_or(current);
+    let mut auto = columns.is_none();
+    let (more, less) = match ui.ctx().wants_keyboard_input() {
+        true => (false, false),
+        false => ui.input(|i| {
+            (
+                i.key_pressed(egui::Key::Plus) || i.key_pressed(egui::Key::Equals),
+                i.key_pressed(egui::Key::Minus),
+            )
+        }),
+    };
+    if more || less {
+        count = match more {
+            true => count + 1,
+            false => count - 1,
+        }
+        .clamp(1, MAX_COLUMNS);
+        auto = false;
+    }
+    ui.checkbox(&mut auto, "fit")
+        .on_hover_text("fit the column count to the width of the view");
+    let drag = egui::DragValue::new(&mut count)
+        .range(1..=MAX_COLUMNS)
+        .suffix(" columns");
+    ui.add_enabled(!auto, drag).on_hover_text("+/- to change");
+    *columns = match auto {
+        true => None,
+        false => Some(count),
+    };
+}
+
+/// Number of columns of the hex view fitting the width of `ui`
+fn fit_columns(ui: &egui::Ui) -> usize {
+    let font = egui::TextStyle::Monospace.resolve(ui.style());
+    let char_width = ui.fonts(|f| f.glyph_width(&font, '0'));
+    let spacing = ui.spacing().item_spacing.x;
+    // address, then per byte two hex digits in the grid and a character in the text column
+    let address = 10. * char_width + spacing;
+    let column = 3. * char_width + 2. * spacing;
+    let columns = (ui.available_width() - address) / column;
+    (columns as usize).clamp(1, MAX_COLUMNS)
+}
+
+const MAX_COLUMNS: usize = 64;
+
+/// Buttons expanding or collapsing the whole tree or expanding it to `depth`, also bound to
+/// ctrl+shift+E and ctrl+shift+C. Returns the depth to expand the tree to this frame
+fn expand_ui(ui: &mut egui::Ui, depth: &mut usize) -> Option<usize> {
+    let mut expand = ui.input_mut(|i| {
+        let shortcut = |key| {
+            egui::KeyboardShortcut::new(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, key)
+        };
+        if i.consume_shortcut(&shortcut(egui::Key::E)) {
+            Some(usize::MAX)
+        } else if i.consume_shortcut(&shortcut(egui::Key::C)) {
+            Some(0)
+        } else {
+            None
+        }
+    });
+    ui.horizontal(|ui| {
+        if ui
+            .button("expand all")
+            .on_hover_text("ctrl+shift+E")
+            .clicked()
+        {
+            expand = Some(usize::MAX);
+        }
+        if ui
+            .button("collapse all")
+            .on_hover_text("ctrl+shift+C")
+            .clicked()
+        {
+            expand = Some(0);
+        }
+        if ui.button("expand to").clicked() {
+            expand = Some(*depth);
+        }
+        ui.add(egui::DragValue::new(depth).range(1..=64).suffix(" levels"));
+    });
+    expand
+}
+
+/// Button loading a Kaitai Struct overlay onto `trace`, or removing the loaded one
+fn overlay_ui(ui: &mut egui::Ui, trace: &mut Trace, error: &mut Option<String>) {
+    if trace.overlay.is_some() {
+        if ui
+            .button("✖ overlay")
+            .on_hover_text("remove the overlay")
+            .clicked()
+        {
+            trace.overlay = None;
+        }
+        return;
+    }
+    let load = ui
+        .button("overlay…")
+        .on_hover_text("overlay the fields of a Kaitai Struct .ksy file");
+    if !load.clicked() {
+        return;
+    }
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter("kaitai struct", &["ksy"])
+        .pick_file()
+    else {
+        return;
+    };
+    if let Err(err) = trace.load_overlay(&path) {
+        *error = Some(format!("{err:#}"));
+    }
+}
+
+/// Button loading the original file `trace` was captured from to compare against, or removing
+/// the loaded one
+fn original_ui(ui: &mut egui::Ui, trace: &mut Trace, error: &mut Option<String>) {
+    if trace.original.is_some() {
+        if ui
+            .button("✖ original")
+            .on_hover_text("stop comparing with the original file")
+            .clicked()
+        {
+            trace.original = None;
+        }
+        return;
+    }
+    let load = ui
+        .button("original…")
+        .on_hover_text("compare the trace data with the file it was captured from");
+    if !load.clicked() {
+        return;
+    }
+    let Some(path) = rfd::FileDialog::new().pick_file() else {
+        return;
+    };
+    if let Err(err) = trace.load_original(&path) {
+        *error = Some(format!("{err:#}"));
+    }
+}
+
+/// Ask for a trace to open
+fn pick_trace() -> Option<PathBuf> {
+    rfd::FileDialog::new()
+        .add_filter("trace", &["json"])
+        .pick_file()
+}
+
+/// Tree path of the span containing `address`
+fn span_path(interval_tree: &IntervalTree<usize, FlatSpan>, address: usize) -> Option<Vec<usize>> {
+    // TODO find "narrowest" span in case of multiple
+    let span = interval_tree.query(address..address + 1).next()?;
+    let mut path = vec![0];
+    path.extend(&span.value.path);
+    Some(path)
+}
+
+impl SparseTreeActionTrait for SparseTreeAction {
     fn build_full_actions(&self, index: &mut usize) -> FullAction {
         match self {
-            Action::Read(size) => {
+            Action::Read(_) | Action::ReadData(_) => {
                 let start = *index;
-                *index += size;
+                *index += self.read_size().unwrap();
                 FullAction::Read(start..*index)
             }
-            Action::Seek(i) => {
+            Action::Seek(seek) => {
                 let start = *index;
-                *index = *i;
-                FullAction::Seek(start, *index)
+                *index = seek.to;
+                FullAction::Seek(start, *seek)
             }
-            Action::Span(span) => FullAction::Span(FullTreeSpan {
-                name: span.0.name.to_string(),
-                actions: span
+            Action::Error(error) => FullAction::Error(*index, error.clone()),
+            Action::SubTrace(sub) => FullAction::SubTrace(sub.clone()),
+            Action::Span(span) => {
+                let actions: Vec<_> = span
                     .0
                     .actions
                     .iter()
-                    .map(|s| s.build_full_actions(index))
-                    .collect(),
-            }),
+                    .enumerate()
+                    .map(|(i, s)| {
+                        let mut action = s.build_full_actions(index);
+                        if let (FullAction::Span(child), Some(timing)) =
+                            (&mut action, span.0.timings.get(i))
+                        {
+                            child.name += &format!(" [{:?}]", timing.duration());
+                        }
+                        action
+                    })
+                    .collect();
+                FullAction::Span(FullTreeSpan {
+                    name: match &span.0.value {
+                        Some(value) => {
+                            format!("{} = {} ({})", span.0.name, value.value, value.type_name)
+                        }
+                        None => span.0.name.to_string(),
+                    },
+                    location: span.0.location.as_ref().map(|l| l.to_string()),
+                    range: actions
+                        .iter()
+                        .filter_map(FullAction::read_range)
+                        .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end)),
+                    diverged: false,
+                    matched: false,
+                    hidden: false,
+                    hide_leaves: false,
+                    actions,
+                })
+            }
         }
     }
 }
 impl FullAction {
-    fn ui(
-        &self,
-        ui: &mut egui::Ui,
-        index: usize,
-        path_select: Option<&[usize]>,
-    ) -> Option<TreeResponse> {
-        let mut res = None;
-
+    /// Bytes read by the action, spanning all reads of a span
+    fn read_range(&self) -> Option<Range<usize>> {
         match self {
-            FullAction::Read(range) => {
-                let scroll_to_me = path_select
-                    .and_then(|p| {
-                        p.split_first().and_then(|(first, rest)| {
-                            (*first == index && rest.is_empty()).then_some(true)
-                        })
-                    })
-                    .unwrap_or_default();
-                let button_res = ui.button(format!("read {}", range.len()));
-                if scroll_to_me {
-                    button_res.scroll_to_me(None);
-                }
-                if button_res.clicked() {
-                    res = Some(TreeResponse::Goto(range.start));
+            FullAction::Read(range) => Some(range.clone()),
+            FullAction::Span(span) => span.range.clone(),
+            _ => None,
+        }
+    }
+    /// Mark the spans leading to the action at `path` as diverged
+    fn mark_diverged(&mut self, path: &[usize]) {
+        if let FullAction::Span(span) = self {
+            span.diverged = true;
+            if let Some((i, rest)) = path.split_first() {
+                if let Some(child) = span.actions.get_mut(*i) {
+                    child.mark_diverged(rest);
                 }
             }
-            FullAction::Seek(from, to) => {
-                ui.label(format!("seek {} => {}", from, to));
-            }
-            FullAction::Span(span) => {
-                ui.push_id(index, |ui| {
-                    egui::CollapsingHeader::new(span.name.as_str())
-                        .open(path_select.map(|p| p.first() == Some(&index)))
-                        .show(ui, |ui| {
-                            let mut ui_action =
-                                |ui: &mut egui::Ui,
-                                 index: usize,
-                                 action: &FullAction,
-                                 path_select: Option<&[usize]>| {
-                                    if let Some(r) = action.ui(ui, index, path_select) {
-                                        res = Some(r);
-                                    }
-                                };
-                            let n = 100;
-                            let path_select = path_select.and_then(|p| {
-                                p.split_first()
-                                    .and_then(|(first, rest)| (*first == index).then_some(rest))
-                            });
-                            for (i, chunk) in span.actions.chunks(n).enumerate() {
-                                let base_index = n * i;
-                                if span.actions.len() > n {
-                                    egui::CollapsingHeader::new(format!(
-                                        "{}-{}:",
-                                        base_index,
-                                        base_index + chunk.len()
-                                    ))
-                                    .open(path_select.map(|p| {
-                                        p.first()
-                                            .map(|p| (base_index..base_index + n).contains(p))
-                                            .unwrap_or_default()
-                                    }))
-                                    .show(ui, |ui| {
-                                        for (ci, action) in chunk.iter().enumerate() {
-                                            ui_action(ui, base_index + ci, action, path_select);
-                                        }
-                                    });
-                                } else {
-                                    for (ci, action) in chunk.iter().enumerate() {
-                                        ui_action(ui, base_index + ci, action, path_select);
-                                    }
-                                }
-                            }
-                        });
-                });
-            }
         }
+    }
+    /// Apply the lowercase tree filter `query`, collecting the tree paths of matching spans.
+    /// Returns whether the action is shown
+    fn filter(
+        &mut self,
+        query: &str,
+        path: &mut Vec<usize>,
+        matches: &mut Vec<Vec<usize>>,
+    ) -> bool {
+        let FullAction::Span(span) = self else {
+            return false;
+        };
+        span.matched = !query.is_empty() && span.name.to_lowercase().contains(query);
+        span.hide_leaves = !query.is_empty() && !span.matched;
+        if span.matched {
+            matches.push(path.clone());
+        }
+        let mut shown = !span.hide_leaves;
+        for (i, action) in span.actions.iter_mut().enumerate() {
+            path.push(i);
+            shown |= action.filter(query, path, matches);
+            path.pop();
+        }
+        span.hidden = !shown;
+        shown
+    }
+}
+
+fn seek_label(from: usize, seek: &ser_hex::SeekAction) -> String {
+    match seek.from {
+        Some(ser_hex::SeekOrigin::Current(delta)) => {
+            format!("skip {delta:+} ({from} => {})", seek.to)
+        }
+        Some(ser_hex::SeekOrigin::End(delta)) => {
+            format!("seek end{delta:+} ({from} => {})", seek.to)
+        }
+        Some(ser_hex::SeekOrigin::Start(_)) | None => format!("seek {from} => {}", seek.to),
+    }
+}
 
-        res
+fn sub_trace_label(sub: &ser_hex::SubTrace) -> String {
+    match sub {
+        ser_hex::SubTrace::File(path) => format!("sub-trace {}", path.display()),
+        ser_hex::SubTrace::Inline(trace) => format!("sub-trace ({} bytes)", trace.data.len()),
     }
 }
 
 #[derive(Debug, Clone)]
 enum TreeResponse {
-    Goto(usize),
+    Goto(Range<usize>),
+    /// Save the bytes of the range to a file
+    Export(Range<usize>),
+    /// Write a note on the range, of the named span if any
+    Note(Range<usize>, Option<String>),
+    /// Show the jump of a seek
+    Seek {
+        from: usize,
+        to: usize,
+    },
+    OpenSubTrace(ser_hex::SubTrace),
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -213,6 +476,10 @@ struct FlatSpan {
     range: Range<usize>,
     name: String,
     path: Vec<usize>,
+    /// Number of spans the read is nested in
+    depth: usize,
+    /// Name of the outermost span containing the read
+    ancestor: String,
 }
 impl RangeBounds<usize> for FlatSpan {
     fn start_bound(&self) -> std::ops::Bound<&usize> {
@@ -226,45 +493,418 @@ impl RangeBounds<usize> for FlatSpan {
 #[derive(Debug)]
 pub enum FullAction {
     Read(Range<usize>),
-    Seek(usize, usize), // from, to
+    Seek(usize, ser_hex::SeekAction), // from, seek
+    Error(usize, ser_hex::ReadError),
+    SubTrace(ser_hex::SubTrace),
     Span(FullTreeSpan),
 }
 
 #[derive(Debug)]
 pub struct FullTreeSpan {
     pub name: String,
+    /// Source location shown when hovering the span
+    pub location: Option<String>,
+    /// Bytes from the start of the first read to the end of the last read within the span
+    pub range: Option<Range<usize>>,
+    /// Contains reads which differ from the trace it is compared to
+    pub diverged: bool,
+    /// Name matches the tree filter
+    pub matched: bool,
+    /// Hidden by the tree filter as neither the span nor any descendant matches
+    pub hidden: bool,
+    /// Hide the non-span actions of the span as it is not itself a match of the tree filter
+    pub hide_leaves: bool,
     pub actions: Vec<FullAction>,
 }
 
 pub struct Trace {
-    data: Vec<u8>,
+    title: String,
+    /// Directory sub-trace file references are resolved against
+    base_dir: PathBuf,
+    /// Memory mapped for traces with a data sidecar
+    data: Box<dyn AsRef<[u8]> + Send>,
+    metadata: ser_hex::TraceMetadata,
+    problems: Vec<ser_hex::Problem>,
     full_tree: FullAction,
     interval_tree: IntervalTree<usize, FlatSpan>,
+    legend: Legend,
+    /// Ranges of the data not covered by any read
+    gaps: Vec<Range<usize>>,
+    /// Destination offsets of all seeks
+    seek_targets: Vec<usize>,
+    /// Source and destination of the seek shown
+    seek: Option<(usize, usize)>,
     mem_editor: MemoryEditor,
+    search: Search,
+    strings: Strings,
+    occurrences: Occurrences,
+    selection: Option<Selection>,
+    bookmarks: Bookmarks,
+    notes: Notes,
+    /// Fields of a reference grammar of the format
+    overlay: Option<Overlay>,
+    /// File the trace was captured from
+    original: Option<Original>,
+    /// Number of bytes the hex view shows, more than the trace data when filled in from the
+    /// original
+    view_len: usize,
+    tree_filter: TreeFilter,
+    tree_view: TreeView,
+    history: History,
 }
 impl Trace {
-    fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let file = fs::File::open(path.as_ref())?;
-        let reader = std::io::BufReader::new(file);
-
-        let trace: ser_hex::Trace = serde_json::from_reader(reader)?;
-        let root = trace.root;
-
-        let interval_tree = root.build_tree();
-        let full_tree = root.build_full_actions(&mut 0);
-
-        let mut mem_editor = MemoryEditor::new()
-            .with_address_range("All", 0..trace.data.len())
-            .with_window_title(path.as_ref().to_string_lossy());
+    fn load<P: AsRef<Path>>(path: P, progress: &Progress) -> Result<Self> {
+        let path = path.as_ref();
+        progress.set(0., "parsing");
+        Self::from_file(ser_hex::Trace::open_mmap(path)?, path, progress)
+    }
+    fn from_file<D: AsRef<[u8]> + Send + 'static>(
+        trace: ser_hex::Trace<D>,
+        path: &Path,
+        progress: &Progress,
+    ) -> Result<Self> {
+        let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        progress.set(0.6, "indexing");
+        let trace = Self::new(trace, path.to_string_lossy().into(), base_dir);
+        progress.set(0.95, "loading bookmarks");
+        Ok(Self {
+            bookmarks: Bookmarks::load(path).context("Failed to load bookmarks")?,
+            notes: Notes::load(path).context("Failed to load notes")?,
+            ..trace
+        })
+    }
+    /// Overlay the fields of the Kaitai Struct file at `path`
+    fn load_overlay(&mut self, path: &Path) -> Result<()> {
+        let mut overlay = Overlay::load(path, (*self.data).as_ref())?;
+        let tree = &self.interval_tree;
+        overlay.compare(|range| tree.query(range.clone()).any(|e| e.range == *range));
+        self.overlay = Some(overlay);
+        Ok(())
+    }
+    fn load_original(&mut self, path: &Path) -> Result<()> {
+        let data = (*self.data).as_ref();
+        self.original = Some(Original::load(path, data, &self.metadata.redacted)?);
+        Ok(())
+    }
+    fn load_sub_trace(&self, sub: &ser_hex::SubTrace) -> Result<Self> {
+        match sub {
+            ser_hex::SubTrace::File(path) => {
+                Self::load(self.base_dir.join(path), &Progress::default())
+            }
+            ser_hex::SubTrace::Inline(trace) => Ok(Self::new(
+                (**trace).clone(),
+                format!("{} (inline)", self.title),
+                self.base_dir.clone(),
+            )),
+        }
+    }
+    fn new<D: AsRef<[u8]> + Send + 'static>(
+        trace: ser_hex::Trace<D>,
+        title: String,
+        base_dir: PathBuf,
+    ) -> Self {
+        let spans = flat_spans(&trace);
+        let legend = Legend::new(&spans);
+        let interval_tree = build_tree(spans);
+        let gaps = unread_gaps(&trace);
+        let seek_targets = seek_targets(&trace);
+        let full_tree = trace.root.build_full_actions(&mut { trace.start_index });
 
-        mem_editor.options.column_count = 16;
+        let view_len = trace.data.as_ref().len();
+        let mem_editor = mem_editor(&title, view_len, 16);
 
-        Ok(Trace {
-            data: trace.data,
+        Trace {
+            title,
+            base_dir,
+            problems: trace.validate(),
+            data: Box::new(trace.data),
+            metadata: trace.metadata,
             full_tree,
             interval_tree,
+            legend,
+            gaps,
+            seek_targets,
+            seek: None,
             mem_editor,
-        })
+            search: Search::default(),
+            strings: Strings::default(),
+            occurrences: Occurrences::default(),
+            selection: None,
+            bookmarks: Bookmarks::default(),
+            notes: Notes::default(),
+            overlay: None,
+            original: None,
+            view_len,
+            tree_filter: TreeFilter::default(),
+            tree_view: TreeView::default(),
+            history: History::default(),
+        }
+    }
+    /// Ranges highlighted in the hex view on top of the spans
+    fn highlights(&self) -> Vec<(Range<usize>, egui::Color32)> {
+        let mut highlights: Vec<_> = self
+            .bookmarks
+            .list()
+            .iter()
+            .map(|b| (b.range.clone(), b.color()))
+            .collect();
+        if let Some(range) = self.search.current() {
+            highlights.push((range, egui::Color32::YELLOW));
+        }
+        if let Some(range) = self.selection.map(|s| s.range()).filter(|r| r.len() > 1) {
+            highlights.push((range, egui::Color32::LIGHT_BLUE));
+        }
+        if let Some((from, to)) = self.seek {
+            highlights.push((from..from + 1, SEEK_COLOR));
+            highlights.push((to..to + 1, SEEK_COLOR));
+        }
+        highlights
+    }
+    /// Carry the selection, open spans, overlay and navigation history of `old`, the trace
+    /// before it was reloaded, over where the structure still matches
+    fn restore(&mut self, old: Trace) {
+        let old_row = old.tree_view.selected_range(&old.full_tree);
+        self.tree_view
+            .restore(old.tree_view, &old.full_tree, &self.full_tree);
+        let new_row = self.tree_view.selected_range(&self.full_tree);
+        if let Some(range) = old.selection.map(|s| s.range()) {
+            // the selection moves with the row it is in, if the row has moved
+            let start = match (old_row, new_row) {
+                (Some(old_row), Some(new_row)) if old_row.contains(&range.start) => {
+                    new_row.start + (range.start - old_row.start)
+                }
+                _ => range.start,
+            };
+            let range = start..start + range.len();
+            if range.end <= (*self.data).as_ref().len() {
+                self.selection = Some(Selection::goto(&mut self.mem_editor, range));
+            }
+        }
+        if let Some(overlay) = old.overlay {
+            if let Err(err) = self.load_overlay(&overlay.path) {
+                eprintln!("failed to reload overlay {err:?}");
+            }
+        }
+        if let Some(original) = old.original {
+            match self.load_original(&original.path) {
+                Ok(()) => self.original.as_mut().unwrap().fill = original.fill,
+                Err(err) => eprintln!("failed to reload original {err:?}"),
+            }
+        }
+        self.history = old.history;
+    }
+    /// Tree path and range of the span enclosing the read at the start of the selection
+    fn enclosing_span(&self) -> Option<(Vec<usize>, Range<usize>)> {
+        let start = self.selection?.range().start;
+        let mut path = span_path(&self.interval_tree, start)?;
+        path.pop();
+        let range = tree_view::span_range(&self.full_tree, &path)?;
+        Some((path, range))
+    }
+    /// Start of the span enclosing the selection, which relative offsets are shown from
+    fn origin(&self) -> Option<usize> {
+        self.enclosing_span().map(|(_, range)| range.start)
+    }
+    /// Draw the hex view with `extra` ranges highlighted, returns the clicked address if the
+    /// selection changed
+    fn hex_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        colors: &Colors,
+        offsets: Offsets,
+        extra: Vec<(Range<usize>, egui::Color32)>,
+    ) -> Option<usize> {
+        let mut highlights = self.highlights();
+        highlights.extend(extra);
+        let origin = self.origin();
+
+        let view_len = match &self.original {
+            Some(original) => original.view_len(),
+            None => (*self.data).as_ref().len(),
+        };
+        if view_len != self.view_len {
+            let columns = self.mem_editor.options.column_count;
+            self.mem_editor = mem_editor(&self.title, view_len, columns);
+            self.view_len = view_len;
+            self.selection = self
+                .selection
+                .map(|s| s.range())
+                .filter(|r| r.end <= view_len)
+                .map(|r| Selection::goto(&mut self.mem_editor, r));
+        }
+
+        let interval_tree = &self.interval_tree;
+        let full_tree = &self.full_tree;
+        let redacted = &self.metadata.redacted;
+        let data: &[u8] = (*self.data).as_ref();
+        let bookmarks = self.bookmarks.list();
+        let notes = &self.notes;
+        let overlay = &self.overlay;
+        let original = &self.original;
+        let is_redacted = |address| redacted.iter().any(|r: &Range<usize>| r.contains(&address));
+        let from_original = |address| original.as_ref()?.byte(address, is_redacted(address));
+
+        let span_query = Box::new(SpanQueryImpl {
+            tree: interval_tree,
+            colors,
+            highlights,
+        });
+        let hover_byte = Box::new(|ui: &mut egui::Ui, address| {
+            if is_redacted(address) {
+                ui.label(egui::RichText::new("redacted").italics());
+            }
+            if from_original(address).is_some() {
+                ui.label(egui::RichText::new("from the original file").italics());
+            } else if let Some(b) = original.as_ref().and_then(|o| o.mismatch(address)) {
+                ui.label(format!("original: {b:02x}"));
+            }
+            for bookmark in bookmarks.iter().filter(|b| b.range.contains(&address)) {
+                ui.colored_label(bookmark.color(), format!("bookmark: {}", bookmark.label));
+            }
+            for note in notes.at(address) {
+                ui.label(format!("note: {}", note.text));
+            }
+            for field in overlay.iter().flat_map(|o| o.at(address)) {
+                ui.label(format!("overlay: {}", field.name));
+            }
+            for range in interval_tree.query_point(address) {
+                let offset = offsets.format(address, origin);
+                ui.label(format!("{offset}: {}", range.value.name));
+                let mut span = full_tree;
+
+                //ui.label(format!("{}, span: {}", 0, span.name));
+                for (depth, span_index) in range.value.path.iter().enumerate() {
+                    match span {
+                        FullAction::Read(range) => {
+                            ui.label(format!("{}, read: {}", depth + 1, range.len()));
+                        }
+                        FullAction::Seek(from, seek) => {
+                            ui.label(format!("{}, {}", depth + 1, seek_label(*from, seek)));
+                        }
+                        FullAction::Error(_, error) => {
+                            ui.label(format!("{}, error: {}", depth + 1, error.kind));
+                        }
+                        FullAction::SubTrace(sub) => {
+                            ui.label(format!("{}, {}", depth + 1, sub_trace_label(sub)));
+                        }
+                        FullAction::Span(s) => {
+                            span = &s.actions[*span_index];
+                            ui.label(format!("{}, span: {}", depth + 1, s.name));
+                        }
+                    }
+                }
+            }
+        });
+        let color_byte = Box::new(|address| {
+            if from_original(address).is_some() {
+                original::ORIGINAL_COLOR
+            } else if is_redacted(address) {
+                egui::Color32::DARK_GRAY
+            } else if let Some(color) = colors.byte(data, address) {
+                color
+            } else if let Some(first) = interval_tree.query_point(address).next() {
+                colors.text(&first.value)
+            } else {
+                egui::Color32::BROWN
+            }
+        });
+
+        let prev_selection = self.mem_editor.frame_data.selected_highlight_address;
+        self.mem_editor.draw_editor_contents_read_only(
+            ui,
+            &mut { data },
+            // addresses past the trace data are only shown when filled in from the original
+            |data, address| {
+                from_original(address)
+                    .or(data.get(address).copied())
+                    .unwrap_or_default()
+                    .into()
+            },
+            RenderCtx {
+                span_query,
+                hover_byte,
+                color_byte,
+            },
+        );
+        let new_selection = self.mem_editor.frame_data.selected_highlight_address;
+        if prev_selection == new_selection {
+            return None;
+        }
+        // panels are drawn before the selection changes
+        ui.ctx().request_repaint();
+        self.selection = new_selection.map(|address| match self.selection {
+            Some(selection) if ui.input(|i| i.modifiers.shift) => Selection {
+                end: address,
+                ..selection
+            },
+            _ => Selection {
+                anchor: address,
+                end: address,
+            },
+        });
+        new_selection
+    }
+}
+
+/// Hex view of `len` bytes
+fn mem_editor(title: &str, len: usize, columns: usize) -> MemoryEditor {
+    let mut mem_editor = MemoryEditor::new()
+        .with_address_range("All", 0..len)
+        .with_window_title(title);
+    mem_editor.options.column_count = columns;
+    mem_editor
+}
+
+/// Byte range selected in the hex view, extended from `anchor` by shift-clicking
+#[derive(Debug, Clone, Copy)]
+struct Selection {
+    anchor: usize,
+    end: usize,
+}
+impl Selection {
+    /// Select `range` and scroll the hex view to it
+    fn goto(mem_editor: &mut MemoryEditor, range: Range<usize>) -> Self {
+        mem_editor.frame_data.set_highlight_address(range.start);
+        mem_editor.frame_data.goto_address_line =
+            Some(range.start / mem_editor.options.column_count);
+        Self {
+            anchor: range.start,
+            end: range.end.max(range.start + 1) - 1,
+        }
+    }
+    fn range(&self) -> Range<usize> {
+        self.anchor.min(self.end)..self.anchor.max(self.end) + 1
+    }
+}
+
+struct SpanQueryImpl<'tree> {
+    tree: &'tree IntervalTree<usize, FlatSpan>,
+    colors: &'tree Colors,
+    /// Bookmarks, search match, selection and any other ranges drawn over the spans
+    highlights: Vec<(Range<usize>, egui::Color32)>,
+}
+impl SpanQuery for SpanQueryImpl<'_> {
+    fn get_spans<'a>(
+        &'a self,
+        range: Range<egui_memory_editor::Address>,
+    ) -> Box<dyn Iterator<Item = egui_memory_editor::Span> + 'a> {
+        let highlights = self
+            .highlights
+            .iter()
+            .filter(|(h, _)| h.start < range.end && range.start < h.end)
+            .map(|(range, color)| egui_memory_editor::Span {
+                range: range.clone(),
+                color: *color,
+            });
+        Box::new(
+            self.tree
+                .query(range)
+                .map(|r| egui_memory_editor::Span {
+                    range: r.range.clone(),
+                    color: self.colors.span(&r.value),
+                })
+                .chain(highlights),
+        )
     }
 }
 
@@ -273,32 +913,259 @@ struct FileTrace {
     trace: Trace,
 }
 impl FileTrace {
-    fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+    fn load(path: PathBuf, progress: &Progress) -> Result<Self> {
         Ok(Self {
-            trace: Trace::load(&path)?,
-            path: fs::canonicalize(path)?,
+            trace: Trace::load(&path, progress)?,
+            path,
         })
     }
-    fn reload(&mut self) -> Result<()> {
-        self.trace = Trace::load(&self.path)?;
-        Ok(())
-    }
 }
 
-pub struct App {
+/// Trace opened in a tab along with its navigation state
+struct Tab {
     trace: FileTrace,
+    /// Stack of sub-traces descended into from the file trace, the last is displayed
+    sub_traces: Vec<Trace>,
     path_select: Option<Vec<usize>>,
+}
+impl Tab {
+    fn new(trace: FileTrace) -> Self {
+        Self {
+            trace,
+            sub_traces: vec![],
+            path_select: None,
+        }
+    }
+    fn name(&self) -> String {
+        let path = &self.trace.path;
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        name.to_string_lossy().into()
+    }
+}
+
+pub struct App {
+    tabs: Vec<Tab>,
+    /// Index of the displayed tab
+    current: usize,
+    /// Byte order values are interpreted with
+    big_endian: bool,
+    /// Highlight bytes not covered by any read
+    show_coverage: bool,
+    /// Highlight the destinations of all seeks
+    show_seeks: bool,
+    /// Columns of the hex view, `None` to fit the available width
+    columns: Option<usize>,
+    /// Depth the tree is expanded to with the expand to depth button
+    expand_depth: usize,
+    /// Added to the offsets of pointers followed from the inspector
+    pointer_base: usize,
+    offsets: Offsets,
+    palette: Palette,
+    hex_menu: HexMenu,
+    colors: Colors,
+    recent: Recent,
+    /// Traces being opened or reloaded
+    loading: Vec<Loading>,
+    /// Failure to open a trace, shown until dismissed
+    error: Option<String>,
     watcher: Option<Debouncer<RecommendedWatcher>>,
     rx: Option<std::sync::mpsc::Receiver<PathBuf>>,
 }
 impl App {
-    fn new(trace: FileTrace) -> Result<Self> {
-        Ok(Self {
-            trace,
-            path_select: None,
+    fn new() -> Self {
+        Self {
+            tabs: vec![],
+            current: 0,
+            big_endian: false,
+            show_coverage: false,
+            show_seeks: false,
+            columns: Some(16),
+            expand_depth: 2,
+            pointer_base: 0,
+            offsets: Offsets::default(),
+            palette: Palette::default(),
+            hex_menu: HexMenu::default(),
+            colors: Colors::load(),
+            recent: Recent::load(),
+            loading: vec![],
+            error: None,
             watcher: None,
             rx: None,
-        })
+        }
+    }
+    /// Start loading a trace to open in a new tab, or switch to its tab if already open
+    fn open(&mut self, path: &Path) {
+        let path = match fs::canonicalize(path) {
+            Ok(path) => path,
+            Err(err) => {
+                self.error = Some(format!("failed to open {}: {err:#}", path.display()));
+                return;
+            }
+        };
+        if let Some(i) = self.tabs.iter().position(|t| t.trace.path == path) {
+            self.current = i;
+            return;
+        }
+        if !self.loading.iter().any(|l| l.path == path && !l.reload) {
+            self.loading.push(Loading::spawn(path, false));
+        }
+    }
+    /// Open or reload the traces done loading
+    fn finish_loading(&mut self) {
+        let mut done = vec![];
+        self.loading.retain(|loading| match loading.poll() {
+            Some(result) => {
+                done.push((loading.path.clone(), loading.reload, result));
+                false
+            }
+            None => true,
+        });
+        for (path, reload, result) in done {
+            let trace = match result {
+                Ok(trace) => trace,
+                Err(err) => {
+                    let action = if reload { "reload" } else { "open" };
+                    self.error = Some(format!("failed to {action} {}: {err:#}", path.display()));
+                    continue;
+                }
+            };
+            if reload {
+                // the tab may have been closed while reloading
+                if let Some(tab) = self.tabs.iter_mut().find(|t| t.trace.path == path) {
+                    println!("reloaded {path:?}");
+                    let old = std::mem::replace(&mut tab.trace, trace);
+                    tab.trace.trace.restore(old.trace);
+                    // sub-traces may no longer match the reloaded trace
+                    tab.sub_traces.clear();
+                }
+                continue;
+            }
+            if let Some(watcher) = &mut self.watcher {
+                let mode = notify::RecursiveMode::NonRecursive;
+                if let Err(err) = watcher.watcher().watch(&trace.path, mode) {
+                    eprintln!("failed to watch trace {err}");
+                }
+            }
+            self.recent.add(&trace.path);
+            self.tabs.push(Tab::new(trace));
+            self.current = self.tabs.len() - 1;
+        }
+    }
+    /// Run `command` if it applies to the whole viewer, otherwise return it to run on the
+    /// displayed trace
+    fn run(&mut self, command: Command) -> Option<Command> {
+        match command {
+            Command::Open => {
+                if let Some(path) = pick_trace() {
+                    self.open(&path);
+                }
+            }
+            Command::CloseTab => {
+                if !self.tabs.is_empty() {
+                    self.close(self.current);
+                }
+            }
+            Command::ToggleCoverage => self.show_coverage ^= true,
+            Command::ToggleSeeks => self.show_seeks ^= true,
+            Command::ToggleEndian => self.big_endian ^= true,
+            Command::ToggleHexOffsets => self.offsets.hex ^= true,
+            Command::ToggleRelativeOffsets => self.offsets.relative ^= true,
+            Command::FitColumns => {
+                self.columns = match self.columns {
+                    Some(_) => None,
+                    None => Some(16),
+                }
+            }
+            Command::SpanColors => self.colors.open = true,
+            command => return Some(command),
+        }
+        None
+    }
+    fn close(&mut self, index: usize) {
+        let tab = self.tabs.remove(index);
+        let still_open = self.tabs.iter().any(|t| t.trace.path == tab.trace.path);
+        if let (Some(watcher), false) = (&mut self.watcher, still_open) {
+            let _ = watcher.watcher().unwatch(&tab.trace.path);
+        }
+        if self.current >= index && self.current > 0 {
+            self.current -= 1;
+        }
+    }
+    /// Open file and recent traces buttons, returns the trace to open
+    fn open_ui(recent: &Recent, ui: &mut egui::Ui) -> Option<PathBuf> {
+        let mut open = None;
+        if ui.button("open… (ctrl+o)").clicked() {
+            ui.close_menu();
+            open = pick_trace();
+        }
+        ui.separator();
+        if recent.paths().is_empty() {
+            ui.weak("no recent traces");
+        }
+        for path in recent.paths() {
+            if ui.button(path.display().to_string()).clicked() {
+                ui.close_menu();
+                open = Some(path.clone());
+            }
+        }
+        open
+    }
+    fn tabs_ui(&mut self, ui: &mut egui::Ui) {
+        let mut close = None;
+        let mut open = None;
+        let mut dismiss = false;
+        egui::menu::bar(ui, |ui| {
+            ui.menu_button("File", |ui| open = Self::open_ui(&self.recent, ui));
+            ui.menu_button("View", |ui| {
+                if ui.button("command palette… (ctrl+p)").clicked() {
+                    self.palette.toggle();
+                    ui.close_menu();
+                }
+                if ui.button("span colors…").clicked() {
+                    self.colors.open = true;
+                    ui.close_menu();
+                }
+            });
+            ui.separator();
+            for (i, tab) in self.tabs.iter().enumerate() {
+                ui.selectable_value(&mut self.current, i, tab.name())
+                    .on_hover_text(tab.trace.path.display().to_string());
+                if ui.small_button("x").on_hover_text("close").clicked() {
+                    close = Some(i);
+                }
+                ui.separator();
+            }
+            ui.weak("drop traces here to open them");
+            for loading in &self.loading {
+                loading.ui(ui);
+            }
+            if let Some(error) = &self.error {
+                ui.colored_label(egui::Color32::RED, error);
+                if ui.small_button("x").on_hover_text("dismiss").clicked() {
+                    dismiss = true;
+                }
+            }
+        });
+        if dismiss {
+            self.error = None;
+        }
+        if let Some(i) = close {
+            self.close(i);
+        }
+        if let Some(path) = open {
+            self.open(&path);
+        }
+    }
+    /// Shown while no trace is open
+    fn start_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Ser-Hex viewer");
+        ui.label("open a trace or drop one here");
+        for loading in &self.loading {
+            loading.ui(ui);
+        }
+        if let Some(path) = Self::open_ui(&self.recent, ui) {
+            self.open(&path);
+        }
     }
 }
 
@@ -306,9 +1173,12 @@ impl eframe::App for App {
     fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
         if let Some(rx) = &self.rx {
             for path in rx.try_iter() {
-                println!("reloading {path:?}");
-                if let Err(err) = self.trace.reload() {
-                    eprintln!("failed to reload trace {err:?}")
+                let open = self.tabs.iter().any(|t| t.trace.path == path);
+                // a reload already running may have read the file before it changed
+                self.loading.retain(|l| !(l.reload && l.path == path));
+                if open {
+                    println!("reloading {path:?}");
+                    self.loading.push(Loading::spawn(path, true));
                 }
             }
         } else {
@@ -334,92 +1204,237 @@ impl eframe::App for App {
                 },
             )
             .unwrap();
-            watcher
-                .watcher()
-                .watch(&self.trace.path, notify::RecursiveMode::NonRecursive)
-                .unwrap();
+            for tab in &self.tabs {
+                watcher
+                    .watcher()
+                    .watch(&tab.trace.path, notify::RecursiveMode::NonRecursive)
+                    .unwrap();
+            }
             self.watcher = Some(watcher);
         }
+        self.finish_loading();
 
-        struct SpanQueryImpl<'tree> {
-            tree: &'tree IntervalTree<usize, FlatSpan>,
+        let dropped: Vec<_> = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|f| f.path.clone())
+                .collect()
+        });
+        for path in dropped {
+            self.open(&path);
         }
-        impl SpanQuery for SpanQueryImpl<'_> {
-            fn get_spans<'a>(
-                &'a self,
-                range: Range<egui_memory_editor::Address>,
-            ) -> Box<dyn Iterator<Item = egui_memory_editor::Span> + 'a> {
-                Box::new(self.tree.query(range).map(|r| {
-                    use std::hash::Hash;
-                    use std::hash::Hasher;
-                    let mut s = DefaultHasher::new();
-                    r.value.name.hash(&mut s);
-
-                    let hash = s.finish();
-
-                    let color = Hsva::new((hash % 256) as f32 / 256.0, 1., 0.5, 1.);
-
-                    egui_memory_editor::Span {
-                        range: r.range.clone(),
-                        color: color.into(),
-                    }
-                }))
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::O)) {
+            if let Some(path) = pick_trace() {
+                self.open(&path);
             }
         }
+        let command = self.palette.ui(ctx).and_then(|command| self.run(command));
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            let layer = egui::LayerId::new(egui::Order::Foreground, egui::Id::new("drop"));
+            let painter = ctx.layer_painter(layer);
+            let rect = ctx.screen_rect();
+            painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(192));
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "drop to open",
+                egui::TextStyle::Heading.resolve(&ctx.style()),
+                egui::Color32::WHITE,
+            );
+        }
+        egui::TopBottomPanel::top("tabs").show(ctx, |ui| self.tabs_ui(ui));
+        self.colors.window(ctx);
+        if self.tabs.is_empty() {
+            egui::CentralPanel::default().show(ctx, |ui| self.start_ui(ui));
+            return;
+        }
 
-        let interval_tree = &self.trace.trace.interval_tree;
-        let full_tree = &self.trace.trace.full_tree;
-
-        let span_query = Box::new(SpanQueryImpl {
-            tree: interval_tree,
-        });
-        let hover_byte = Box::new(|ui: &mut egui::Ui, address| {
-            for range in interval_tree.query_point(address) {
-                ui.label(format!("{address}: {}", range.value.name));
-                let mut span = full_tree;
+        let tab = &mut self.tabs[self.current];
+        let is_sub_trace = !tab.sub_traces.is_empty();
+        let trace = tab.sub_traces.last_mut().unwrap_or(&mut tab.trace.trace);
 
-                //ui.label(format!("{}, span: {}", 0, span.name));
-                for (depth, span_index) in range.value.path.iter().enumerate() {
-                    match span {
-                        FullAction::Read(range) => {
-                            ui.label(format!("{}, read: {}", depth + 1, range.len()));
-                        }
-                        FullAction::Seek(from, to) => {
-                            ui.label(format!("{}, seek: {} => {}", depth + 1, from, to));
-                        }
-                        FullAction::Span(s) => {
-                            span = &s.actions[*span_index];
-                            ui.label(format!("{}, span: {}", depth + 1, s.name));
-                        }
-                    }
+        let mut tree_res = None;
+        let mut palette_expand = None;
+        let goto = match command {
+            Some(Command::Goto(offset)) => match offset < (*trace.data).as_ref().len() {
+                true => Some(offset..offset + 1),
+                false => {
+                    self.error = Some(format!("offset {offset:#x} is past the end of the data"));
+                    None
                 }
+            },
+            Some(Command::Back) => trace.history.go(false),
+            Some(Command::Forward) => trace.history.go(true),
+            Some(Command::Search) => {
+                trace.search.focus();
+                None
             }
-        });
-        let color_byte = Box::new(|address| {
-            if let Some(first) = interval_tree.query_point(address).next() {
-                use std::hash::Hash;
-                use std::hash::Hasher;
-                let mut s = DefaultHasher::new();
-                first.value.name.hash(&mut s);
-
-                let hash = s.finish();
+            Some(Command::ExpandAll) => {
+                palette_expand = Some(usize::MAX);
+                None
+            }
+            Some(Command::CollapseAll) => {
+                palette_expand = Some(0);
+                None
+            }
+            _ => None,
+        };
+        if let Some(range) = goto {
+            tab.path_select = span_path(&trace.interval_tree, range.start);
+            tree_res = Some(TreeResponse::Goto(range));
+        }
 
-                egui::Color32::from_rgb(hash as u8, (hash >> 8) as u8, (hash >> 16) as u8)
-            } else {
-                egui::Color32::BROWN
+        egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
+            let goto = ui.horizontal(|ui| {
+                let visited = trace.history.ui(ui);
+                ui.separator();
+                ui.selectable_value(&mut self.big_endian, false, "LE")
+                    .on_hover_text("interpret values as little endian");
+                ui.selectable_value(&mut self.big_endian, true, "BE")
+                    .on_hover_text("interpret values as big endian");
+                ui.separator();
+                let unread: usize = trace.gaps.iter().map(|g| g.len()).sum();
+                ui.toggle_value(&mut self.show_coverage, "unread")
+                    .on_hover_text(format!(
+                        "highlight the {unread} bytes in {} gap(s) not covered by any read",
+                        trace.gaps.len()
+                    ));
+                ui.toggle_value(&mut self.show_seeks, "seeks")
+                    .on_hover_text(format!(
+                        "highlight the {} seek destination(s)",
+                        trace.seek_targets.len()
+                    ));
+                ui.separator();
+                columns_ui(ui, &mut self.columns, trace.mem_editor.options.column_count);
+                self.offsets.ui(ui);
+                ui.separator();
+                self.colors.bytes_ui(ui);
+                ui.separator();
+                overlay_ui(ui, trace, &mut self.error);
+                original_ui(ui, trace, &mut self.error);
+                ui.separator();
+                let found = trace.search.ui(ui, (*trace.data).as_ref());
+                ui.separator();
+                let selection = trace.selection.map(|s| s.range());
+                let start = selection.as_ref().map(|s| s.start);
+                trace.occurrences.update((*trace.data).as_ref(), selection);
+                let occurrence = trace.occurrences.ui(ui, start);
+                visited.or(found).or(occurrence)
+            });
+            if let Some(range) = goto.inner {
+                tab.path_select = span_path(&trace.interval_tree, range.start);
+                trace.selection = Some(Selection::goto(&mut trace.mem_editor, range));
             }
         });
-
-        let mut tree_res = None;
+        egui::TopBottomPanel::top("breadcrumb").show(ctx, |ui| {
+            if let Some(range) = trace.tree_view.breadcrumb_ui(ui, &trace.full_tree) {
+                tree_res = Some(TreeResponse::Goto(range));
+            }
+        });
+        let mut close_sub_trace = false;
         //self.shrink_window_ui(ui);
         egui::SidePanel::left("left").show(ctx, |ui| {
-            egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
-                tree_res = self
-                    .trace
-                    .trace
-                    .full_tree
-                    .ui(ui, 0, self.path_select.take().as_deref())
+            if is_sub_trace {
+                ui.horizontal(|ui| {
+                    close_sub_trace = ui.button("⬅ back").clicked();
+                    ui.label(&trace.title);
+                });
+            }
+            let metadata = trace.metadata.entries();
+            if !metadata.is_empty() {
+                egui::CollapsingHeader::new("metadata").show(ui, |ui| {
+                    egui::Grid::new("metadata").show(ui, |ui| {
+                        for (key, value) in metadata {
+                            ui.label(key);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    });
+                });
+            }
+            let problems = &trace.problems;
+            if !problems.is_empty() {
+                egui::CollapsingHeader::new(
+                    egui::RichText::new(format!("{} problem(s)", problems.len()))
+                        .color(egui::Color32::RED),
+                )
+                .show(ui, |ui| {
+                    for problem in problems {
+                        ui.label(problem.to_string());
+                    }
+                });
+            }
+            let bookmarks = &mut trace.bookmarks;
+            let header = format!("{} bookmark(s)", bookmarks.list().len());
+            egui::CollapsingHeader::new(header).show(ui, |ui| {
+                let selection = trace.selection.map(|s| s.range());
+                if let Some(range) = bookmarks.ui(ui, selection) {
+                    tab.path_select = span_path(&trace.interval_tree, range.start);
+                    tree_res = Some(TreeResponse::Goto(range));
+                }
             });
+            let notes = &mut trace.notes;
+            egui::CollapsingHeader::new(format!("{} note(s)", notes.list().len())).show(ui, |ui| {
+                let selection = trace.selection.map(|s| s.range());
+                if let Some(range) = notes.ui(ui, selection) {
+                    tab.path_select = span_path(&trace.interval_tree, range.start);
+                    tree_res = Some(TreeResponse::Goto(range));
+                }
+            });
+            let header = format!("{} span name(s)", trace.legend.count());
+            egui::CollapsingHeader::new(header).show(ui, |ui| {
+                if let Some(range) = trace.legend.ui(ui, &self.colors) {
+                    tab.path_select = span_path(&trace.interval_tree, range.start);
+                    tree_res = Some(TreeResponse::Goto(range));
+                }
+            });
+            if let Some(overlay) = &trace.overlay {
+                let name = overlay
+                    .path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy();
+                egui::CollapsingHeader::new(format!("overlay {name}")).show(ui, |ui| {
+                    if let Some(range) = overlay.ui(ui) {
+                        tab.path_select = span_path(&trace.interval_tree, range.start);
+                        tree_res = Some(TreeResponse::Goto(range));
+                    }
+                });
+            }
+            if let Some(original) = &mut trace.original {
+                let name = original
+                    .path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned();
+                egui::CollapsingHeader::new(format!("original {name}")).show(ui, |ui| {
+                    if let Some(range) = original.ui(ui) {
+                        tab.path_select = span_path(&trace.interval_tree, range.start);
+                        tree_res = Some(TreeResponse::Goto(range));
+                    }
+                });
+            }
+            egui::CollapsingHeader::new("strings").show(ui, |ui| {
+                if let Some(range) = trace.strings.ui(ui, (*trace.data).as_ref()) {
+                    tab.path_select = span_path(&trace.interval_tree, range.start);
+                    tree_res = Some(TreeResponse::Goto(range));
+                }
+            });
+            if let Some(path) = trace.tree_filter.ui(ui, &mut trace.full_tree) {
+                tab.path_select = Some(path);
+            }
+            let expand = expand_ui(ui, &mut self.expand_depth).or(palette_expand);
+            let tree = &trace.full_tree;
+            if let Some(range) = trace.tree_view.input(ui.ctx(), tree) {
+                tree_res = Some(TreeResponse::Goto(range));
+            }
+            let filter = trace.tree_filter.applied();
+            let path_select = tab.path_select.take();
+            if let Some(res) = trace.tree_view.ui(ui, tree, filter, path_select, expand) {
+                tree_res = Some(res);
+            }
         });
 
         // https://github.com/emilk/egui/issues/901
@@ -427,51 +1442,148 @@ impl eframe::App for App {
             .show_separator_line(false)
             .show(ctx, |_| ());
 
+        egui::SidePanel::right("minimap")
+            .exact_width(24.)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let selection = trace.selection.map(|s| s.range());
+                let data = (*trace.data).as_ref();
+                let clicked = minimap::ui(ui, data.len(), selection, trace.seek, |address| {
+                    if let Some(color) = self.colors.byte(data, address) {
+                        return color;
+                    }
+                    match trace.interval_tree.query_point(address).next() {
+                        Some(span) => self.colors.span(&span.value),
+                        None => egui::Color32::from_gray(40),
+                    }
+                });
+                if let Some(address) = clicked {
+                    tab.path_select = span_path(&trace.interval_tree, address);
+                    tree_res = Some(TreeResponse::Goto(address..address + 1));
+                }
+            });
+
+        if let Some(selection) = trace.selection {
+            egui::SidePanel::right("inspector").show(ctx, |ui| {
+                let origin = trace.origin().filter(|_| self.offsets.relative);
+                let followed = inspector::ui(
+                    ui,
+                    (*trace.data).as_ref(),
+                    selection.range(),
+                    self.big_endian,
+                    origin.map(|origin| (self.offsets, origin)),
+                    &mut self.pointer_base,
+                );
+                if let Some(address) = followed {
+                    tab.path_select = span_path(&trace.interval_tree, address);
+                    tree_res = Some(TreeResponse::Goto(address..address + 1));
+                }
+            });
+        }
+
+        let mut open_sub_trace = None;
         egui::CentralPanel::default().show(ctx, |ui| {
             match tree_res {
                 None => {}
-                Some(TreeResponse::Goto(address)) => {
-                    self.trace
-                        .trace
-                        .mem_editor
-                        .frame_data
-                        .set_highlight_address(address);
-                    self.trace.trace.mem_editor.frame_data.goto_address_line =
-                        Some(address / self.trace.trace.mem_editor.options.column_count);
+                Some(TreeResponse::Goto(range)) => {
+                    trace.seek = None;
+                    trace.selection = Some(Selection::goto(&mut trace.mem_editor, range));
+                }
+                Some(TreeResponse::Seek { from, to }) => {
+                    trace.seek = Some((from, to));
+                    trace.selection = Some(Selection::goto(&mut trace.mem_editor, to..to + 1));
+                }
+                Some(TreeResponse::Export(range)) => export_bytes((*trace.data).as_ref(), range),
+                Some(TreeResponse::Note(range, span)) => trace.notes.write(range, span),
+                Some(TreeResponse::OpenSubTrace(sub)) => open_sub_trace = Some(sub),
+            }
+            let mut extra = vec![];
+            if self.show_coverage {
+                let color = egui::Color32::from_rgb(200, 0, 200);
+                extra.extend(trace.gaps.iter().map(|g| (g.clone(), color)));
+            }
+            if let Some(overlay) = &trace.overlay {
+                extra.extend(overlay.highlights());
+            }
+            if let Some(original) = &trace.original {
+                extra.extend(original.highlights());
+            }
+            let start = trace.selection.map(|s| s.range().start);
+            extra.extend(trace.occurrences.highlights(start));
+            if self.show_seeks {
+                extra.extend(trace.seek_targets.iter().map(|&t| (t..t + 1, SEEK_COLOR)));
+            }
+            trace.mem_editor.options.column_count = self.columns.unwrap_or_else(|| fit_columns(ui));
+            if let Some(address) = trace.hex_ui(ui, &self.colors, self.offsets, extra) {
+                trace.seek = None;
+                if let Some(path) = span_path(&trace.interval_tree, address) {
+                    tab.path_select = Some(path);
                 }
             }
-            let prev_selection = self
-                .trace
-                .trace
-                .mem_editor
-                .frame_data
-                .selected_highlight_address;
-            self.trace.trace.mem_editor.draw_editor_contents_read_only(
+            let Some(selection) = trace.selection.map(|s| s.range()) else {
+                return;
+            };
+            let data = (*trace.data).as_ref();
+            let action = self.hex_menu.ui(
                 ui,
-                &mut self.trace.trace.data,
-                |data, address| data[address].into(),
-                RenderCtx {
-                    span_query,
-                    hover_byte,
-                    color_byte,
-                },
+                data,
+                selection,
+                self.big_endian,
+                self.pointer_base,
+                self.offsets,
             );
-            let new_selection = self
-                .trace
-                .trace
-                .mem_editor
-                .frame_data
-                .selected_highlight_address;
-            if prev_selection != new_selection {
-                if let Some(selection) = new_selection {
-                    // TODO find "narrowest" span in case of multiple
-                    if let Some(span) = interval_tree.query(selection..selection + 1).next() {
-                        let mut path_select = vec![0];
-                        path_select.extend(&span.value.path);
-                        self.path_select = Some(path_select);
-                    }
+            let goto = match action {
+                None => None,
+                Some(HexAction::Bookmark(range)) => {
+                    trace.bookmarks.add(range);
+                    None
+                }
+                Some(HexAction::Note(range)) => {
+                    let span = trace.interval_tree.query_point(range.start).next();
+                    let span = span.map(|s| s.value.name.clone());
+                    trace.notes.write(range, span);
+                    None
+                }
+                Some(HexAction::Follow(address)) => {
+                    tab.path_select = span_path(&trace.interval_tree, address);
+                    Some(address..address + 1)
+                }
+                Some(HexAction::SelectSpan) => trace.enclosing_span().map(|(path, range)| {
+                    tab.path_select = Some(path);
+                    range
+                }),
+                Some(HexAction::Export(range)) => {
+                    export_bytes(data, range);
+                    None
                 }
+                Some(HexAction::ExportHtml(range)) => {
+                    let columns = trace.mem_editor.options.column_count;
+                    html::export(data, range, columns, |address| {
+                        let span = &trace.interval_tree.query_point(address).next()?.value;
+                        Some(html::Style {
+                            label: span.name.clone(),
+                            background: self.colors.span(span),
+                            text: self.colors.text(span),
+                        })
+                    });
+                    None
+                }
+            };
+            if let Some(range) = goto {
+                trace.seek = None;
+                trace.selection = Some(Selection::goto(&mut trace.mem_editor, range));
             }
         });
+        trace.notes.draft_window(ctx);
+        trace.history.record(trace.selection.map(|s| s.range()));
+
+        if let Some(sub) = open_sub_trace {
+            match trace.load_sub_trace(&sub) {
+                Ok(sub_trace) => tab.sub_traces.push(sub_trace),
+                Err(err) => eprintln!("failed to load sub-trace {err:?}"),
+            }
+        } else if close_sub_trace {
+            tab.sub_traces.pop();
+        }
     }
 }
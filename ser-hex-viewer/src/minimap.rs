@@ -0,0 +1,58 @@
+//! Vertical strip giving an overview of the whole data
+
+use std::ops::Range;
+
+use eframe::egui;
+
+/// Draws the `len` bytes of the data squeezed into the available height, each row colored by
+/// `color` of its first byte, with an arrow for the `seek` shown. Returns the offset clicked
+pub fn ui(
+    ui: &mut egui::Ui,
+    len: usize,
+    selection: Option<Range<usize>>,
+    seek: Option<(usize, usize)>,
+    color: impl Fn(usize) -> egui::Color32,
+) -> Option<usize> {
+    let (rect, response) = ui.allocate_exact_size(ui.available_size(), egui::Sense::click());
+    if len == 0 {
+        return None;
+    }
+    let offset = |y: f32| {
+        let offset = (y - rect.top()) / rect.height() * len as f32;
+        (offset.max(0.) as usize).min(len - 1)
+    };
+    let y = |offset: usize| rect.top() + offset as f32 / len as f32 * rect.height();
+
+    let painter = ui.painter_at(rect);
+    let rows = rect.height().max(1.) as usize;
+    for row in 0..rows {
+        let top = rect.top() + row as f32;
+        let row_rect = egui::Rect::from_x_y_ranges(rect.x_range(), top..=top + 1.);
+        painter.rect_filled(row_rect, 0., color(row * len / rows));
+    }
+    if let Some(selection) = selection {
+        let top = y(selection.start);
+        let bottom = y(selection.end).max(top + 2.);
+        painter.rect_stroke(
+            egui::Rect::from_x_y_ranges(rect.x_range(), top..=bottom),
+            0.,
+            egui::Stroke::new(1., egui::Color32::WHITE),
+        );
+    }
+    if let Some((from, to)) = seek {
+        let from = egui::pos2(rect.center().x, y(from));
+        let to = egui::pos2(rect.center().x, y(to));
+        let stroke = egui::Stroke::new(2., crate::SEEK_COLOR);
+        painter.arrow(from, to - from, stroke);
+    }
+
+    let hovered = response.hover_pos().map(|pos| offset(pos.y));
+    let response = match hovered {
+        Some(hovered) => response.on_hover_text_at_pointer(format!("{hovered:#x}")),
+        None => response,
+    };
+    response
+        .interact_pointer_pos()
+        .filter(|_| response.clicked())
+        .map(|pos| offset(pos.y))
+}
@@ -0,0 +1,143 @@
+//! Free-text notes on byte ranges and spans saved to a sidecar file next to the trace
+
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use eframe::egui;
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Note {
+    pub range: Range<usize>,
+    /// Name of the span the note was added to, `None` for notes on raw bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<String>,
+    pub text: String,
+}
+impl Note {
+    fn title(&self) -> String {
+        let range = &self.range;
+        match &self.span {
+            Some(span) => format!("{span} ({}..{})", range.start, range.end),
+            None => format!("{}..{}", range.start, range.end),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Notes {
+    /// Sidecar file, `None` for inline sub-traces which are not persisted
+    path: Option<PathBuf>,
+    list: Vec<Note>,
+    /// Note being written, shown in a window until saved or discarded
+    draft: Option<Note>,
+}
+impl Notes {
+    /// Load the notes of the trace at `trace_path`, if any have been saved
+    pub fn load(trace_path: &Path) -> Result<Self> {
+        let mut name = trace_path.file_name().unwrap_or_default().to_owned();
+        name.push(".notes.json");
+        let path = trace_path.with_file_name(name);
+        let list = match path.exists() {
+            true => serde_json::from_slice(&fs::read(&path)?)?,
+            false => vec![],
+        };
+        Ok(Self {
+            path: Some(path),
+            list,
+            draft: None,
+        })
+    }
+    fn save(&self) -> Result<()> {
+        if let Some(path) = &self.path {
+            fs::write(path, serde_json::to_string_pretty(&self.list)?)?;
+        }
+        Ok(())
+    }
+    fn save_or_log(&self) {
+        if let Err(err) = self.save() {
+            eprintln!("failed to save notes {err:?}");
+        }
+    }
+    pub fn list(&self) -> &[Note] {
+        &self.list
+    }
+    /// Notes on ranges containing `address`
+    pub fn at(&self, address: usize) -> impl Iterator<Item = &Note> {
+        self.list.iter().filter(move |n| n.range.contains(&address))
+    }
+    /// Start writing a note on `range`, of the span named `span` if any
+    pub fn write(&mut self, range: Range<usize>, span: Option<String>) {
+        self.draft = Some(Note {
+            range,
+            span,
+            text: String::new(),
+        });
+    }
+    /// Window of the note being written
+    pub fn draft_window(&mut self, ctx: &egui::Context) {
+        let Some(draft) = &mut self.draft else {
+            return;
+        };
+        let (mut save, mut discard) = (false, false);
+        egui::Window::new(format!("note on {}", draft.title()))
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let text = ui.text_edit_multiline(&mut draft.text);
+                if ui.memory(|m| m.focused().is_none()) {
+                    text.request_focus();
+                }
+                ui.horizontal(|ui| {
+                    save = ui.button("save").clicked();
+                    discard = ui.button("discard").clicked();
+                });
+            });
+        if save {
+            self.list.extend(self.draft.take());
+            self.save_or_log();
+        } else if discard {
+            self.draft = None;
+        }
+    }
+    /// Note list and a button to add one to `selection`, returns the range of the note to go to
+    /// if one was clicked
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        selection: Option<Range<usize>>,
+    ) -> Option<Range<usize>> {
+        let mut goto = None;
+        let add = ui.add_enabled(
+            selection.is_some(),
+            egui::Button::new("add note to selection"),
+        );
+        if let (true, Some(range)) = (add.clicked(), selection) {
+            self.write(range, None);
+        }
+        let mut remove = None;
+        let mut changed = false;
+        for (i, note) in self.list.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button(note.title()).clicked() {
+                    goto = Some(note.range.clone());
+                }
+                if ui.small_button("🗑").on_hover_text("remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+            changed |= ui.text_edit_multiline(&mut note.text).lost_focus();
+        }
+        if let Some(i) = remove {
+            self.list.remove(i);
+            changed = true;
+        }
+        if changed {
+            self.save_or_log();
+        }
+        goto
+    }
+}
@@ -0,0 +1,114 @@
+//! Other occurrences of the selected bytes, to find values repeated elsewhere in the data
+
+use std::ops::Range;
+
+use eframe::egui;
+
+use crate::search::find_all;
+
+/// Longest selection looked for elsewhere
+const MAX_LEN: usize = 64;
+
+/// Color of the occurrences in the hex view
+pub const COLOR: egui::Color32 = egui::Color32::from_rgb(255, 120, 200);
+
+#[derive(Debug, Default)]
+pub struct Occurrences {
+    pub enabled: bool,
+    /// Selected bytes the occurrences were found for
+    bytes: Vec<u8>,
+    /// Offsets of every occurrence including the selection itself
+    matches: Vec<usize>,
+}
+impl Occurrences {
+    /// Find the occurrences of the bytes in `selection` if they changed, nothing is looked for
+    /// when disabled or fewer than two or more than [`MAX_LEN`] bytes are selected
+    pub fn update(&mut self, data: &[u8], selection: Option<Range<usize>>) {
+        let bytes = selection
+            .filter(|s| self.enabled && (2..=MAX_LEN).contains(&s.len()))
+            .and_then(|s| data.get(s))
+            .unwrap_or_default();
+        if bytes != self.bytes {
+            self.bytes = bytes.to_vec();
+            self.matches = match bytes.is_empty() {
+                true => vec![],
+                false => find_all(data, bytes),
+            };
+        }
+    }
+    /// Occurrences other than the selection starting at `start`
+    pub fn highlights(
+        &self,
+        start: Option<usize>,
+    ) -> impl Iterator<Item = (Range<usize>, egui::Color32)> + '_ {
+        let len = self.bytes.len();
+        self.matches
+            .iter()
+            .filter(move |&&m| Some(m) != start)
+            .map(move |&m| (m..m + len, COLOR))
+    }
+    /// Occurrence after or before the one at `start`, wrapping around at either end
+    fn step(&self, start: usize, forward: bool) -> Option<Range<usize>> {
+        let next = match forward {
+            true => self.matches.iter().find(|&&m| m > start),
+            false => self.matches.iter().rev().find(|&&m| m < start),
+        };
+        let wrapped = match forward {
+            true => self.matches.first(),
+            false => self.matches.last(),
+        };
+        let m = *next.or(wrapped)?;
+        Some(m..m + self.bytes.len())
+    }
+    /// Toggle, count and buttons cycling through the occurrences of the selection starting at
+    /// `start`. Returns the occurrence to go to
+    pub fn ui(&mut self, ui: &mut egui::Ui, start: Option<usize>) -> Option<Range<usize>> {
+        ui.toggle_value(&mut self.enabled, "occurrences")
+            .on_hover_text(format!(
+                "highlight other occurrences of the selected bytes, 2 to {MAX_LEN} bytes"
+            ));
+        let start = start.filter(|_| self.matches.len() > 1)?;
+        let index = self.matches.iter().position(|&m| m == start);
+        let index = index.map(|i| (i + 1).to_string());
+        ui.label(format!(
+            "{}/{}",
+            index.as_deref().unwrap_or("-"),
+            self.matches.len()
+        ));
+        let mut step = None;
+        if ui
+            .button("⬅")
+            .on_hover_text("previous occurrence")
+            .clicked()
+        {
+            step = Some(false);
+        }
+        if ui.button("➡").on_hover_text("next occurrence").clicked() {
+            step = Some(true);
+        }
+        step.and_then(|forward| self.step(start, forward))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_occurrences() {
+        let data = [1, 2, 3, 1, 2, 1, 2, 3];
+        let mut occurrences = Occurrences::default();
+        occurrences.update(&data, Some(0..2));
+        assert!(occurrences.matches.is_empty());
+
+        occurrences.enabled = true;
+        occurrences.update(&data, Some(0..2));
+        assert_eq!(occurrences.matches, [0, 3, 5]);
+        assert_eq!(occurrences.step(0, true), Some(3..5));
+        assert_eq!(occurrences.step(5, true), Some(0..2));
+        assert_eq!(occurrences.step(0, false), Some(5..7));
+
+        occurrences.update(&data, Some(0..1));
+        assert!(occurrences.matches.is_empty());
+    }
+}
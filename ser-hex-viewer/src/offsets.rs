@@ -0,0 +1,60 @@
+//! How offsets of the hex view are shown
+
+use eframe::egui;
+
+/// Offsets of the hover text and inspector, hexadecimal or decimal and optionally relative to
+/// the start of the span enclosing the selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Offsets {
+    pub hex: bool,
+    pub relative: bool,
+}
+impl Default for Offsets {
+    fn default() -> Self {
+        Self {
+            hex: true,
+            relative: false,
+        }
+    }
+}
+impl Offsets {
+    /// `address` relative to `origin` when relative offsets are shown and there is one
+    pub fn format(self, address: usize, origin: Option<usize>) -> String {
+        let (sign, offset) = match origin.filter(|_| self.relative) {
+            Some(origin) if address < origin => ("-", origin - address),
+            Some(origin) => ("+", address - origin),
+            None => ("", address),
+        };
+        match self.hex {
+            true => format!("{sign}{offset:#x}"),
+            false => format!("{sign}{offset}"),
+        }
+    }
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.selectable_value(&mut self.hex, true, "hex")
+            .on_hover_text("show offsets in hexadecimal");
+        ui.selectable_value(&mut self.hex, false, "dec")
+            .on_hover_text("show offsets in decimal");
+        ui.toggle_value(&mut self.relative, "relative")
+            .on_hover_text(
+                "show offsets relative to the start of the span enclosing the selection",
+            );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let mut offsets = Offsets::default();
+        assert_eq!(offsets.format(0x1a3f4, Some(0x1a3f0)), "0x1a3f4");
+        offsets.relative = true;
+        assert_eq!(offsets.format(0x1a3f4, Some(0x1a3f0)), "+0x4");
+        assert_eq!(offsets.format(0x1a3e0, Some(0x1a3f0)), "-0x10");
+        assert_eq!(offsets.format(0x1a3f4, None), "0x1a3f4");
+        offsets.hex = false;
+        assert_eq!(offsets.format(20, Some(8)), "+12");
+    }
+}
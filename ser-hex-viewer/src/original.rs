@@ -0,0 +1,140 @@
+//! Original file a trace was captured from, compared with the trace data and optionally shown
+//! where the trace data is redacted or truncated
+
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use eframe::egui;
+use fs_err as fs;
+
+/// Color of the bytes differing from the original
+const MISMATCH_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 40, 40);
+/// Text color of the bytes shown from the original
+pub const ORIGINAL_COLOR: egui::Color32 = egui::Color32::from_rgb(170, 170, 255);
+
+/// Ranges where `trace` and `original` differ, ignoring `redacted` bytes and bytes past the end
+/// of either
+fn mismatches(trace: &[u8], original: &[u8], redacted: &[Range<usize>]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = vec![];
+    for (i, (a, b)) in trace.iter().zip(original).enumerate() {
+        if a == b || redacted.iter().any(|r| r.contains(&i)) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(last) if last.end == i => last.end += 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
+
+#[derive(Debug)]
+pub struct Original {
+    pub path: PathBuf,
+    data: Vec<u8>,
+    /// Length of the trace data
+    trace_len: usize,
+    mismatches: Vec<Range<usize>>,
+    /// Show the original bytes where the trace data is redacted or truncated
+    pub fill: bool,
+}
+impl Original {
+    pub fn load(path: &Path, trace: &[u8], redacted: &[Range<usize>]) -> Result<Self> {
+        let data = fs::read(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            mismatches: mismatches(trace, &data, redacted),
+            trace_len: trace.len(),
+            data,
+            fill: false,
+        })
+    }
+    /// Number of bytes shown in the hex view, the original's length if longer and filling in
+    pub fn view_len(&self) -> usize {
+        match self.fill {
+            true => self.trace_len.max(self.data.len()),
+            false => self.trace_len,
+        }
+    }
+    /// Byte shown from the original at `address` in place of the trace data
+    pub fn byte(&self, address: usize, redacted: bool) -> Option<u8> {
+        let shown = self.fill && (redacted || address >= self.trace_len);
+        shown.then(|| self.data.get(address).copied()).flatten()
+    }
+    /// Original byte at `address` if it differs from the trace data
+    pub fn mismatch(&self, address: usize) -> Option<u8> {
+        self.mismatches
+            .iter()
+            .any(|m| m.contains(&address))
+            .then(|| self.data[address])
+    }
+    pub fn highlights(&self) -> impl Iterator<Item = (Range<usize>, egui::Color32)> + '_ {
+        self.mismatches.iter().map(|m| (m.clone(), MISMATCH_COLOR))
+    }
+    /// Summary and mismatching ranges, returns the range to go to if one was clicked
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<Range<usize>> {
+        let mut goto = None;
+        let (trace_len, len) = (self.trace_len, self.data.len());
+        if trace_len != len {
+            ui.colored_label(
+                MISMATCH_COLOR,
+                format!("trace data is {trace_len} bytes, the original {len} bytes"),
+            );
+        }
+        let mismatched: usize = self.mismatches.iter().map(|m| m.len()).sum();
+        match mismatched {
+            0 => ui.label("trace data matches the original"),
+            n => ui.colored_label(
+                MISMATCH_COLOR,
+                format!("{n} byte(s) in {} range(s) differ", self.mismatches.len()),
+            ),
+        };
+        ui.checkbox(&mut self.fill, "show original bytes")
+            .on_hover_text(
+                "show bytes from the original where the trace data is redacted or truncated",
+            );
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        egui::ScrollArea::vertical()
+            .max_height(300.)
+            .id_salt("original")
+            .show_rows(ui, row_height, self.mismatches.len(), |ui, rows| {
+                for range in &self.mismatches[rows] {
+                    let text = format!("{}..{} ({} bytes)", range.start, range.end, range.len());
+                    if ui.selectable_label(false, text).clicked() {
+                        goto = Some(range.clone());
+                    }
+                }
+            });
+        goto
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mismatches() {
+        let trace = [0, 1, 9, 9, 4, 0, 0, 7];
+        let original = [0, 1, 2, 3, 4, 5, 6, 8, 8, 9];
+        assert_eq!(mismatches(&trace, &original, &[5..7]), [2..4, 7..8]);
+
+        let mut original = Original {
+            path: PathBuf::new(),
+            data: original.to_vec(),
+            trace_len: trace.len(),
+            mismatches: vec![],
+            fill: false,
+        };
+        assert_eq!(original.view_len(), 8);
+        assert_eq!(original.byte(5, true), None);
+        original.fill = true;
+        assert_eq!(original.view_len(), 10);
+        assert_eq!(original.byte(5, true), Some(5));
+        assert_eq!(original.byte(4, false), None);
+        assert_eq!(original.byte(9, false), Some(9));
+    }
+}
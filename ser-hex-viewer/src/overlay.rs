@@ -0,0 +1,316 @@
+//! Field boundaries of a reference grammar overlaid on the trace data, parsed from a subset of
+//! Kaitai Struct `.ksy` files: sequences of fixed size integers and floats, sized or
+//! `contents` fields, null terminated strings and user types, repeated by count or until the end
+//! of the data. Sizes and counts may refer to earlier integer fields of the same type
+
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context as _, Result};
+use eframe::egui;
+use fs_err as fs;
+use serde_yaml::{Mapping, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub range: Range<usize>,
+    /// Dotted path of the field from the top-level type
+    pub name: String,
+    /// Not of a user type with fields of its own
+    pub leaf: bool,
+    /// A read of the trace covers exactly the same bytes
+    pub matched: bool,
+}
+
+/// Size of a primitive type, whether it is an integer and its byte order if specified
+fn primitive(ty: &str) -> Option<(usize, bool, Option<bool>)> {
+    let (ty, big_endian) = match ty {
+        _ if ty.ends_with("be") => (&ty[..ty.len() - 2], Some(true)),
+        _ if ty.ends_with("le") => (&ty[..ty.len() - 2], Some(false)),
+        _ => (ty, None),
+    };
+    let (size, integer) = match ty {
+        "u1" | "s1" => (1, true),
+        "u2" | "s2" => (2, true),
+        "u4" | "s4" => (4, true),
+        "u8" | "s8" => (8, true),
+        "f4" => (4, false),
+        "f8" => (8, false),
+        _ => return None,
+    };
+    Some((size, integer, big_endian))
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    types: Option<&'a Mapping>,
+    big_endian: bool,
+    offset: usize,
+    fields: Vec<Field>,
+}
+impl Parser<'_> {
+    /// Integer literal or the value of an earlier field
+    fn int(&self, value: &Value, values: &HashMap<&str, u64>) -> Result<u64> {
+        match value {
+            Value::Number(n) => n.as_u64().context("negative or fractional number"),
+            Value::String(id) => match values.get(id.as_str()) {
+                Some(value) => Ok(*value),
+                None => bail!("expression {id:?} is not supported"),
+            },
+            _ => bail!("expected a number or field name, found {value:?}"),
+        }
+    }
+    fn seq(&mut self, seq: &Value, prefix: &str) -> Result<()> {
+        let mut values = HashMap::new();
+        for item in seq.as_sequence().context("seq is not a list")? {
+            let id = item["id"].as_str().context("seq item without id")?;
+            let name = format!("{prefix}{id}");
+            for key in ["if", "pos", "process", "terminator", "size-eos", "encoding"] {
+                if item.get(key).is_some() {
+                    bail!("{name}: `{key}` is not supported");
+                }
+            }
+            let count = match item.get("repeat").and_then(Value::as_str) {
+                None => Some(1),
+                Some("expr") => Some(self.int(&item["repeat-expr"], &values)?),
+                Some("eos") => None,
+                Some(other) => bail!("{name}: repeat {other} is not supported"),
+            };
+            let mut i = 0;
+            while count.map_or(self.offset < self.data.len(), |count| i < count) {
+                let start = self.offset;
+                let name = match count {
+                    Some(1) => name.clone(),
+                    _ => format!("{name}[{i}]"),
+                };
+                let value = self.field(item, name, &values)?;
+                if let (Some(1), Some(value)) = (count, value) {
+                    values.insert(id, value);
+                }
+                if count.is_none() && self.offset == start {
+                    bail!("{name}: repeated until the end of the data without reading");
+                }
+                i += 1;
+            }
+        }
+        Ok(())
+    }
+    /// Parse a field, returns its value if it is an unsigned integer
+    fn field(
+        &mut self,
+        item: &Value,
+        name: String,
+        values: &HashMap<&str, u64>,
+    ) -> Result<Option<u64>> {
+        let start = self.offset;
+        let ty = item.get("type").and_then(Value::as_str);
+        let size = match item.get("contents") {
+            Some(Value::String(s)) => Some(s.len()),
+            Some(Value::Sequence(bytes)) => Some(bytes.len()),
+            Some(other) => bail!("{name}: unsupported contents {other:?}"),
+            None => None,
+        };
+        let size = match (size, item.get("size")) {
+            (Some(size), _) => Some(size),
+            (None, Some(size)) => Some(self.int(size, values)? as usize),
+            (None, None) => None,
+        };
+        let mut value = None;
+        let mut leaf = true;
+        let end = match (ty, size) {
+            (Some(ty), size) if primitive(ty).is_some() => {
+                let (len, integer, big_endian) = primitive(ty).unwrap();
+                let end = start + size.unwrap_or(len);
+                if integer && ty.starts_with('u') {
+                    value = self.uint(start..end, big_endian.unwrap_or(self.big_endian));
+                }
+                end
+            }
+            (Some("strz"), None) => {
+                let rest = self.data.get(start..).unwrap_or_default();
+                let len = rest
+                    .iter()
+                    .position(|b| *b == 0)
+                    .context("unterminated strz")?;
+                start + len + 1
+            }
+            (Some("str" | "strz"), Some(size)) | (None, Some(size)) => start + size,
+            (Some(ty), size) => {
+                let seq = self
+                    .types
+                    .and_then(|types| types.get(ty))
+                    .and_then(|ty| ty.get("seq"))
+                    .with_context(|| format!("{name}: unknown type {ty}"))?;
+                self.seq(seq, &format!("{name}."))?;
+                leaf = false;
+                match size {
+                    Some(size) => start + size,
+                    None => self.offset,
+                }
+            }
+            (None, None) => bail!("{name}: no type or size"),
+        };
+        if end > self.data.len() {
+            bail!("{name}: ends at {end} past the end of the data");
+        }
+        self.offset = end;
+        self.fields.push(Field {
+            range: start..end,
+            name,
+            leaf,
+            matched: false,
+        });
+        Ok(value)
+    }
+    fn uint(&self, range: Range<usize>, big_endian: bool) -> Option<u64> {
+        let bytes = self.data.get(range)?;
+        let fold = |value: u64, b: &u8| (value << 8) | *b as u64;
+        Some(match big_endian {
+            true => bytes.iter().fold(0, fold),
+            false => bytes.iter().rev().fold(0, fold),
+        })
+    }
+}
+
+/// Fields of `ksy` parsed from `data`, in the order they end
+fn parse(ksy: &str, data: &[u8]) -> Result<Vec<Field>> {
+    let ksy: Value = serde_yaml::from_str(ksy)?;
+    let big_endian = match ksy["meta"]["endian"].as_str() {
+        Some("be") => true,
+        Some("le") | None => false,
+        Some(other) => bail!("endian {other} is not supported"),
+    };
+    let mut parser = Parser {
+        data,
+        types: ksy.get("types").and_then(Value::as_mapping),
+        big_endian,
+        offset: 0,
+        fields: vec![],
+    };
+    parser.seq(ksy.get("seq").context("no top-level seq")?, "")?;
+    Ok(parser.fields)
+}
+
+#[derive(Debug)]
+pub struct Overlay {
+    pub path: PathBuf,
+    pub fields: Vec<Field>,
+}
+impl Overlay {
+    /// Parse the `.ksy` file at `path` against `data`
+    pub fn load(path: &Path, data: &[u8]) -> Result<Self> {
+        let ksy = fs::read_to_string(path)?;
+        let fields = parse(&ksy, data).with_context(|| format!("failed to apply {path:?}"))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            fields,
+        })
+    }
+    /// Mark the fields read exactly the same way by the trace
+    pub fn compare(&mut self, matched: impl Fn(&Range<usize>) -> bool) {
+        for field in &mut self.fields {
+            field.matched = matched(&field.range);
+        }
+    }
+    /// Leaf fields colored by whether the trace read them the same way
+    pub fn highlights(&self) -> impl Iterator<Item = (Range<usize>, egui::Color32)> + '_ {
+        self.fields.iter().filter(|f| f.leaf).map(|f| {
+            let color = match f.matched {
+                true => egui::Color32::from_rgb(0, 160, 0),
+                false => egui::Color32::from_rgb(255, 90, 0),
+            };
+            (f.range.clone(), color)
+        })
+    }
+    /// Fields containing `address`
+    pub fn at(&self, address: usize) -> impl Iterator<Item = &Field> {
+        self.fields
+            .iter()
+            .filter(move |f| f.range.contains(&address))
+    }
+    /// Field list, returns the range of the field clicked
+    pub fn ui(&self, ui: &mut egui::Ui) -> Option<Range<usize>> {
+        let mut goto = None;
+        let unmatched = self.fields.iter().filter(|f| f.leaf && !f.matched).count();
+        ui.label(format!(
+            "{unmatched} field(s) not read the same way by the trace"
+        ));
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        egui::ScrollArea::vertical()
+            .max_height(300.)
+            .id_salt("overlay")
+            .show_rows(ui, row_height, self.fields.len(), |ui, rows| {
+                for field in &self.fields[rows] {
+                    let range = &field.range;
+                    let mut text = egui::RichText::new(format!(
+                        "{} ({}..{})",
+                        field.name, range.start, range.end
+                    ));
+                    if field.leaf && !field.matched {
+                        text = text.color(egui::Color32::from_rgb(255, 90, 0));
+                    }
+                    if ui.selectable_label(false, text).clicked() {
+                        goto = Some(range.clone());
+                    }
+                }
+            });
+        goto
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let ksy = r#"
+meta:
+  id: test
+  endian: le
+seq:
+  - id: magic
+    contents: "AB"
+  - id: count
+    type: u2
+  - id: entries
+    type: entry
+    repeat: expr
+    repeat-expr: count
+  - id: name
+    type: strz
+types:
+  entry:
+    seq:
+      - id: len
+        type: u1
+      - id: body
+        size: len
+"#;
+        let data = b"AB\x02\x00\x01x\x02yzhi\0";
+        let fields: Vec<_> = parse(ksy, data)
+            .unwrap()
+            .into_iter()
+            .map(|f| (f.name, f.range, f.leaf))
+            .collect();
+        let expected = [
+            ("magic", 0..2, true),
+            ("count", 2..4, true),
+            ("entries[0].len", 4..5, true),
+            ("entries[0].body", 5..6, true),
+            ("entries[0]", 4..6, false),
+            ("entries[1].len", 6..7, true),
+            ("entries[1].body", 7..9, true),
+            ("entries[1]", 6..9, false),
+            ("name", 9..12, true),
+        ];
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(name, range, leaf)| (name.to_string(), range, leaf))
+            .collect();
+        assert_eq!(fields, expected);
+    }
+}
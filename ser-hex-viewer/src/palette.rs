@@ -0,0 +1,236 @@
+//! Command palette running any of the viewer's actions by fuzzy matched name
+
+use eframe::egui;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Select the byte at the offset
+    Goto(usize),
+    Open,
+    CloseTab,
+    Back,
+    Forward,
+    Search,
+    ExpandAll,
+    CollapseAll,
+    ToggleCoverage,
+    ToggleSeeks,
+    ToggleEndian,
+    ToggleHexOffsets,
+    ToggleRelativeOffsets,
+    FitColumns,
+    SpanColors,
+}
+impl Command {
+    const ALL: [Command; 14] = [
+        Command::Open,
+        Command::CloseTab,
+        Command::Back,
+        Command::Forward,
+        Command::Search,
+        Command::ExpandAll,
+        Command::CollapseAll,
+        Command::ToggleCoverage,
+        Command::ToggleSeeks,
+        Command::ToggleEndian,
+        Command::ToggleHexOffsets,
+        Command::ToggleRelativeOffsets,
+        Command::FitColumns,
+        Command::SpanColors,
+    ];
+
+    fn label(self) -> String {
+        match self {
+            Command::Goto(offset) => return format!("go to offset {offset:#x} ({offset})"),
+            Command::Open => "open trace…",
+            Command::CloseTab => "close tab",
+            Command::Back => "go back",
+            Command::Forward => "go forward",
+            Command::Search => "search",
+            Command::ExpandAll => "expand all spans",
+            Command::CollapseAll => "collapse all spans",
+            Command::ToggleCoverage => "toggle unread bytes (coverage)",
+            Command::ToggleSeeks => "toggle seek destinations",
+            Command::ToggleEndian => "toggle byte order (LE/BE)",
+            Command::ToggleHexOffsets => "toggle hex/decimal offsets",
+            Command::ToggleRelativeOffsets => "toggle relative offsets",
+            Command::FitColumns => "toggle fit columns to width",
+            Command::SpanColors => "span colors…",
+        }
+        .into()
+    }
+    fn shortcut(self) -> &'static str {
+        match self {
+            Command::Open => "ctrl+O",
+            Command::Back => "alt+left",
+            Command::Forward => "alt+right",
+            Command::Search => "ctrl+F",
+            Command::ExpandAll => "ctrl+shift+E",
+            Command::CollapseAll => "ctrl+shift+C",
+            _ => "",
+        }
+    }
+}
+
+/// Offset typed into the palette, decimal or `0x` prefixed hexadecimal
+fn parse_offset(query: &str) -> Option<usize> {
+    let query = query.trim();
+    match query
+        .strip_prefix("0x")
+        .or_else(|| query.strip_prefix("0X"))
+    {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => query.parse().ok(),
+    }
+}
+
+/// Score of `label` matching `query` as a subsequence ignoring case, higher for consecutive
+/// characters, characters starting words and matches starting early. `None` if it does not match
+fn score(query: &str, label: &str) -> Option<i32> {
+    let mut chars = label.char_indices();
+    let mut score = 0;
+    let mut prev = None;
+    for q in query.chars().filter(|c| !c.is_whitespace()) {
+        let q = q.to_ascii_lowercase();
+        let (i, _) = chars.find(|(_, c)| c.to_ascii_lowercase() == q)?;
+        score += 1;
+        if prev.is_some_and(|p| p + 1 == i) {
+            score += 3;
+        }
+        if i == 0 || label[..i].ends_with(' ') {
+            score += 2;
+        }
+        if prev.is_none() {
+            score -= i.min(5) as i32;
+        }
+        prev = Some(i);
+    }
+    Some(score)
+}
+
+/// Commands matching `query`, best first
+fn matching(query: &str) -> Vec<Command> {
+    let mut scored: Vec<_> = Command::ALL
+        .into_iter()
+        .filter_map(|c| Some((score(query, &c.label())?, c)))
+        .collect();
+    // stable, ties keep the order of `ALL`
+    scored.sort_by_key(|(score, _)| -score);
+    let goto = parse_offset(query).map(Command::Goto);
+    goto.into_iter()
+        .chain(scored.into_iter().map(|(_, c)| c))
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct Palette {
+    open: bool,
+    query: String,
+    /// Index into the matching commands of the highlighted one
+    selected: usize,
+}
+impl Palette {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.query.clear();
+        self.selected = 0;
+    }
+    /// Palette window toggled with ctrl+P, returns the command to run
+    pub fn ui(&mut self, ctx: &egui::Context) -> Option<Command> {
+        let shortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::P);
+        if ctx.input_mut(|i| i.consume_shortcut(&shortcut)) {
+            self.toggle();
+        }
+        if !self.open {
+            return None;
+        }
+        let (up, down, enter, escape) = ctx.input_mut(|i| {
+            use egui::{Key, Modifiers};
+            (
+                i.consume_key(Modifiers::NONE, Key::ArrowUp),
+                i.consume_key(Modifiers::NONE, Key::ArrowDown),
+                i.key_pressed(Key::Enter),
+                i.key_pressed(Key::Escape),
+            )
+        });
+        let commands = matching(&self.query);
+        if down {
+            self.selected += 1;
+        }
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        self.selected = self.selected.min(commands.len().saturating_sub(1));
+
+        let mut run = None;
+        egui::Window::new("command palette")
+            .title_bar(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, [0., 40.])
+            .fixed_size([400., 0.])
+            .show(ctx, |ui| {
+                let query = ui.add(
+                    egui::TextEdit::singleline(&mut self.query)
+                        .hint_text("command or offset to go to")
+                        .desired_width(f32::INFINITY),
+                );
+                query.request_focus();
+                if query.changed() {
+                    self.selected = 0;
+                }
+                if commands.is_empty() {
+                    ui.weak("no matching commands");
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(300.)
+                    .show(ui, |ui| {
+                        for (i, command) in commands.iter().enumerate() {
+                            let selected = i == self.selected;
+                            let row = ui.horizontal(|ui| {
+                                let label = ui.selectable_label(selected, command.label());
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::Center),
+                                    |ui| {
+                                        ui.weak(command.shortcut());
+                                    },
+                                );
+                                label
+                            });
+                            if selected {
+                                row.inner.scroll_to_me(None);
+                            }
+                            if row.inner.clicked() {
+                                run = Some(*command);
+                            }
+                        }
+                    });
+            });
+        if enter {
+            run = commands.get(self.selected).copied();
+        }
+        if run.is_some() || escape {
+            self.open = false;
+        }
+        run
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matching() {
+        assert_eq!(parse_offset("0x1A3f0"), Some(0x1a3f0));
+        assert_eq!(parse_offset(" 42 "), Some(42));
+        assert_eq!(parse_offset("zz"), None);
+
+        assert_eq!(score("xyz", "expand all spans"), None);
+        assert!(score("col", "collapse all spans") > score("col", "span colors…"));
+
+        assert_eq!(matching("cov"), [Command::ToggleCoverage]);
+        assert_eq!(matching("expall")[0], Command::ExpandAll);
+        assert_eq!(matching("16")[0], Command::Goto(16));
+        assert_eq!(matching("").len(), Command::ALL.len());
+    }
+}
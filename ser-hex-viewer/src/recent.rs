@@ -0,0 +1,58 @@
+//! Recently opened traces, remembered in the user config directory
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result};
+use fs_err as fs;
+
+/// Number of traces remembered
+const MAX_RECENT: usize = 10;
+
+#[derive(Debug, Default)]
+pub struct Recent {
+    /// Most recently opened first
+    paths: Vec<PathBuf>,
+}
+impl Recent {
+    fn file() -> Option<PathBuf> {
+        Some(
+            dirs::config_dir()?
+                .join("ser-hex-viewer")
+                .join("recent.json"),
+        )
+    }
+    fn read() -> Result<Vec<PathBuf>> {
+        match Self::file().filter(|file| file.exists()) {
+            Some(file) => Ok(serde_json::from_slice(&fs::read(file)?)?),
+            None => Ok(vec![]),
+        }
+    }
+    /// Load the recent traces, empty if none have been saved or they could not be read
+    pub fn load() -> Self {
+        let paths = Self::read().unwrap_or_else(|err| {
+            eprintln!("failed to load recent traces {err:?}");
+            vec![]
+        });
+        Self { paths }
+    }
+    fn save(&self) -> Result<()> {
+        let file = Self::file().context("no config directory")?;
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(file, serde_json::to_string_pretty(&self.paths)?)?;
+        Ok(())
+    }
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+    /// Move `path` to the front of the recent traces
+    pub fn add(&mut self, path: &Path) {
+        self.paths.retain(|p| p != path);
+        self.paths.insert(0, path.to_path_buf());
+        self.paths.truncate(MAX_RECENT);
+        if let Err(err) = self.save() {
+            eprintln!("failed to save recent traces {err:?}");
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Traces read from stdin or downloaded, saved to a temporary file to be opened like any other
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    io,
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use fs_err as fs;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Remote {
+    Stdin,
+    Url(String),
+}
+impl Remote {
+    /// Remote trace named by a command line argument, `-` for stdin or an http(s) URL
+    pub fn parse(arg: &str) -> Option<Self> {
+        if arg == "-" {
+            Some(Remote::Stdin)
+        } else if arg.starts_with("http://") || arg.starts_with("https://") {
+            Some(Remote::Url(arg.into()))
+        } else {
+            None
+        }
+    }
+    /// Temporary file the trace is saved to, named after the downloaded file
+    pub fn path(&self) -> PathBuf {
+        let dir = std::env::temp_dir().join("ser-hex-viewer");
+        match self {
+            Remote::Stdin => dir
+                .join(format!("stdin-{}", std::process::id()))
+                .join("stdin.json"),
+            Remote::Url(url) => {
+                let mut s = DefaultHasher::new();
+                url.hash(&mut s);
+                let name = url
+                    .split(['?', '#'])
+                    .next()
+                    .and_then(|url| url.rsplit('/').next())
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or("trace.json");
+                dir.join(format!("{:016x}", s.finish())).join(name)
+            }
+        }
+    }
+    /// Save the trace to [`Self::path`]
+    pub fn fetch(&self) -> Result<()> {
+        let path = self.path();
+        fs::create_dir_all(path.parent().unwrap())?;
+        let mut file = fs::File::create(&path)?;
+        match self {
+            Remote::Stdin => io::copy(&mut io::stdin().lock(), &mut file)?,
+            Remote::Url(url) => io::copy(&mut ureq::get(url).call()?.into_reader(), &mut file)?,
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_remote() {
+        assert_eq!(Remote::parse("-"), Some(Remote::Stdin));
+        assert_eq!(Remote::parse("trace.json"), None);
+        let url = Remote::parse("https://ci.example.com/artifacts/trace.json?token=1").unwrap();
+        assert!(url.path().ends_with("trace.json"));
+        let other = Remote::Url("https://ci.example.com/other/trace.json".into());
+        assert_ne!(url.path(), other.path());
+    }
+}
@@ -0,0 +1,187 @@
+//! Searching trace data for byte patterns and strings
+
+use std::ops::Range;
+
+use eframe::egui;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Bytes written as hex digits, e.g. `de ad be ef`
+    #[default]
+    Hex,
+    Ascii,
+    /// UTF-16 little endian string
+    Utf16,
+}
+impl SearchMode {
+    const ALL: [SearchMode; 3] = [SearchMode::Hex, SearchMode::Ascii, SearchMode::Utf16];
+
+    fn label(self) -> &'static str {
+        match self {
+            SearchMode::Hex => "hex",
+            SearchMode::Ascii => "ascii",
+            SearchMode::Utf16 => "utf-16",
+        }
+    }
+}
+
+/// Bytes to search for, `None` if the query is empty or not valid hex
+fn parse_pattern(query: &str, mode: SearchMode) -> Option<Vec<u8>> {
+    let pattern: Vec<u8> = match mode {
+        SearchMode::Hex => {
+            let digits: Vec<u8> = query
+                .split_whitespace()
+                .map(|word| word.strip_prefix("0x").unwrap_or(word))
+                .flat_map(str::bytes)
+                .collect();
+            if digits.len() % 2 != 0 {
+                return None;
+            }
+            digits
+                .chunks(2)
+                .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+                .collect::<Option<_>>()?
+        }
+        SearchMode::Ascii => query.as_bytes().to_vec(),
+        SearchMode::Utf16 => query.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+    };
+    (!pattern.is_empty()).then_some(pattern)
+}
+
+/// Offsets of every (possibly overlapping) occurrence of `pattern`
+pub fn find_all(data: &[u8], pattern: &[u8]) -> Vec<usize> {
+    data.windows(pattern.len())
+        .enumerate()
+        .filter_map(|(i, window)| (window == pattern).then_some(i))
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct Search {
+    query: String,
+    mode: SearchMode,
+    /// Query and mode the matches were found with, searched again once either changes
+    searched: Option<(String, SearchMode)>,
+    pattern_len: usize,
+    matches: Vec<usize>,
+    /// Index into `matches` of the selected match
+    current: Option<usize>,
+    /// Focus the query when next drawn
+    focus: bool,
+}
+impl Search {
+    /// Range of the selected match
+    pub fn current(&self) -> Option<Range<usize>> {
+        let start = self.matches[self.current?];
+        Some(start..start + self.pattern_len)
+    }
+    fn run(&mut self, data: &[u8]) {
+        let searched = (self.query.clone(), self.mode);
+        if self.searched.as_ref() == Some(&searched) {
+            return;
+        }
+        self.searched = Some(searched);
+        self.current = None;
+        match parse_pattern(&self.query, self.mode) {
+            Some(pattern) => {
+                self.matches = find_all(data, &pattern);
+                self.pattern_len = pattern.len();
+            }
+            None => self.matches.clear(),
+        }
+    }
+    /// Select the next or previous match, wrapping around at either end
+    fn step(&mut self, data: &[u8], forward: bool) -> Option<Range<usize>> {
+        self.run(data);
+        let len = self.matches.len();
+        if len == 0 {
+            return None;
+        }
+        self.current = Some(match (self.current, forward) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(i), true) => (i + 1) % len,
+            (Some(i), false) => (i + len - 1) % len,
+        });
+        self.current()
+    }
+    /// Focus the search query, as ctrl+f does
+    pub fn focus(&mut self) {
+        self.focus = true;
+    }
+    /// Search bar, returns the match to navigate to if the selection changed
+    pub fn ui(&mut self, ui: &mut egui::Ui, data: &[u8]) -> Option<Range<usize>> {
+        let mut step = None;
+        ui.horizontal(|ui| {
+            let query = ui.add(
+                egui::TextEdit::singleline(&mut self.query)
+                    .hint_text("search (ctrl+f)")
+                    .desired_width(200.),
+            );
+            if ui.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::F))
+                || std::mem::take(&mut self.focus)
+            {
+                query.request_focus();
+            }
+            if query.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                step = Some(!ui.input(|i| i.modifiers.shift));
+                query.request_focus();
+            }
+            egui::ComboBox::from_id_salt("search mode")
+                .selected_text(self.mode.label())
+                .show_ui(ui, |ui| {
+                    for mode in SearchMode::ALL {
+                        ui.selectable_value(&mut self.mode, mode, mode.label());
+                    }
+                });
+            if ui
+                .button("⬆")
+                .on_hover_text("previous (shift+enter)")
+                .clicked()
+            {
+                step = Some(false);
+            }
+            if ui.button("⬇").on_hover_text("next (enter)").clicked() {
+                step = Some(true);
+            }
+
+            let searched = self.searched.as_ref() == Some(&(self.query.clone(), self.mode));
+            if self.query.is_empty() || !searched {
+                return;
+            }
+            if parse_pattern(&self.query, self.mode).is_none() {
+                ui.colored_label(egui::Color32::RED, "invalid hex");
+            } else if self.matches.is_empty() {
+                ui.label("no matches");
+            } else {
+                let current = self.current.map(|i| (i + 1).to_string());
+                ui.label(format!(
+                    "{}/{}",
+                    current.as_deref().unwrap_or("-"),
+                    self.matches.len()
+                ));
+            }
+        });
+        step.and_then(|forward| self.step(data, forward))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_pattern() {
+        let hex = |q| parse_pattern(q, SearchMode::Hex);
+        assert_eq!(hex("de ad BE ef"), Some(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(hex("0x0102 03"), Some(vec![1, 2, 3]));
+        assert_eq!(hex("123"), None);
+        assert_eq!(hex("zz"), None);
+        assert_eq!(hex(""), None);
+        assert_eq!(
+            parse_pattern("ab", SearchMode::Utf16),
+            Some(vec![b'a', 0, b'b', 0])
+        );
+        assert_eq!(find_all(b"aaab", b"aa"), vec![0, 1]);
+    }
+}
@@ -0,0 +1,141 @@
+//! Printable strings found in trace data, like the `strings` tool
+
+use std::ops::Range;
+
+use eframe::egui;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Found {
+    range: Range<usize>,
+    text: String,
+    /// UTF-16 little endian rather than ASCII
+    utf16: bool,
+}
+
+fn printable(b: u8) -> bool {
+    b == b'\t' || (0x20..0x7f).contains(&b)
+}
+
+/// Runs of at least `min_len` printable ASCII characters, either as bytes or as UTF-16 code units
+fn extract(data: &[u8], min_len: usize) -> Vec<Found> {
+    let mut found = vec![];
+    let mut push = |range: Range<usize>, text: &mut String, utf16| {
+        if text.len() >= min_len {
+            found.push(Found {
+                range,
+                text: std::mem::take(text),
+                utf16,
+            });
+        }
+        text.clear();
+    };
+
+    let mut text = String::new();
+    for (i, &b) in data.iter().enumerate() {
+        match printable(b) {
+            true => text.push(b as char),
+            false => push(i - text.len()..i, &mut text, false),
+        }
+    }
+    push(data.len() - text.len()..data.len(), &mut text, false);
+
+    for align in 0..2 {
+        let units = data.get(align..).unwrap_or_default().chunks_exact(2);
+        let mut end = align;
+        for unit in units {
+            end += 2;
+            match (printable(unit[0]), unit[1]) {
+                (true, 0) => text.push(unit[0] as char),
+                _ => push(end - 2 - 2 * text.len()..end - 2, &mut text, true),
+            }
+        }
+        push(end - 2 * text.len()..end, &mut text, true);
+    }
+
+    found.sort_by_key(|f| f.range.start);
+    found
+}
+
+#[derive(Debug)]
+pub struct Strings {
+    /// Extracted when first shown, again once `min_len` changes
+    found: Option<Vec<Found>>,
+    min_len: usize,
+    filter: String,
+}
+impl Default for Strings {
+    fn default() -> Self {
+        Self {
+            found: None,
+            min_len: 4,
+            filter: String::new(),
+        }
+    }
+}
+impl Strings {
+    /// Filterable list of the strings in `data`, returns the range of the string clicked
+    pub fn ui(&mut self, ui: &mut egui::Ui, data: &[u8]) -> Option<Range<usize>> {
+        let mut goto = None;
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.filter)
+                    .hint_text("filter strings")
+                    .desired_width(150.),
+            );
+            let min_len = egui::DragValue::new(&mut self.min_len)
+                .range(1..=256)
+                .prefix("min length ");
+            if ui.add(min_len).changed() {
+                self.found = None;
+            }
+        });
+        let found = self
+            .found
+            .get_or_insert_with(|| extract(data, self.min_len));
+        let filter = self.filter.to_lowercase();
+        let shown: Vec<_> = found
+            .iter()
+            .filter(|f| f.text.to_lowercase().contains(&filter))
+            .collect();
+        ui.label(format!("{} string(s)", shown.len()));
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        egui::ScrollArea::vertical()
+            .max_height(300.)
+            .id_salt("strings")
+            .show_rows(ui, row_height, shown.len(), |ui, rows| {
+                for found in &shown[rows] {
+                    let kind = match found.utf16 {
+                        true => "utf-16",
+                        false => "ascii",
+                    };
+                    let text = format!("{:#x} {kind} {:?}", found.range.start, found.text);
+                    if ui.selectable_label(false, text).clicked() {
+                        goto = Some(found.range.clone());
+                    }
+                }
+            });
+        goto
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let data = b"\0abcd\x01\x01x\0y\0z\0w\0\xffhello";
+        let found: Vec<_> = extract(data, 4)
+            .into_iter()
+            .map(|f| (f.range, f.text, f.utf16))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (1..5, "abcd".to_string(), false),
+                (7..15, "xyzw".to_string(), true),
+                (16..21, "hello".to_string(), false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,84 @@
+//! Filtering the tree by span name
+
+use eframe::egui;
+
+use crate::FullAction;
+
+#[derive(Debug, Default)]
+pub struct TreeFilter {
+    query: String,
+    /// Query the tree was last filtered with
+    applied: String,
+    /// Tree paths of the matching spans in tree order
+    matches: Vec<Vec<usize>>,
+    /// Index into `matches` of the selected match
+    current: Option<usize>,
+}
+impl TreeFilter {
+    fn apply(&mut self, tree: &mut FullAction) {
+        if self.query == self.applied {
+            return;
+        }
+        self.applied = self.query.clone();
+        self.matches.clear();
+        self.current = None;
+        tree.filter(&self.query.to_lowercase(), &mut vec![0], &mut self.matches);
+    }
+    /// Query the tree was last filtered with
+    pub fn applied(&self) -> &str {
+        &self.applied
+    }
+    fn step(&mut self, forward: bool) -> Option<Vec<usize>> {
+        let len = self.matches.len();
+        if len == 0 {
+            return None;
+        }
+        let current = match (self.current, forward) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(i), true) => (i + 1) % len,
+            (Some(i), false) => (i + len - 1) % len,
+        };
+        self.current = Some(current);
+        Some(self.matches[current].clone())
+    }
+    /// Filter box, filters `tree` as the query changes and returns the tree path of the match to
+    /// select when cycling through them
+    pub fn ui(&mut self, ui: &mut egui::Ui, tree: &mut FullAction) -> Option<Vec<usize>> {
+        let mut step = None;
+        ui.horizontal(|ui| {
+            let query = ui.add(
+                egui::TextEdit::singleline(&mut self.query)
+                    .hint_text("filter spans")
+                    .desired_width(150.),
+            );
+            if query.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                step = Some(!ui.input(|i| i.modifiers.shift));
+                query.request_focus();
+            }
+            if ui.input(|i| i.key_pressed(egui::Key::F3)) {
+                step = Some(!ui.input(|i| i.modifiers.shift));
+            }
+            self.apply(tree);
+            if ui
+                .button("⬆")
+                .on_hover_text("previous match (shift+F3)")
+                .clicked()
+            {
+                step = Some(false);
+            }
+            if ui.button("⬇").on_hover_text("next match (F3)").clicked() {
+                step = Some(true);
+            }
+            if !self.query.is_empty() {
+                let current = self.current.map(|i| (i + 1).to_string());
+                ui.label(format!(
+                    "{}/{}",
+                    current.as_deref().unwrap_or("-"),
+                    self.matches.len()
+                ));
+            }
+        });
+        step.and_then(|forward| self.step(forward))
+    }
+}
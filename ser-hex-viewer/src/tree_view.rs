@@ -0,0 +1,544 @@
+//! Tree panel drawing only the rows scrolled into view, so traces with millions of actions stay
+//! responsive. The tree is flattened into the rows of the open spans, rebuilt when spans are
+//! opened or closed or the tree filter changes.
+//!
+//! Up/down (or k/j) move the selection between rows, right (l) opens a span or moves into it and
+//! left (h) closes it or moves to its parent
+
+use std::{collections::HashSet, ops::Range};
+
+use eframe::egui;
+
+use crate::{seek_label, sub_trace_label, FullAction, TreeResponse};
+
+/// Action at tree `path`, the first index being that of the root itself
+fn node<'a>(root: &'a FullAction, path: &[usize]) -> Option<&'a FullAction> {
+    path.iter()
+        .skip(1)
+        .try_fold(root, |action, i| match action {
+            FullAction::Span(span) => span.actions.get(*i),
+            _ => None,
+        })
+}
+
+/// Range of the span at tree `path`
+pub fn span_range(root: &FullAction, path: &[usize]) -> Option<Range<usize>> {
+    match node(root, path)? {
+        FullAction::Span(span) => span.range.clone(),
+        _ => None,
+    }
+}
+
+/// Whether the nodes leading to `path` are of the same kinds, and spans of the same names, in
+/// both trees
+fn matches(a: &FullAction, b: &FullAction, path: &[usize]) -> bool {
+    (1..=path.len()).all(|len| match (node(a, &path[..len]), node(b, &path[..len])) {
+        (Some(FullAction::Span(a)), Some(FullAction::Span(b))) => a.name == b.name,
+        (Some(a), Some(b)) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        _ => false,
+    })
+}
+
+/// Breadcrumb label of `action`, the child at `index` of `parent`. Spans sharing their name with
+/// siblings are numbered among them
+fn crumb(parent: Option<&FullAction>, index: usize, action: &FullAction) -> String {
+    let span = match action {
+        FullAction::Span(span) => span,
+        FullAction::Read(range) => return format!("read {}", range.len()),
+        FullAction::Seek(from, seek) => return seek_label(*from, seek),
+        FullAction::Error(_, error) => return format!("error: {}", error.kind),
+        FullAction::SubTrace(sub) => return sub_trace_label(sub),
+    };
+    let siblings = match parent {
+        Some(FullAction::Span(parent)) => &parent.actions[..],
+        _ => &[],
+    };
+    let same = |a: &&FullAction| matches!(a, FullAction::Span(s) if s.name == span.name);
+    match siblings.iter().filter(same).count() > 1 {
+        true => format!(
+            "{}[{}]",
+            span.name,
+            siblings[..index].iter().filter(same).count()
+        ),
+        false => span.name.clone(),
+    }
+}
+
+/// Bytes selected when a row is selected
+fn node_range(action: &FullAction) -> Option<Range<usize>> {
+    match action {
+        FullAction::Read(range) => Some(range.clone()),
+        FullAction::Seek(_, seek) => Some(seek.to..seek.to + 1),
+        FullAction::Span(span) => span.range.clone(),
+        FullAction::Error(..) | FullAction::SubTrace(_) => None,
+    }
+}
+
+#[derive(Debug)]
+pub struct TreeView {
+    /// Tree paths of the open spans
+    open: HashSet<Vec<usize>>,
+    /// Tree paths of the visible rows in order
+    rows: Vec<Vec<usize>>,
+    /// `rows` need to be rebuilt
+    stale: bool,
+    /// Tree filter query the rows were built with
+    filter: String,
+    selected: Option<Vec<usize>>,
+    /// Scroll to the selected row when next drawn
+    scroll: bool,
+}
+impl Default for TreeView {
+    fn default() -> Self {
+        Self {
+            open: HashSet::from([vec![0]]),
+            rows: vec![],
+            stale: true,
+            filter: String::new(),
+            selected: None,
+            scroll: false,
+        }
+    }
+}
+impl TreeView {
+    fn rebuild(&mut self, root: &FullAction) {
+        fn visit(
+            action: &FullAction,
+            path: &mut Vec<usize>,
+            open: &HashSet<Vec<usize>>,
+            rows: &mut Vec<Vec<usize>>,
+        ) {
+            let span = match action {
+                FullAction::Span(span) if span.hidden => return,
+                FullAction::Span(span) => span,
+                _ => return rows.push(path.clone()),
+            };
+            rows.push(path.clone());
+            if !open.contains(path) {
+                return;
+            }
+            for (i, action) in span.actions.iter().enumerate() {
+                if span.hide_leaves && !matches!(action, FullAction::Span(_)) {
+                    continue;
+                }
+                path.push(i);
+                visit(action, path, open, rows);
+                path.pop();
+            }
+        }
+        self.rows.clear();
+        visit(root, &mut vec![0], &self.open, &mut self.rows);
+        self.stale = false;
+    }
+    fn set_open(&mut self, path: &[usize], open: bool) {
+        match open {
+            true => self.open.insert(path.to_vec()),
+            false => self.open.remove(path),
+        };
+        self.stale = true;
+    }
+    /// Open the spans `depth` levels deep and shallower, closing the deeper ones
+    fn expand(&mut self, root: &FullAction, depth: usize) {
+        fn visit(
+            action: &FullAction,
+            path: &mut Vec<usize>,
+            depth: usize,
+            open: &mut HashSet<Vec<usize>>,
+        ) {
+            let FullAction::Span(span) = action else {
+                return;
+            };
+            if depth == 0 {
+                return;
+            }
+            open.insert(path.clone());
+            for (i, action) in span.actions.iter().enumerate() {
+                path.push(i);
+                visit(action, path, depth - 1, open);
+                path.pop();
+            }
+        }
+        self.open.clear();
+        visit(root, &mut vec![0], depth, &mut self.open);
+        self.stale = true;
+    }
+    /// Select the node at `path`, opening the spans leading to it
+    fn select(&mut self, path: Vec<usize>) {
+        for len in 1..=path.len() {
+            self.open.insert(path[..len].to_vec());
+        }
+        self.selected = Some(path);
+        self.scroll = true;
+        self.stale = true;
+    }
+    /// Range of the selected row
+    pub fn selected_range(&self, root: &FullAction) -> Option<Range<usize>> {
+        node(root, self.selected.as_ref()?).and_then(node_range)
+    }
+    /// Carry the open spans and selected row of `old`, the view of `old_root` before it was
+    /// reloaded as `root`, over where the structure still matches
+    pub fn restore(&mut self, old: TreeView, old_root: &FullAction, root: &FullAction) {
+        self.open = old
+            .open
+            .into_iter()
+            .filter(|path| matches(old_root, root, path))
+            .collect();
+        self.selected = old.selected.filter(|path| matches(old_root, root, path));
+        self.scroll = self.selected.is_some();
+        self.stale = true;
+    }
+    /// Ancestry of the selected row, clicking an ancestor selects it. Returns the range of the
+    /// clicked node
+    pub fn breadcrumb_ui(&mut self, ui: &mut egui::Ui, root: &FullAction) -> Option<Range<usize>> {
+        let Some(path) = self.selected.clone() else {
+            ui.weak("select a byte or span to show its path");
+            return None;
+        };
+        let mut clicked = None;
+        ui.horizontal_wrapped(|ui| {
+            for len in 1..=path.len() {
+                let Some(action) = node(root, &path[..len]) else {
+                    break;
+                };
+                let parent = node(root, &path[..len - 1]).filter(|_| len > 1);
+                if len > 1 {
+                    ui.weak("▸");
+                }
+                let label = crumb(parent, path[len - 1], action);
+                if ui.selectable_label(len == path.len(), label).clicked() {
+                    clicked = Some(len);
+                }
+            }
+        });
+        let len = clicked?;
+        let range = node(root, &path[..len]).and_then(node_range);
+        self.select(path[..len].to_vec());
+        range
+    }
+    /// Handle the navigation keys, returns the range of the newly selected row
+    pub fn input(&mut self, ctx: &egui::Context, root: &FullAction) -> Option<Range<usize>> {
+        if ctx.wants_keyboard_input() {
+            return None;
+        }
+        let (up, down, left, right) = ctx.input(|i| {
+            use egui::Key;
+            let key =
+                |keys: [Key; 2]| i.modifiers.is_none() && keys.iter().any(|k| i.key_pressed(*k));
+            (
+                key([Key::ArrowUp, Key::K]),
+                key([Key::ArrowDown, Key::J]),
+                key([Key::ArrowLeft, Key::H]),
+                key([Key::ArrowRight, Key::L]),
+            )
+        });
+        if !(up || down || left || right) {
+            return None;
+        }
+        if self.stale {
+            self.rebuild(root);
+        }
+        let current = self
+            .selected
+            .as_ref()
+            .and_then(|s| self.rows.iter().position(|r| r == s));
+        let Some(i) = current else {
+            self.selected = self.rows.first().cloned();
+            self.scroll = true;
+            return node_range(node(root, self.selected.as_ref()?)?);
+        };
+        let path = self.rows[i].clone();
+        let is_span = matches!(node(root, &path), Some(FullAction::Span(_)));
+        let is_open = is_span && self.open.contains(&path);
+        let target = if up {
+            i.saturating_sub(1)
+        } else if down {
+            (i + 1).min(self.rows.len() - 1)
+        } else if left && is_open {
+            self.set_open(&path, false);
+            return None;
+        } else if left {
+            let parent = &path[..path.len() - 1];
+            self.rows.iter().position(|r| r == parent)?
+        } else if is_span && !is_open {
+            self.set_open(&path, true);
+            return None;
+        } else {
+            // first child, if any are shown
+            (i + 1 < self.rows.len() && self.rows[i + 1].starts_with(&path)).then_some(i + 1)?
+        };
+        self.selected = Some(self.rows[target].clone());
+        self.scroll = true;
+        node_range(node(root, &self.rows[target])?)
+    }
+    /// Draw the visible rows of the tree. `path_select` is selected and scrolled to, and when
+    /// `expand` is given the tree is expanded to that depth. `filter` is the query the tree was
+    /// last filtered with
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        root: &FullAction,
+        filter: &str,
+        path_select: Option<Vec<usize>>,
+        expand: Option<usize>,
+    ) -> Option<TreeResponse> {
+        if let Some(depth) = expand {
+            self.expand(root, depth);
+        }
+        if let Some(path) = path_select {
+            self.select(path);
+        }
+        if self.filter != filter {
+            self.filter = filter.to_string();
+            self.stale = true;
+        }
+        if self.stale {
+            self.rebuild(root);
+        }
+
+        let row_height = ui.spacing().interact_size.y;
+        let mut scroll_area = egui::ScrollArea::both().auto_shrink(false);
+        if std::mem::take(&mut self.scroll) {
+            let row = self
+                .selected
+                .as_ref()
+                .and_then(|s| self.rows.iter().position(|r| r == s));
+            if let Some(row) = row {
+                // a few rows of context above the selection
+                let spacing = ui.spacing().item_spacing.y;
+                let offset = row.saturating_sub(3) as f32 * (row_height + spacing);
+                scroll_area = scroll_area.vertical_scroll_offset(offset);
+            }
+        }
+
+        let mut res = None;
+        let mut toggle = None;
+        let mut clicked = None;
+        scroll_area.show_rows(ui, row_height, self.rows.len(), |ui, rows| {
+            for path in &self.rows[rows] {
+                let Some(action) = node(root, path) else {
+                    continue;
+                };
+                let selected = self.selected.as_ref() == Some(path);
+                ui.horizontal(|ui| {
+                    ui.add_space((path.len() - 1) as f32 * ui.spacing().indent);
+                    let row = row_ui(ui, action, self.open.contains(path), selected);
+                    if row.toggled {
+                        toggle = Some(path.clone());
+                    }
+                    if row.clicked {
+                        clicked = Some(path.clone());
+                    }
+                    if row.res.is_some() {
+                        res = row.res;
+                    }
+                });
+            }
+        });
+        if let Some(path) = toggle {
+            let open = !self.open.contains(&path);
+            self.set_open(&path, open);
+        }
+        if let Some(path) = clicked {
+            self.selected = Some(path);
+        }
+        res
+    }
+}
+
+struct RowResponse {
+    res: Option<TreeResponse>,
+    /// Span opened or closed
+    toggled: bool,
+    clicked: bool,
+}
+
+fn row_ui(ui: &mut egui::Ui, action: &FullAction, open: bool, selected: bool) -> RowResponse {
+    let mut res = None;
+    let mut toggled = false;
+    let response = match action {
+        FullAction::Read(range) => {
+            let button = egui::Button::new(format!("read {}", range.len())).selected(selected);
+            let response = ui.add(button);
+            response.context_menu(|ui| {
+                if ui.button("export bytes…").clicked() {
+                    res = Some(TreeResponse::Export(range.clone()));
+                    ui.close_menu();
+                }
+                if ui.button("add note…").clicked() {
+                    res = Some(TreeResponse::Note(range.clone(), None));
+                    ui.close_menu();
+                }
+            });
+            if response.clicked() {
+                res = Some(TreeResponse::Goto(range.clone()));
+            }
+            response
+        }
+        FullAction::Seek(from, seek) => {
+            let button = egui::Button::new(seek_label(*from, seek)).selected(selected);
+            let response = ui.add(button).on_hover_text("show the jump");
+            if response.clicked() {
+                res = Some(TreeResponse::Seek {
+                    from: *from,
+                    to: seek.to,
+                });
+            }
+            response
+        }
+        FullAction::Error(offset, error) => {
+            let mut text = egui::RichText::new(format!(
+                "error {} reading {} at {}",
+                error.kind, error.size, offset
+            ))
+            .color(egui::Color32::RED);
+            if selected {
+                text = text.strong();
+            }
+            ui.label(text).on_hover_text(&error.message)
+        }
+        FullAction::SubTrace(sub) => {
+            let button = egui::Button::new(sub_trace_label(sub)).selected(selected);
+            let response = ui.add(button);
+            if response.clicked() {
+                res = Some(TreeResponse::OpenSubTrace(sub.clone()));
+            }
+            response
+        }
+        FullAction::Span(span) => {
+            let icon = match open {
+                true => "▼",
+                false => "▶",
+            };
+            toggled |= ui.add(egui::Button::new(icon).frame(false)).clicked();
+            let mut name = egui::RichText::new(span.name.as_str());
+            if span.diverged {
+                name = name.color(egui::Color32::RED);
+            }
+            if span.matched {
+                name = name.strong().underline();
+            }
+            let mut response = ui.selectable_label(selected, name);
+            toggled |= response.clicked();
+            if let Some(range) = &span.range {
+                response.context_menu(|ui| {
+                    if ui.button("select bytes").clicked() {
+                        res = Some(TreeResponse::Goto(range.clone()));
+                        ui.close_menu();
+                    }
+                    if ui.button("export bytes…").clicked() {
+                        res = Some(TreeResponse::Export(range.clone()));
+                        ui.close_menu();
+                    }
+                    if ui.button("add note…").clicked() {
+                        let name = Some(span.name.clone());
+                        res = Some(TreeResponse::Note(range.clone(), name));
+                        ui.close_menu();
+                    }
+                });
+            }
+            if let Some(location) = &span.location {
+                response = response.on_hover_text(location);
+            }
+            response
+        }
+    };
+    RowResponse {
+        res,
+        toggled,
+        clicked: response.clicked(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::FullTreeSpan;
+
+    fn span(name: &str, actions: Vec<FullAction>) -> FullAction {
+        FullAction::Span(FullTreeSpan {
+            name: name.into(),
+            location: None,
+            range: None,
+            diverged: false,
+            matched: false,
+            hidden: false,
+            hide_leaves: false,
+            actions,
+        })
+    }
+
+    #[test]
+    fn test_rows() {
+        let root = span(
+            "root",
+            vec![
+                span("a", vec![FullAction::Read(0..1), FullAction::Read(1..2)]),
+                FullAction::Read(2..4),
+            ],
+        );
+        assert!(matches!(node(&root, &[0, 0, 1]), Some(FullAction::Read(r)) if *r == (1..2)));
+
+        let mut view = TreeView::default();
+        view.rebuild(&root);
+        assert_eq!(view.rows, [vec![0], vec![0, 0], vec![0, 1]]);
+
+        view.select(vec![0, 0, 1]);
+        view.rebuild(&root);
+        assert_eq!(view.rows.len(), 5);
+
+        view.expand(&root, 1);
+        view.rebuild(&root);
+        assert_eq!(view.rows, [vec![0], vec![0, 0], vec![0, 1]]);
+
+        view.expand(&root, 0);
+        view.rebuild(&root);
+        assert_eq!(view.rows, [vec![0]]);
+    }
+
+    #[test]
+    fn test_restore() {
+        let old = span(
+            "root",
+            vec![
+                span("a", vec![FullAction::Read(0..4)]),
+                span("b", vec![FullAction::Read(4..8)]),
+            ],
+        );
+        let new = span(
+            "root",
+            vec![
+                span("a", vec![FullAction::Read(0..6)]),
+                span("c", vec![FullAction::Read(6..8)]),
+            ],
+        );
+        let mut old_view = TreeView::default();
+        old_view.set_open(&[0, 0], true);
+        old_view.set_open(&[0, 1], true);
+        old_view.select(vec![0, 0, 0]);
+        let mut view = TreeView::default();
+        view.restore(old_view, &old, &new);
+        assert!(view.open.contains(&vec![0, 0]));
+        assert!(!view.open.contains(&vec![0, 1]));
+        assert_eq!(view.selected_range(&new), Some(0..6));
+    }
+
+    #[test]
+    fn test_crumbs() {
+        let root = span(
+            "root",
+            vec![
+                span("entry", vec![]),
+                span("name", vec![]),
+                span("entry", vec![FullAction::Read(0..4)]),
+            ],
+        );
+        let FullAction::Span(root_span) = &root else {
+            unreachable!()
+        };
+        let crumbs: Vec<_> = (0..3)
+            .map(|i| crumb(Some(&root), i, &root_span.actions[i]))
+            .collect();
+        assert_eq!(crumbs, ["entry[0]", "name", "entry[1]"]);
+        assert_eq!(crumb(None, 0, &root), "root");
+    }
+}
@@ -0,0 +1,110 @@
+//! Spans for [binrw](::binrw) parsers
+//!
+//! ```ignore
+//! use ser_hex::binrw::TracedBinReaderExt;
+//!
+//! #[ser_hex::binrw::spans]
+//! #[derive(BinRead)]
+//! struct Header {
+//!     magic: u32,
+//!     #[br(parse_with = ser_hex::binrw::read_traced)]
+//!     entries: Entries,
+//! }
+//!
+//! // root span named "Header" containing spans "magic" and "entries" > "Entries"
+//! let header: Header = reader.read_traced_le()?;
+//! ```
+
+use std::{
+    borrow::Cow,
+    io::{Read, Seek},
+    ops::{Deref, DerefMut},
+};
+
+use ::binrw::{BinRead, BinResult, Endian};
+
+use crate::short_type_name;
+
+pub use ser_hex_macros::binrw_spans as spans;
+
+/// Read `T` inside a span named after its type, usable as
+/// `#[br(parse_with = ser_hex::binrw::read_traced)]`
+pub fn read_traced<R: Read + Seek, T: BinRead>(
+    reader: &mut R,
+    endian: Endian,
+    args: T::Args<'_>,
+) -> BinResult<T> {
+    read_named(
+        reader,
+        endian,
+        args,
+        short_type_name(std::any::type_name::<T>()),
+    )
+}
+
+/// Read `T` inside a span with the given name
+pub fn read_named<R: Read + Seek, T: BinRead>(
+    reader: &mut R,
+    endian: Endian,
+    args: T::Args<'_>,
+    name: impl Into<Cow<'static, str>>,
+) -> BinResult<T> {
+    let name = name.into();
+    let _guard = tracing::info_span!("binrw", ser_hex.name = %name).entered();
+    T::read_options(reader, endian, args)
+}
+
+/// Wrapper which reads `T` inside a span named after its type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Traced<T>(pub T);
+impl<T: BinRead> BinRead for Traced<T> {
+    type Args<'a> = T::Args<'a>;
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        endian: Endian,
+        args: Self::Args<'_>,
+    ) -> BinResult<Self> {
+        read_traced(reader, endian, args).map(Self)
+    }
+}
+impl<T> Deref for Traced<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+impl<T> DerefMut for Traced<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// Variants of binrw's `BinReaderExt` which read inside a span named after the type
+pub trait TracedBinReaderExt: Read + Seek + Sized {
+    fn read_traced_type<T: BinRead>(&mut self, endian: Endian) -> BinResult<T>
+    where
+        for<'a> T::Args<'a>: Default,
+    {
+        read_traced(self, endian, Default::default())
+    }
+    fn read_traced_le<T: BinRead>(&mut self) -> BinResult<T>
+    where
+        for<'a> T::Args<'a>: Default,
+    {
+        self.read_traced_type(Endian::Little)
+    }
+    fn read_traced_be<T: BinRead>(&mut self) -> BinResult<T>
+    where
+        for<'a> T::Args<'a>: Default,
+    {
+        self.read_traced_type(Endian::Big)
+    }
+    fn read_traced_ne<T: BinRead>(&mut self) -> BinResult<T>
+    where
+        for<'a> T::Args<'a>: Default,
+    {
+        self.read_traced_type(Endian::NATIVE)
+    }
+}
+impl<R: Read + Seek> TracedBinReaderExt for R {}
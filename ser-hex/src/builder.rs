@@ -0,0 +1,79 @@
+use std::{borrow::Cow, io::Cursor, io::Write};
+
+use crate::{
+    Action, ReadSpan, SeekAction, SeekOrigin, Trace, TraceMetadata, TreeSpan, Value, TRACE_VERSION,
+};
+
+/// Builds a [`Trace`] from explicit span, read and seek calls, for producers which cannot wrap
+/// their stream in a [`crate::TraceStream`] such as hooks in native code
+pub struct TraceBuilder {
+    data: Cursor<Vec<u8>>,
+    /// Open spans, the first is the root
+    stack: Vec<ReadSpan>,
+    pub metadata: TraceMetadata,
+}
+impl Default for TraceBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl TraceBuilder {
+    pub fn new() -> Self {
+        Self {
+            data: Default::default(),
+            stack: vec![ReadSpan::new("root")],
+            metadata: Default::default(),
+        }
+    }
+    fn current(&mut self) -> &mut ReadSpan {
+        self.stack.last_mut().unwrap()
+    }
+    pub fn begin_span(&mut self, name: impl Into<Cow<'static, str>>) {
+        let mut span = ReadSpan::new("");
+        span.name = name.into();
+        self.stack.push(span);
+    }
+    /// Close the innermost open span, does nothing if only the root span is open
+    pub fn end_span(&mut self) {
+        if self.stack.len() > 1 {
+            let span = self.stack.pop().unwrap();
+            self.current().actions.push(Action::Span(TreeSpan(span)));
+        }
+    }
+    /// Record a read of `data` at the current offset
+    pub fn read(&mut self, data: &[u8]) {
+        self.data.write_all(data).unwrap();
+        self.current().actions.push(Action::Read(data.len()));
+    }
+    /// Record a seek to an absolute offset
+    pub fn seek(&mut self, offset: usize) {
+        self.data.set_position(offset as u64);
+        self.current().actions.push(Action::Seek(SeekAction {
+            to: offset,
+            from: Some(SeekOrigin::Start(offset as u64)),
+        }));
+    }
+    /// Annotate the innermost open span with a decoded value
+    pub fn value(&mut self, value: Value) {
+        self.current().value = Some(value);
+    }
+    /// Build a trace of everything recorded so far, closing any open spans
+    pub fn trace(&self) -> Trace {
+        let mut stack = self.stack.clone();
+        while stack.len() > 1 {
+            let span = stack.pop().unwrap();
+            stack
+                .last_mut()
+                .unwrap()
+                .actions
+                .push(Action::Span(TreeSpan(span)));
+        }
+        Trace {
+            version: TRACE_VERSION,
+            data: self.data.get_ref().clone(),
+            start_index: 0,
+            root: Action::Span(TreeSpan(stack.pop().unwrap())),
+            metadata: self.metadata.clone(),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use sha2::{Digest, Sha256};
+
+use crate::Trace;
+
+/// Lowercase hex encoded SHA-256 of `data`
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+impl<D: AsRef<[u8]>> Trace<D> {
+    /// Store the SHA-256 of the data in [`TraceMetadata::data_sha256`], checked when the trace is
+    /// loaded
+    ///
+    /// [`TraceMetadata::data_sha256`]: crate::TraceMetadata::data_sha256
+    pub fn update_checksum(&mut self) {
+        self.metadata.data_sha256 = Some(sha256_hex(self.data.as_ref()));
+    }
+    /// Check the data against the stored checksum, if any
+    pub fn verify_checksum(&self) -> anyhow::Result<()> {
+        if let Some(expected) = &self.metadata.data_sha256 {
+            let actual = sha256_hex(self.data.as_ref());
+            if !actual.eq_ignore_ascii_case(expected) {
+                anyhow::bail!("trace data checksum mismatch: expected {expected}, got {actual}");
+            }
+        }
+        Ok(())
+    }
+}
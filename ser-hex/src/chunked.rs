@@ -0,0 +1,251 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use ::serde::{Deserialize, Serialize};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+
+use crate::{from_json, split::data_path, Action, Header, Trace, TraceMetadata, TreeSpan};
+
+/// Layout of data saved with [`Trace::save_chunked`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkOptions {
+    /// Uncompressed size of each chunk, only the last chunk may be shorter
+    pub chunk_size: usize,
+    /// Deflate each chunk individually
+    pub compress: bool,
+}
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1 << 20,
+            compress: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Compression {
+    None,
+    Deflate,
+}
+
+/// Location of a stored chunk in the sidecar file
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Chunk {
+    offset: u64,
+    length: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChunkIndex {
+    /// Sidecar file relative to the trace file
+    file: PathBuf,
+    /// Total uncompressed size of the data
+    size: usize,
+    chunk_size: usize,
+    compression: Compression,
+    chunks: Vec<Chunk>,
+}
+
+/// JSON part of a trace whose data is stored as chunks in a sidecar file
+#[derive(Serialize, Deserialize)]
+struct ChunkedTrace<A, M> {
+    version: u32,
+    data_chunks: ChunkIndex,
+    start_index: usize,
+    root: A,
+    metadata: M,
+}
+
+pub(crate) fn load<D: AsRef<[u8]> + From<Vec<u8>>>(
+    path: &Path,
+    json: &[u8],
+) -> anyhow::Result<Trace<D>> {
+    let chunked: ChunkedTrace<Action<TreeSpan>, TraceMetadata> = from_json(json)?;
+    let data = ChunkedData::new(path, chunked.data_chunks)?;
+    let mut trace = Trace {
+        version: chunked.version,
+        data: data.read_range(0..data.len())?.into(),
+        start_index: chunked.start_index,
+        root: chunked.root,
+        metadata: chunked.metadata,
+    };
+    trace.upgrade();
+    trace.verify_checksum()?;
+    Ok(trace)
+}
+
+impl<D: AsRef<[u8]>> Trace<D> {
+    /// Save the trace with data stored in a sidecar file (`<path>.chunks`) as a sequence of
+    /// fixed-size chunks rather than base64 encoded in the JSON
+    ///
+    /// Only one chunk is held in memory at a time and the chunks can later be read individually
+    /// with [`ChunkedData`].
+    pub fn save_chunked(
+        &self,
+        path: impl AsRef<Path>,
+        options: ChunkOptions,
+    ) -> Result<(), io::Error> {
+        assert!(options.chunk_size > 0, "chunk size must be non-zero");
+        let path = path.as_ref();
+        let mut data_file = path.file_name().unwrap_or_default().to_owned();
+        data_file.push(".chunks");
+        let data_path = data_path(path, data_file.as_ref());
+
+        let mut tmp_path = data_path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+        let mut chunks = vec![];
+        let mut offset = 0;
+        for chunk in self.data.as_ref().chunks(options.chunk_size) {
+            let compressed;
+            let stored = if options.compress {
+                let mut encoder = DeflateEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(chunk)?;
+                compressed = encoder.finish()?;
+                &compressed[..]
+            } else {
+                chunk
+            };
+            writer.write_all(stored)?;
+            let length = stored.len() as u64;
+            chunks.push(Chunk { offset, length });
+            offset += length;
+        }
+        writer.into_inner().map_err(|e| e.into_error())?;
+        fs::rename(&tmp_path, &data_path)?;
+
+        let json = serde_json::to_string(&ChunkedTrace {
+            version: self.version,
+            data_chunks: ChunkIndex {
+                file: PathBuf::from(data_file),
+                size: self.data.as_ref().len(),
+                chunk_size: options.chunk_size,
+                compression: if options.compress {
+                    Compression::Deflate
+                } else {
+                    Compression::None
+                },
+                chunks,
+            },
+            start_index: self.start_index,
+            root: &self.root,
+            metadata: &self.metadata,
+        })?;
+        fs::write(path, json)
+    }
+}
+
+/// Data of a trace saved with [`Trace::save_chunked`], read from disk a chunk at a time
+#[derive(Debug)]
+pub struct ChunkedData {
+    file: fs::File,
+    index: ChunkIndex,
+}
+impl ChunkedData {
+    /// Open the data of the chunked trace at `path` without reading it into memory
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        #[derive(Deserialize)]
+        struct Index {
+            data_chunks: Option<ChunkIndex>,
+        }
+
+        let path = path.as_ref();
+        let json = fs::read(path)?;
+        Header::parse(&json)?;
+        let Some(index) = from_json::<Index>(&json)?.data_chunks else {
+            anyhow::bail!("trace data is not chunked, save it with Trace::save_chunked");
+        };
+        Self::new(path, index)
+    }
+    fn new(path: &Path, index: ChunkIndex) -> anyhow::Result<Self> {
+        let expected = match index.chunk_size {
+            0 if index.size == 0 => 0,
+            0 => anyhow::bail!("chunk size must be non-zero"),
+            chunk_size => index.size.div_ceil(chunk_size),
+        };
+        if index.chunks.len() != expected {
+            anyhow::bail!(
+                "expected {expected} chunks for {} bytes of data, found {}",
+                index.size,
+                index.chunks.len()
+            );
+        }
+        Ok(Self {
+            file: fs::File::open(data_path(path, &index.file))?,
+            index,
+        })
+    }
+    /// Total uncompressed size of the data
+    pub fn len(&self) -> usize {
+        self.index.size
+    }
+    pub fn is_empty(&self) -> bool {
+        self.index.size == 0
+    }
+    /// Uncompressed size of each chunk
+    pub fn chunk_size(&self) -> usize {
+        self.index.chunk_size
+    }
+    pub fn chunk_count(&self) -> usize {
+        self.index.chunks.len()
+    }
+    /// Read and decompress a single chunk
+    pub fn read_chunk(&self, index: usize) -> io::Result<Vec<u8>> {
+        let Some(chunk) = self.index.chunks.get(index) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("chunk {index} out of range"),
+            ));
+        };
+        let size = self
+            .index
+            .chunk_size
+            .min(self.index.size - index * self.index.chunk_size);
+
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(chunk.offset))?;
+        let stored = file.take(chunk.length);
+        let mut data = Vec::with_capacity(size);
+        match self.index.compression {
+            Compression::None => stored.take(size as u64 + 1).read_to_end(&mut data)?,
+            Compression::Deflate => DeflateDecoder::new(stored)
+                .take(size as u64 + 1)
+                .read_to_end(&mut data)?,
+        };
+        if data.len() != size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("chunk {index} is {} bytes, expected {size}", data.len()),
+            ));
+        }
+        Ok(data)
+    }
+    /// Read a range of the data, decompressing only the chunks it overlaps
+    pub fn read_range(&self, range: Range<usize>) -> io::Result<Vec<u8>> {
+        if range.start > range.end || range.end > self.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{range:?} out of range for {} bytes of data", self.len()),
+            ));
+        }
+        let mut data = Vec::with_capacity(range.len());
+        if range.is_empty() {
+            return Ok(data);
+        }
+        let chunk_size = self.index.chunk_size;
+        for index in range.start / chunk_size..range.end.div_ceil(chunk_size) {
+            let chunk = self.read_chunk(index)?;
+            let start = index * chunk_size;
+            data.extend_from_slice(
+                &chunk[range.start.saturating_sub(start)..(range.end - start).min(chunk.len())],
+            );
+        }
+        Ok(data)
+    }
+}
@@ -0,0 +1,54 @@
+use std::ops::Range;
+
+use crate::Trace;
+
+impl<D: AsRef<[u8]>> Trace<D> {
+    /// Sorted, non-overlapping byte ranges read at least once
+    pub fn covered_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<_> = self
+            .iter_flat()
+            .filter(|flat| flat.action.read_size().is_some() && !flat.range.is_empty())
+            .map(|flat| flat.range)
+            .collect();
+        ranges.sort_by_key(|r| r.start);
+
+        let mut merged: Vec<Range<usize>> = vec![];
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+    /// Sorted byte ranges of the data which were never read
+    pub fn uncovered_ranges(&self) -> Vec<Range<usize>> {
+        let len = self.data.as_ref().len();
+        let mut uncovered = vec![];
+        let mut offset = 0;
+        for range in self.covered_ranges() {
+            if range.start > offset {
+                uncovered.push(offset..range.start.min(len));
+            }
+            offset = offset.max(range.end);
+        }
+        if offset < len {
+            uncovered.push(offset..len);
+        }
+        uncovered.retain(|r| !r.is_empty());
+        uncovered
+    }
+    /// Fraction of the data read at least once
+    pub fn coverage(&self) -> f64 {
+        let len = self.data.as_ref().len();
+        if len == 0 {
+            return 1.0;
+        }
+        let covered: usize = self
+            .covered_ranges()
+            .iter()
+            .map(|r| r.end.min(len).saturating_sub(r.start))
+            .sum();
+        covered as f64 / len as f64
+    }
+}
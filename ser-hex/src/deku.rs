@@ -0,0 +1,57 @@
+//! Spans for [deku](::deku) parsers
+//!
+//! ```ignore
+//! use deku::prelude::*;
+//! use ser_hex::deku::TracedDekuReaderExt;
+//!
+//! #[ser_hex::deku::spans]
+//! #[derive(DekuRead)]
+//! #[deku(endian = "little")]
+//! struct Header {
+//!     magic: u32,
+//!     #[deku(reader = "ser_hex::deku::read_traced(deku::reader, ())")]
+//!     entries: Entries,
+//! }
+//!
+//! // root span named "Header" containing spans "magic" and "entries" > "Entries"
+//! let header: Header = reader.read_traced_deku()?;
+//! ```
+
+use std::{
+    borrow::Cow,
+    io::{Read, Seek},
+};
+
+use ::deku::{reader::Reader, DekuError, DekuReader};
+
+use crate::short_type_name;
+
+pub use ser_hex_macros::deku_spans as spans;
+
+/// Read `T` inside a span named after its type, usable as
+/// `#[deku(reader = "ser_hex::deku::read_traced(deku::reader, ())")]`
+pub fn read_traced<'a, R: Read + Seek, T: DekuReader<'a, Ctx>, Ctx>(
+    reader: &mut Reader<R>,
+    ctx: Ctx,
+) -> Result<T, DekuError> {
+    read_named(reader, ctx, short_type_name(std::any::type_name::<T>()))
+}
+
+/// Read `T` inside a span with the given name
+pub fn read_named<'a, R: Read + Seek, T: DekuReader<'a, Ctx>, Ctx>(
+    reader: &mut Reader<R>,
+    ctx: Ctx,
+    name: impl Into<Cow<'static, str>>,
+) -> Result<T, DekuError> {
+    let name = name.into();
+    let _guard = tracing::info_span!("deku", ser_hex.name = %name).entered();
+    T::from_reader_with_ctx(reader, ctx)
+}
+
+/// Read a deku type from a stream inside a span named after the type
+pub trait TracedDekuReaderExt: Read + Seek + Sized {
+    fn read_traced_deku<T: for<'a> DekuReader<'a>>(&mut self) -> Result<T, DekuError> {
+        read_traced(&mut Reader::new(self), ())
+    }
+}
+impl<R: Read + Seek> TracedDekuReaderExt for R {}
@@ -0,0 +1,120 @@
+use std::ops::Range;
+
+use crate::{Action, FlatIter, ReadSpan, SeekOrigin, Trace, TreeSpan, TRACE_VERSION};
+
+/// Stream offset after performing an action starting at `offset`
+fn end_offset(action: &Action<TreeSpan>, offset: usize) -> usize {
+    FlatIter::new(action, offset)
+        .last()
+        .map_or(offset, |flat| flat.range.end)
+}
+
+/// Byte range touched by an action starting at `offset`, including the starting offset itself
+fn covered(action: &Action<TreeSpan>, offset: usize) -> Range<usize> {
+    FlatIter::new(action, offset).fold(offset..offset, |acc, flat| {
+        acc.start.min(flat.range.start)..acc.end.max(flat.range.end)
+    })
+}
+
+/// Shift absolute seek offsets so they are relative to `base`. Seeks from the end are turned into
+/// seeks from the start as the extracted data no longer ends where the original stream did
+fn rebase(action: &mut Action<TreeSpan>, base: usize) {
+    match action {
+        Action::Seek(seek) => {
+            seek.to -= base;
+            match &mut seek.from {
+                Some(SeekOrigin::Start(offset)) => *offset = offset.saturating_sub(base as u64),
+                Some(from @ SeekOrigin::End(_)) => *from = SeekOrigin::Start(seek.to as u64),
+                _ => {}
+            }
+        }
+        Action::Span(span) => {
+            for action in &mut span.0.actions {
+                rebase(action, base);
+            }
+        }
+        _ => {}
+    }
+}
+
+impl<D: AsRef<[u8]>> Trace<D> {
+    /// Extract the action at `path` (indices starting from the children of the root span as in
+    /// [`crate::FlatAction::index_path`]) into a standalone trace containing only the bytes it
+    /// covers
+    pub fn extract(&self, path: &[usize]) -> Option<Trace> {
+        let mut offset = self.start_index;
+        let mut action = &self.root;
+        for &index in path {
+            let Action::Span(span) = action else {
+                return None;
+            };
+            for child in span.0.actions.get(..index)? {
+                offset = end_offset(child, offset);
+            }
+            action = span.0.actions.get(index)?;
+        }
+        Some(self.extract_action(action, offset))
+    }
+
+    /// Extract the innermost span covering all of `range` into a standalone trace, see
+    /// [`Trace::extract`]
+    pub fn slice(&self, range: Range<usize>) -> Option<Trace> {
+        let contains = |c: Range<usize>| c.start <= range.start && range.end <= c.end;
+
+        let mut offset = self.start_index;
+        let mut action = &self.root;
+        if !contains(covered(action, offset)) {
+            return None;
+        }
+        'descend: while let Action::Span(span) = action {
+            let mut child_offset = offset;
+            for child in &span.0.actions {
+                if matches!(child, Action::Span(_)) && contains(covered(child, child_offset)) {
+                    action = child;
+                    offset = child_offset;
+                    continue 'descend;
+                }
+                child_offset = end_offset(child, child_offset);
+            }
+            break;
+        }
+        Some(self.extract_action(action, offset))
+    }
+
+    fn extract_action(&self, action: &Action<TreeSpan>, offset: usize) -> Trace {
+        let range = covered(action, offset);
+        let data = self.data.as_ref();
+        let data = &data[range.start.min(data.len())..range.end.min(data.len())];
+
+        let mut root = action.clone();
+        rebase(&mut root, range.start);
+        if !matches!(root, Action::Span(_)) {
+            root = Action::Span(TreeSpan(ReadSpan {
+                name: "root".into(),
+                value: None,
+                actions: vec![root],
+                timings: vec![],
+                location: None,
+            }));
+        }
+
+        let mut metadata = self.metadata.clone();
+        metadata.redacted = metadata
+            .redacted
+            .iter()
+            .map(|r| {
+                let start = r.start.max(range.start) - range.start;
+                start..r.end.min(range.end).saturating_sub(range.start)
+            })
+            .filter(|r| !r.is_empty())
+            .collect();
+
+        Trace {
+            version: TRACE_VERSION,
+            data: data.to_vec(),
+            start_index: offset - range.start,
+            root,
+            metadata,
+        }
+    }
+}
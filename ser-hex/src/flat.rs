@@ -0,0 +1,97 @@
+use std::ops::Range;
+
+use crate::{Action, TreeSpan};
+
+/// A span enclosing a flattened action along with its index in the parent span
+#[derive(Debug, Clone, Copy)]
+pub struct SpanRef<'a> {
+    pub index: usize,
+    pub span: &'a TreeSpan,
+}
+
+#[derive(Debug, Clone)]
+pub struct FlatAction<'a> {
+    /// Byte range covered by the action (empty range at the destination for seeks and at the
+    /// current offset for errors and sub-traces)
+    pub range: Range<usize>,
+    /// Enclosing spans starting from the root
+    pub path: Vec<SpanRef<'a>>,
+    /// Index of the action in the innermost enclosing span
+    pub index: usize,
+    pub action: &'a Action<TreeSpan>,
+}
+impl FlatAction<'_> {
+    /// Indices of the action starting from the children of the root span
+    pub fn index_path(&self) -> Vec<usize> {
+        self.path
+            .iter()
+            .skip(1)
+            .map(|s| s.index)
+            .chain([self.index])
+            .collect()
+    }
+}
+
+/// Depth first iterator over all non-span actions of a trace tree, tracking the stream offset
+pub struct FlatIter<'a> {
+    offset: usize,
+    root: Option<&'a Action<TreeSpan>>,
+    path: Vec<SpanRef<'a>>,
+    stack: Vec<std::iter::Enumerate<std::slice::Iter<'a, Action<TreeSpan>>>>,
+}
+impl<'a> FlatIter<'a> {
+    pub fn new(root: &'a Action<TreeSpan>, start_index: usize) -> Self {
+        Self {
+            offset: start_index,
+            root: Some(root),
+            path: vec![],
+            stack: vec![],
+        }
+    }
+    fn visit(&mut self, index: usize, action: &'a Action<TreeSpan>) -> Option<FlatAction<'a>> {
+        let start = self.offset;
+        let range = match action {
+            Action::Read(_) | Action::ReadData(_) => {
+                self.offset += action.read_size().unwrap();
+                start..self.offset
+            }
+            Action::Seek(seek) => {
+                self.offset = seek.to;
+                seek.to..seek.to
+            }
+            Action::Error(_) | Action::SubTrace(_) => start..start,
+            Action::Span(span) => {
+                self.path.push(SpanRef { index, span });
+                self.stack.push(span.0.actions.iter().enumerate());
+                return None;
+            }
+        };
+        Some(FlatAction {
+            range,
+            path: self.path.clone(),
+            index,
+            action,
+        })
+    }
+}
+impl<'a> Iterator for FlatIter<'a> {
+    type Item = FlatAction<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(root) = self.root.take() {
+            if let Some(flat) = self.visit(0, root) {
+                return Some(flat);
+            }
+        }
+        loop {
+            let Some((index, action)) = self.stack.last_mut()?.next() else {
+                self.stack.pop();
+                self.path.pop();
+                continue;
+            };
+            if let Some(flat) = self.visit(index, action) {
+                return Some(flat);
+            }
+        }
+    }
+}
@@ -1,32 +1,219 @@
-use serde::{Deserialize, Serialize};
+// allow ser_hex:: paths emitted by macros to resolve within this crate
+extern crate self as ser_hex;
+
+#[cfg(feature = "binrw")]
+pub mod binrw;
+mod builder;
+mod checksum;
+mod chunked;
+mod coverage;
+#[cfg(feature = "deku")]
+pub mod deku;
+mod extract;
+mod flat;
+#[cfg(feature = "nom")]
+pub mod nom;
+mod read_ext;
+mod redact;
+mod replay;
+mod sample;
+mod schema;
+pub mod serde;
+mod sparse;
+mod split;
+mod strings;
+pub mod testing;
+mod validate;
+
+pub use builder::TraceBuilder;
+pub use checksum::sha256_hex;
+pub use chunked::{ChunkOptions, ChunkedData};
+pub use flat::{FlatAction, FlatIter, SpanRef};
+pub use memmap2::Mmap;
+pub use read_ext::TracedReadExt;
+pub use sample::Sampler;
+pub use schema::json_schema;
+pub use validate::{Problem, ProblemKind};
+
+use ::serde::{Deserialize, Serialize};
 use tracing::{
     span::{self, EnteredSpan},
     subscriber::{self, DefaultGuard, Subscriber},
-    Event, Id, Metadata,
+    Dispatch, Event, Id, Metadata,
 };
 use tracing_core::span::Current;
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs,
     io::{self, Cursor, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
 };
 
+#[derive(Debug, Default, Clone)]
+pub struct TraceOptions {
+    /// Maximum number of bytes of data to store in the trace. Actions past the cap are still
+    /// recorded, but their bytes are not captured
+    pub max_data_size: Option<usize>,
+    /// Metadata to store in the trace. Tool name, version and capture time are filled in
+    /// automatically if not set
+    pub metadata: TraceMetadata,
+    /// Store the bytes returned by each read in the action log ([`Action::ReadData`]) in
+    /// addition to the positional mirror, preserving data when a region is read more than once
+    pub record_read_data: bool,
+    /// Record the monotonic start and end time of each action ([`ReadSpan::timings`]) so the
+    /// trace doubles as a performance profile
+    pub record_timings: bool,
+    /// Only trace invocations selected by the sampler, matched against
+    /// [`TraceMetadata::source`] if set or else the trace path
+    pub sampler: Option<&'static Sampler>,
+    /// Save data to a raw sidecar file instead of base64 encoding it in the trace, see
+    /// [`Trace::save_split`]
+    pub split_data: bool,
+    /// Save data as a sequence of fixed-size chunks in a sidecar file, see
+    /// [`Trace::save_chunked`]. Takes precedence over `split_data`
+    pub chunk_data: Option<ChunkOptions>,
+    /// Leave the thread's default subscriber in place for the lifetime of a [`TraceStream`]
+    /// instead of replacing it with the trace. Spans are then only recorded inside
+    /// [`TraceStream::with_subscriber`]
+    pub scoped: bool,
+    /// Capture only the bytes actually read instead of reading the whole stream up front, for
+    /// huge or slow streams. The captured bytes are stored as segments like with `sparse_data`,
+    /// bytes which are never read are left zeroed in [`Trace::data`] once loaded
+    pub lazy_mirror: bool,
+    /// Store the SHA-256 of the data in the trace ([`TraceMetadata::data_sha256`]), along with
+    /// that of the source stream when it is mirrored in full
+    pub checksum: bool,
+    /// Only store data and actions within this byte range of the stream. Reads and seeks outside
+    /// of it are counted ([`TraceMetadata::skipped_actions`]) but not stored, and spans left
+    /// empty are dropped
+    pub window: Option<std::ops::Range<usize>>,
+    /// Merge consecutive reads within the same span into a single action, shrinking traces of
+    /// parsers which read a byte at a time
+    pub coalesce_reads: bool,
+    /// Store only the bytes actually read, as a list of segments at their stream offsets.
+    /// Incremental traces of seekable streams are rebased onto stream offsets the first time the
+    /// position of the stream is known, so seeks no longer misplace data. Takes precedence over
+    /// `split_data` and `chunk_data`
+    pub sparse_data: bool,
+    /// Forward spans and events to the thread's previous default subscriber in addition to
+    /// recording them, so e.g. console logging keeps working while a trace is active
+    pub tee: bool,
+}
+impl TraceOptions {
+    /// Whether tracing is enabled and the invocation is selected by the sampler
+    fn should_trace(&self, trace_path: &Path) -> bool {
+        if !is_enabled() {
+            return false;
+        }
+        let Some(sampler) = self.sampler else {
+            return true;
+        };
+        match &self.metadata.source {
+            Some(source) => sampler.sample(source),
+            None => sampler.sample(&trace_path.to_string_lossy()),
+        }
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Globally enable or disable tracing at runtime (enabled by default)
+///
+/// While disabled, new [`TraceStream`]s forward reads and seeks to the inner stream without
+/// mirroring data, recording actions or writing a trace. Streams already being traced are not
+/// affected.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
 /// Build a stream (Cursor<Vec<u8>>) mirroring all the data in the underlying stream and cursor position
-fn build_mirror<S: Read + Seek>(stream: &mut S) -> Result<Cursor<Vec<u8>>, io::Error> {
+fn build_mirror<S: Read + Seek + ?Sized>(
+    stream: &mut S,
+    options: &mut TraceOptions,
+) -> Result<Cursor<Vec<u8>>, io::Error> {
     let pos = stream.stream_position()?;
+    if let Some(window) = options.window.clone().filter(|_| !options.lazy_mirror) {
+        // zeros up to the window so data stays positionally correct
+        let mut data = vec![0; window.start];
+        stream.seek(SeekFrom::Start(window.start as u64))?;
+        let len = match options.max_data_size {
+            Some(max) => window.len().min(max.saturating_sub(window.start)),
+            None => window.len(),
+        };
+        Read::take(&mut *stream, len as u64).read_to_end(&mut data)?;
+        stream.seek(SeekFrom::Start(pos))?;
+        let mut cursor = Cursor::new(data);
+        cursor.set_position(pos);
+        return Ok(cursor);
+    }
+    if options.lazy_mirror {
+        // only tracks the position, reads are captured into segments
+        let mut cursor = Cursor::new(vec![]);
+        cursor.set_position(pos);
+        return Ok(cursor);
+    }
     stream.seek(SeekFrom::Start(0))?;
     let mut data = vec![];
-    stream.read_to_end(&mut data)?;
+    match options.max_data_size {
+        Some(max) => Read::take(&mut *stream, max as u64).read_to_end(&mut data)?,
+        None => stream.read_to_end(&mut data)?,
+    };
+    if options.checksum && options.max_data_size.is_none_or(|max| data.len() < max) {
+        // the mirror holds the whole source
+        let metadata = &mut options.metadata;
+        metadata
+            .source_sha256
+            .get_or_insert_with(|| sha256_hex(&data));
+    }
     let mut cursor = Cursor::new(data);
     stream.seek(SeekFrom::Start(pos))?;
     cursor.seek(SeekFrom::Start(pos))?;
     Ok(cursor)
 }
 
-pub fn read<'t, 'r: 't, P: AsRef<Path>, R: Read + Seek + 'r, F, T>(
+pub fn read<'t, 'r: 't, P: AsRef<Path>, R: Read + Seek + ?Sized + 'r, F, T>(
+    out_path: P,
+    reader: &'r mut R,
+    f: F,
+) -> T
+where
+    F: FnOnce(&mut TraceStream<&'r mut R>) -> T,
+{
+    read_options(out_path, reader, Default::default(), f)
+}
+
+pub fn read_options<'t, 'r: 't, P: AsRef<Path>, R: Read + Seek + ?Sized + 'r, F, T>(
+    out_path: P,
+    reader: &'r mut R,
+    mut options: TraceOptions,
+    f: F,
+) -> T
+where
+    F: FnOnce(&mut TraceStream<&'r mut R>) -> T,
+{
+    if !options.should_trace(out_path.as_ref()) {
+        return f(&mut TraceStream::disabled(reader));
+    }
+    let cursor = build_mirror(reader, &mut options).unwrap();
+    CounterSubscriber::read(
+        out_path.as_ref().to_owned(),
+        Some(cursor),
+        options,
+        reader,
+        f,
+    )
+}
+
+pub fn read_incremental<'t, 'r: 't, P: AsRef<Path>, R: Read + ?Sized + 'r, F, T>(
     out_path: P,
     reader: &'r mut R,
     f: F,
@@ -34,24 +221,86 @@ pub fn read<'t, 'r: 't, P: AsRef<Path>, R: Read + Seek + 'r, F, T>(
 where
     F: FnOnce(&mut TraceStream<&'r mut R>) -> T,
 {
-    let cursor = build_mirror(reader).unwrap();
-    CounterSubscriber::read(out_path.as_ref().to_owned(), Some(cursor), reader, f)
+    read_incremental_options(out_path, reader, Default::default(), f)
 }
 
-pub fn read_incremental<'t, 'r: 't, P: AsRef<Path>, R: Read + 'r, F, T>(
+pub fn read_incremental_options<'t, 'r: 't, P: AsRef<Path>, R: Read + ?Sized + 'r, F, T>(
     out_path: P,
     reader: &'r mut R,
+    options: TraceOptions,
+    f: F,
+) -> T
+where
+    F: FnOnce(&mut TraceStream<&'r mut R>) -> T,
+{
+    if !options.should_trace(out_path.as_ref()) {
+        return f(&mut TraceStream::disabled(reader));
+    }
+    CounterSubscriber::read(out_path.as_ref().to_owned(), None, options, reader, f)
+}
+
+/// Trace parsing of a nested stream (e.g. a decompressed blob) and attach the result inline to
+/// the current span of the enclosing trace as an [`Action::SubTrace`]
+///
+/// Should be called right after the read of the blob the nested stream was decoded from. If no
+/// trace is in progress the nested stream is read untraced.
+pub fn read_nested<'t, 'r: 't, R: Read + ?Sized + 'r, F, T>(reader: &'r mut R, f: F) -> T
+where
+    F: FnOnce(&mut TraceStream<&'r mut R>) -> T,
+{
+    read_nested_options(reader, Default::default(), f)
+}
+
+pub fn read_nested_options<'t, 'r: 't, R: Read + ?Sized + 'r, F, T>(
+    reader: &'r mut R,
+    options: TraceOptions,
     f: F,
 ) -> T
 where
     F: FnOnce(&mut TraceStream<&'r mut R>) -> T,
 {
-    CounterSubscriber::read(out_path.as_ref().to_owned(), None, reader, f)
+    let Some(parent) = CounterSubscriber::current() else {
+        return f(&mut TraceStream::disabled(reader));
+    };
+    CounterSubscriber::read(Output::Parent(parent), None, options, reader, f)
+}
+
+/// Attach a trace of a nested stream to the current span of the enclosing trace, describing the
+/// data returned by the preceding read. See [`read_nested`] to capture one inline
+pub fn attach_sub_trace(sub_trace: SubTrace) {
+    if let Some(subscriber) = CounterSubscriber::current() {
+        subscriber.push_action(Action::SubTrace(sub_trace), subscriber.now());
+    }
 }
 
+/// Names of the spans entered on the current thread's trace below its root span, outermost
+/// first. Empty if no trace is the thread's default subscriber
+pub fn current_span_path() -> Vec<std::borrow::Cow<'static, str>> {
+    let Some(subscriber) = CounterSubscriber::current() else {
+        return vec![];
+    };
+    let lock = subscriber.inner.lock().unwrap();
+    lock.stack
+        .iter()
+        .skip(1)
+        .map(|id| lock.spans[id].name.clone())
+        .collect()
+}
+
+/// [`Read`] + [`Seek`] as a single trait usable as a trait object, e.g. `Box<dyn ReadSeek>`
+pub trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+/// [`TraceStream`] with the type of the wrapped stream erased, see [`TraceStream::boxed`]
+pub type DynTraceStream<'a> = TraceStream<Box<dyn ReadSeek + 'a>>;
+
 pub struct TraceStream<S> {
     stream: S,
+    /// None if tracing is disabled, in which case the stream is forwarded untouched
+    state: Option<TraceState>,
+}
 
+struct TraceState {
     // first drop span
     #[allow(unused)]
     scope_guard: EnteredSpan,
@@ -65,68 +314,420 @@ pub struct TraceStream<S> {
 }
 
 impl<S: Read + Seek> TraceStream<S> {
-    pub fn new<P: Into<PathBuf>>(trace_path: P, mut inner_stream: S) -> Self {
-        let cursor = build_mirror(&mut inner_stream).unwrap();
-        let subscriber = CounterSubscriber::new(trace_path.into(), cursor);
-        let guard = Some(tracing::subscriber::set_default(subscriber.clone()));
+    pub fn new<P: Into<PathBuf>>(trace_path: P, inner_stream: S) -> Self {
+        Self::new_options(trace_path, inner_stream, Default::default())
+    }
+    pub fn new_options<P: Into<PathBuf>>(
+        trace_path: P,
+        mut inner_stream: S,
+        mut options: TraceOptions,
+    ) -> Self {
+        let trace_path: PathBuf = trace_path.into();
+        if !options.should_trace(&trace_path) {
+            return Self::disabled(inner_stream);
+        }
+        let cursor = build_mirror(&mut inner_stream, &mut options).unwrap();
+        let scoped = options.scoped;
+        let subscriber = CounterSubscriber::new(Output::File(trace_path), Some(cursor), options);
+        let guard = (!scoped).then(|| tracing::subscriber::set_default(subscriber.clone()));
         Self::new_internal(inner_stream, subscriber, guard)
     }
 }
 impl<S> TraceStream<S> {
     pub fn new_incremental<P: Into<PathBuf>>(trace_path: P, inner_stream: S) -> Self {
-        let subscriber = CounterSubscriber::new(trace_path.into(), Cursor::new(vec![]));
-        let guard = Some(tracing::subscriber::set_default(subscriber.clone()));
+        Self::new_incremental_options(trace_path, inner_stream, Default::default())
+    }
+    pub fn new_incremental_options<P: Into<PathBuf>>(
+        trace_path: P,
+        inner_stream: S,
+        options: TraceOptions,
+    ) -> Self {
+        let trace_path: PathBuf = trace_path.into();
+        if !options.should_trace(&trace_path) {
+            return Self::disabled(inner_stream);
+        }
+        let scoped = options.scoped;
+        let subscriber = CounterSubscriber::new(Output::File(trace_path), None, options);
+        let guard = (!scoped).then(|| tracing::subscriber::set_default(subscriber.clone()));
         Self::new_internal(inner_stream, subscriber, guard)
     }
 }
 impl<S> TraceStream<S> {
     fn new_internal(stream: S, subscriber: CounterSubscriber, guard: Option<DefaultGuard>) -> Self {
+        // root span must belong to the trace even if it is not the default subscriber
+        let dispatch = Dispatch::new(subscriber.clone());
+        let root = tracing::dispatcher::with_default(&dispatch, || tracing::info_span!("root"));
         Self {
             stream,
-            scope_guard: tracing::info_span!("root").entered(),
-            guard,
-            subscriber,
+            state: Some(TraceState {
+                scope_guard: root.entered(),
+                guard,
+                subscriber,
+            }),
+        }
+    }
+    /// Wrap a stream without tracing it, forwarding all reads and seeks directly
+    pub fn disabled(inner_stream: S) -> Self {
+        Self {
+            stream: inner_stream,
+            state: None,
+        }
+    }
+    /// Whether the stream is being traced
+    pub fn is_enabled(&self) -> bool {
+        self.state.is_some()
+    }
+    /// Run `f` with the trace as the thread's default subscriber so spans it enters are recorded,
+    /// restoring the previous default afterwards. See [`TraceOptions::scoped`]
+    pub fn with_subscriber<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let Some(TraceState { subscriber, .. }) = &self.state else {
+            return f(self);
+        };
+        let dispatch = Dispatch::new(subscriber.clone());
+        tracing::dispatcher::with_default(&dispatch, || f(self))
+    }
+}
+impl<'a, S: Read + Seek + 'a> TraceStream<S> {
+    /// Erase the type of the wrapped stream so trace streams of different readers can be passed
+    /// through a single choke point
+    ///
+    /// Note trace streams are not `Send` as they hold the entered root span of the thread.
+    pub fn boxed(self) -> DynTraceStream<'a> {
+        TraceStream {
+            stream: Box::new(self.stream),
+            state: self.state,
         }
     }
 }
 impl<R: Read + Seek> Seek for TraceStream<R> {
     fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        let Some(TraceState { subscriber, .. }) = &self.state else {
+            return self.stream.seek(pos);
+        };
+        subscriber.anchor(|| self.stream.stream_position());
+        let start = subscriber.now();
         self.stream
             .seek(pos)
-            .inspect(|&to| self.subscriber.seek_action(to))
+            .inspect(|&to| subscriber.seek_action(to, pos, start))
+    }
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        // does not move the stream so is not recorded
+        let position = self.stream.stream_position()?;
+        if let Some(TraceState { subscriber, .. }) = &self.state {
+            subscriber.anchor(|| Ok(position));
+        }
+        Ok(position)
     }
 }
 impl<R: Read> Read for TraceStream<R> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let Some(TraceState { subscriber, .. }) = &self.state else {
+            return self.stream.read(buf);
+        };
+        let start = subscriber.now();
         self.stream
             .read(buf)
-            .inspect(|&s| self.subscriber.read_action(buf, s))
+            .inspect(|&s| subscriber.read_action(buf, s, start))
+            .inspect_err(|e| subscriber.error_action(e, buf.len(), start))
+    }
+    fn read_exact(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+        let Some(TraceState { subscriber, .. }) = &self.state else {
+            return self.stream.read_exact(buf);
+        };
+        let start = subscriber.now();
+        // forwarded as a single action so EOF is recorded rather than silently stopping
+        self.stream
+            .read_exact(buf)
+            .inspect(|_| subscriber.read_action(buf, buf.len(), start))
+            .inspect_err(|e| subscriber.error_action(e, buf.len(), start))
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Action<S> {
     Read(usize),
-    Seek(usize),
+    Seek(SeekAction),
     Span(S),
+    Error(ReadError),
+    /// A read along with the bytes it returned, see [`TraceOptions::record_read_data`]
+    ReadData(#[serde(with = "base64")] Vec<u8>),
+    /// Trace of a nested stream decoded from the data of the preceding read
+    SubTrace(SubTrace),
+}
+impl<S> Action<S> {
+    /// Number of bytes consumed if the action is a read
+    pub fn read_size(&self) -> Option<usize> {
+        match self {
+            Action::Read(size) => Some(*size),
+            Action::ReadData(data) => Some(data.len()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "SeekActionRepr")]
+pub struct SeekAction {
+    /// Absolute destination offset
+    pub to: usize,
+    /// Origin and offset the seek was requested with, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<SeekOrigin>,
+}
+impl SeekAction {
+    /// Whether the seek was relative to the current position (e.g. alignment or padding skips)
+    pub fn is_relative(&self) -> bool {
+        matches!(self.from, Some(SeekOrigin::Current(_)))
+    }
+}
+impl From<usize> for SeekAction {
+    fn from(to: usize) -> Self {
+        Self { to, from: None }
+    }
+}
+
+/// Serializable mirror of [`SeekFrom`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SeekOrigin {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+impl From<SeekFrom> for SeekOrigin {
+    fn from(value: SeekFrom) -> Self {
+        match value {
+            SeekFrom::Start(o) => Self::Start(o),
+            SeekFrom::End(o) => Self::End(o),
+            SeekFrom::Current(o) => Self::Current(o),
+        }
+    }
+}
+
+/// Accepts both the version 1 bare offset and the full seek
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SeekActionRepr {
+    Offset(usize),
+    Full {
+        to: usize,
+        #[serde(default)]
+        from: Option<SeekOrigin>,
+    },
+}
+impl From<SeekActionRepr> for SeekAction {
+    fn from(value: SeekActionRepr) -> Self {
+        match value {
+            SeekActionRepr::Offset(to) => Self { to, from: None },
+            SeekActionRepr::Full { to, from } => Self { to, from },
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Trace of a nested stream such as a compressed or embedded blob, see [`read_nested`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SubTrace {
+    /// Trace saved to a separate file, relative paths are relative to the enclosing trace
+    File(PathBuf),
+    Inline(Box<Trace>),
+}
+impl SubTrace {
+    /// Load the sub-trace, resolving file references relative to `base_dir`
+    pub fn load(&self, base_dir: impl AsRef<Path>) -> anyhow::Result<Trace> {
+        match self {
+            SubTrace::File(path) => Trace::load(base_dir.as_ref().join(path)),
+            SubTrace::Inline(trace) => Ok((**trace).clone()),
+        }
+    }
+}
+
+/// A read which failed, e.g. due to hitting EOF
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadError {
+    /// [`io::ErrorKind`] of the error
+    pub kind: String,
+    pub message: String,
+    /// Number of bytes requested
+    pub size: usize,
+}
+impl ReadError {
+    pub fn new(error: &io::Error, size: usize) -> Self {
+        Self {
+            kind: format!("{:?}", error.kind()),
+            message: error.to_string(),
+            size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReadSpan<S = TreeSpan> {
+    /// Name of the `tracing` span, or of its `ser_hex.name` field if recorded for names only
+    /// known at runtime e.g. `info_span!("read_property", ser_hex.name = %key)`
     pub name: std::borrow::Cow<'static, str>,
+    /// Value decoded by the parser within this span, see [`value!`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
     pub actions: Vec<Action<S>>,
+    /// Timing of each action, parallel to `actions`. Empty unless captured with
+    /// [`TraceOptions::record_timings`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timings: Vec<Timing>,
+    /// Source location of the code within the span, recorded by backtrace based tracers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
 }
 impl<S> ReadSpan<S> {
     fn new(name: &'static str) -> Self {
         Self {
             name: name.into(),
+            value: None,
             actions: vec![],
+            timings: vec![],
+            location: None,
+        }
+    }
+}
+
+/// Monotonic start and end time of an action in nanoseconds since the trace began
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timing {
+    pub start: u64,
+    pub end: u64,
+}
+impl Timing {
+    pub fn duration(&self) -> std::time::Duration {
+        std::time::Duration::from_nanos(self.end.saturating_sub(self.start))
+    }
+}
+
+/// Source file and line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+}
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line}", self.file),
+            None => write!(f, "{}", self.file),
         }
     }
 }
 
+/// Decoded value and its type as interpreted by the parser
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Value {
+    #[serde(rename = "type")]
+    pub type_name: String,
+    pub value: String,
+}
+
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "binrw")]
+    pub use ::binrw;
+    #[cfg(feature = "deku")]
+    pub use ::deku;
+    pub use tracing;
+}
+
+#[cfg(feature = "instrument")]
+pub use ser_hex_macros::instrument;
+#[cfg(not(feature = "instrument"))]
+pub use ser_hex_macros::instrument_disabled as instrument;
+
+/// Annotate the current span with a decoded value and its type, evaluating to the value
+///
+/// ```ignore
+/// let length = ser_hex::value!(reader.read_u32::<LE>()?);
+/// let magic = ser_hex::value!(reader.read_u32::<BE>()?, "u32 BE");
+/// ```
+#[macro_export]
+macro_rules! value {
+    ($value:expr) => {{
+        let value = $value;
+        $crate::__private::tracing::trace!(
+            ser_hex.value = ?value,
+            ser_hex.type = ::std::any::type_name_of_val(&value)
+        );
+        value
+    }};
+    ($value:expr, $type:expr) => {{
+        let value = $value;
+        $crate::__private::tracing::trace!(ser_hex.value = ?value, ser_hex.type = $type);
+        value
+    }};
+}
+
+#[derive(Default)]
+struct ValueVisitor {
+    value: Option<String>,
+    type_name: Option<String>,
+}
+impl ValueVisitor {
+    fn into_value(self) -> Option<Value> {
+        Some(Value {
+            type_name: self.type_name.unwrap_or_default(),
+            value: self.value?,
+        })
+    }
+}
+/// Collects `ser_hex.arg.*` fields recorded by [`instrument`] and the `ser_hex.name` override
+#[derive(Default)]
+struct ArgsVisitor {
+    name: Option<String>,
+    args: Vec<String>,
+}
+impl tracing::field::Visit for ArgsVisitor {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        match field.name() {
+            "ser_hex.name" => self.name = Some(value.to_string()),
+            _ => self.record_debug(field, &value),
+        }
+    }
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "ser_hex.name" {
+            self.name = Some(format!("{value:?}"));
+        } else if let Some(arg) = field.name().strip_prefix("ser_hex.arg.") {
+            self.args.push(format!("{arg}={value:?}"));
+        }
+    }
+}
+
+impl tracing::field::Visit for ValueVisitor {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        match field.name() {
+            "ser_hex.value" => self.value = Some(value.to_string()),
+            "ser_hex.type" => self.type_name = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "ser_hex.value" => self.value = Some(format!("{value:?}")),
+            "ser_hex.type" => self.type_name = Some(format!("{value:?}")),
+            _ => {}
+        }
+    }
+}
+
+/// Destination of a finished trace
+enum Output {
+    File(PathBuf),
+    /// Attached inline to the current span of the enclosing trace
+    Parent(CounterSubscriber),
+}
+impl From<PathBuf> for Output {
+    fn from(path: PathBuf) -> Self {
+        Self::File(path)
+    }
+}
+
 struct CounterSubscriberInner {
-    out_path: PathBuf,
+    output: Output,
+    options: TraceOptions,
     start_index: usize,
     data: Cursor<Vec<u8>>,
     last_id: u64,
@@ -134,24 +735,106 @@ struct CounterSubscriberInner {
     spans: HashMap<Id, ReadSpan<Id>>,
     metadata: HashMap<Id, &'static Metadata<'static>>,
     stack: Vec<Id>,
+    /// Ids of spans in the forwarded subscriber when teeing
+    forwarded: HashMap<Id, Id>,
+    /// Stream position implied by the stored actions, which lags the actual position when
+    /// actions outside the window are skipped
+    stored_position: u64,
+    skipped_actions: usize,
+    /// Data captured by reads if [`TraceOptions::sparse_data`] or [`TraceOptions::lazy_mirror`]
+    /// is set, in which case `data` only tracks the position
+    segments: Option<sparse::Segments>,
+    /// Whether positions are stream offsets, which for incremental traces are only known once
+    /// the stream has been queried
+    anchored: bool,
 }
 impl CounterSubscriberInner {
-    fn new(out_path: PathBuf, mut data: Cursor<Vec<u8>>) -> Self {
+    /// `data` is the mirror of the stream or None for incremental traces
+    fn new(output: Output, data: Option<Cursor<Vec<u8>>>, options: TraceOptions) -> Self {
+        let anchored = data.is_some();
+        let data = data.unwrap_or_default();
+        let start_index = data.position();
         Self {
-            out_path,
-            start_index: data.stream_position().unwrap() as usize,
+            output,
+            segments: (options.sparse_data || options.lazy_mirror).then(Default::default),
+            options,
+            start_index: start_index as usize,
+            stored_position: start_index,
+            skipped_actions: 0,
+            anchored,
             data,
             last_id: Default::default(),
             root_span: Default::default(),
             spans: Default::default(),
             metadata: Default::default(),
             stack: Default::default(),
+            forwarded: Default::default(),
+        }
+    }
+    /// Append an action to the current span along with its start and end time if recorded
+    fn push_action(&mut self, action: Action<Id>, time: Option<(u64, u64)>) {
+        let current = self.stack.last().cloned().unwrap();
+        let span = self.spans.get_mut(&current).unwrap();
+        span.actions.push(action);
+        if let Some((start, end)) = time {
+            span.timings.push(Timing { start, end });
+        }
+    }
+    /// Whether an action covering `range` of the stream should be stored, counting it otherwise
+    fn in_window(&mut self, range: std::ops::Range<u64>) -> bool {
+        let Some(window) = &self.options.window else {
+            return true;
+        };
+        let window = window.start as u64..window.end as u64;
+        let stored = if range.is_empty() {
+            window.contains(&range.start)
+        } else {
+            range.start < window.end && window.start < range.end
+        };
+        if !stored {
+            self.skipped_actions += 1;
+        }
+        stored
+    }
+    /// Store a seek to the actual position if skipped actions left the stored position behind
+    fn sync_position(&mut self, position: u64, time: Option<(u64, u64)>) {
+        if self.stored_position != position {
+            let action = Action::Seek(SeekAction {
+                to: position as usize,
+                from: None,
+            });
+            self.push_action(action, time);
         }
     }
+    /// Extend the last action of the current span if it is a read of the same kind, returning
+    /// whether the read was merged
+    fn coalesce_read(&mut self, action: &Action<Id>, time: Option<(u64, u64)>) -> bool {
+        let current = self.stack.last().cloned().unwrap();
+        let span = self.spans.get_mut(&current).unwrap();
+        match (span.actions.last_mut(), action) {
+            (Some(Action::Read(last)), Action::Read(size)) => *last += size,
+            (Some(Action::ReadData(last)), Action::ReadData(data)) => last.extend(data),
+            _ => return false,
+        }
+        if let (Some(timing), Some((_, end))) = (span.timings.last_mut(), time) {
+            timing.end = end;
+        }
+        true
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Current version of the trace format
+pub const TRACE_VERSION: u32 = 2;
+
+/// Version of traces saved with [`Trace::save_interned`], newer than [`TRACE_VERSION`] so
+/// readers which predate interning reject them instead of failing on the name indexes
+pub const INTERNED_TRACE_VERSION: u32 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Trace<D: AsRef<[u8]> = Vec<u8>> {
+    /// Format version, traces written before the version field was introduced are version 0
+    #[serde(default)]
+    pub version: u32,
     #[serde(
         serialize_with = "base64::serialize",
         deserialize_with = "base64::deserialize",
@@ -160,17 +843,333 @@ pub struct Trace<D: AsRef<[u8]> = Vec<u8>> {
     pub data: D,
     pub start_index: usize,
     pub root: Action<TreeSpan>,
+    #[serde(default, skip_serializing_if = "TraceMetadata::is_empty")]
+    pub metadata: TraceMetadata,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceMetadata {
+    /// Name of the tool which produced the trace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_version: Option<String>,
+    /// Capture time in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// Name of the traced file or stream
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Arbitrary user provided key/values e.g. game build or parser version
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub user: BTreeMap<String, String>,
+    /// SHA-256 of the data, checked on load. See [`Trace::update_checksum`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_sha256: Option<String>,
+    /// SHA-256 of the original source file or stream
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_sha256: Option<String>,
+    /// Number of actions outside [`TraceOptions::window`] which were not stored
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub skipped_actions: usize,
+    /// Sorted byte ranges of the data masked by [`Trace::redact`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redacted: Vec<std::ops::Range<usize>>,
+    /// Path of each module, keyed by file name, of spans named `module+0xoffset` by a capture
+    /// which deferred symbolizing its frames
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub modules: BTreeMap<String, String>,
+}
+impl TraceMetadata {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+    /// Fill in tool name, version and capture time if not already set
+    pub fn with_defaults(mut self, tool: &str, tool_version: &str) -> Self {
+        self.tool.get_or_insert_with(|| tool.to_string());
+        self.tool_version
+            .get_or_insert_with(|| tool_version.to_string());
+        self.timestamp.get_or_insert_with(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        });
+        self
+    }
+    /// Combine the metadata of another trace of the same data, see [`Trace::merge`]. Fields set
+    /// to different values are an error, except the capture time of which the earliest is kept
+    fn merge(&mut self, other: TraceMetadata) -> anyhow::Result<()> {
+        fn field<T: PartialEq + std::fmt::Debug>(
+            name: &str,
+            value: &mut Option<T>,
+            other: Option<T>,
+        ) -> anyhow::Result<()> {
+            if let (Some(a), Some(b)) = (&*value, &other) {
+                if a != b {
+                    anyhow::bail!("traces have different {name}: {a:?} and {b:?}");
+                }
+            }
+            if value.is_none() {
+                *value = other;
+            }
+            Ok(())
+        }
+        fn entries(
+            name: &str,
+            map: &mut BTreeMap<String, String>,
+            other: BTreeMap<String, String>,
+        ) -> anyhow::Result<()> {
+            for (key, b) in other {
+                if let Some(a) = map.get(&key).filter(|a| **a != b) {
+                    anyhow::bail!("traces have different {name} {key:?}: {a:?} and {b:?}");
+                }
+                map.insert(key, b);
+            }
+            Ok(())
+        }
+
+        field("tool", &mut self.tool, other.tool)?;
+        field("tool version", &mut self.tool_version, other.tool_version)?;
+        field("source", &mut self.source, other.source)?;
+        field("data checksum", &mut self.data_sha256, other.data_sha256)?;
+        field(
+            "source checksum",
+            &mut self.source_sha256,
+            other.source_sha256,
+        )?;
+        entries("user value", &mut self.user, other.user)?;
+        entries("module", &mut self.modules, other.modules)?;
+        self.timestamp = self.timestamp.into_iter().chain(other.timestamp).min();
+        self.skipped_actions += other.skipped_actions;
+        self.redacted.extend(other.redacted);
+        redact::normalize(&mut self.redacted);
+        Ok(())
+    }
+    /// Key/value pairs for display, in a stable order
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let mut entries = vec![];
+        if let Some(tool) = &self.tool {
+            let version = self.tool_version.as_deref().unwrap_or("");
+            entries.push(("tool".into(), format!("{tool} {version}").trim().into()));
+        }
+        if let Some(timestamp) = self.timestamp {
+            entries.push(("captured".into(), format_timestamp(timestamp)));
+        }
+        if let Some(source) = &self.source {
+            entries.push(("source".into(), source.clone()));
+        }
+        for (k, v) in &self.user {
+            entries.push((k.clone(), v.clone()));
+        }
+        if !self.redacted.is_empty() {
+            let bytes: usize = self.redacted.iter().map(|r| r.len()).sum();
+            entries.push(("redacted".into(), format!("{bytes} bytes")));
+        }
+        entries
+    }
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// Format seconds since the Unix epoch as an ISO 8601 UTC date and time
+fn format_timestamp(timestamp: u64) -> String {
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (timestamp / 86400) as i64;
+    let secs = timestamp % 86400;
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    format!(
+        "{y:04}-{m:02}-{d:02} {:02}:{:02}:{:02} UTC",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
 }
 impl<D: AsRef<[u8]>> Trace<D> {
     pub fn save(&self, path: impl AsRef<Path>) -> Result<(), std::io::Error> {
-        let json = serde_json::to_string(&self).unwrap();
+        let json = serde_json::to_string(&self)?;
         fs::write(path, json)
     }
+    /// Iterate over all reads and seeks along with the byte range they cover and their span path
+    pub fn iter_flat(&self) -> FlatIter<'_> {
+        FlatIter::new(&self.root, self.start_index)
+    }
+    /// Migrate a trace deserialized from an older format version to the current version
+    fn upgrade(&mut self) {
+        if self.version == 0 {
+            // unversioned traces share the version 1 layout
+            self.version = 1;
+        }
+        if self.version == 1 {
+            // bare seek offsets are accepted by SeekAction's deserializer
+            self.version = 2;
+        }
+    }
+}
+
+/// Fields inspected before deserializing a full trace
+#[derive(Deserialize)]
+struct Header {
+    #[serde(default)]
+    version: u32,
+    /// Set for traces saved with [`Trace::save_split`]
+    #[serde(default)]
+    data_file: Option<PathBuf>,
+    /// Set for traces saved with [`Trace::save_chunked`]
+    #[serde(default)]
+    data_chunks: Option<::serde::de::IgnoredAny>,
+    /// Set for traces recorded with [`TraceOptions::sparse_data`]
+    #[serde(default)]
+    segments: Option<::serde::de::IgnoredAny>,
+    /// Set for traces saved with [`Trace::save_interned`]
+    #[serde(default)]
+    strings: Option<::serde::de::IgnoredAny>,
+}
+impl Header {
+    fn parse(json: &[u8]) -> anyhow::Result<Self> {
+        let header: Self = from_json(json)?;
+        let supported = match header.strings {
+            Some(_) => INTERNED_TRACE_VERSION,
+            None => TRACE_VERSION,
+        };
+        if header.version > supported {
+            anyhow::bail!(
+                "trace version {} is newer than the supported version {supported}",
+                header.version
+            );
+        }
+        Ok(header)
+    }
+}
+
+/// Deserialize JSON without a recursion limit as trace trees can be arbitrarily deep
+fn from_json<'de, T: Deserialize<'de>>(json: &'de [u8]) -> serde_json::Result<T> {
+    let mut deserializer = serde_json::Deserializer::from_slice(json);
+    deserializer.disable_recursion_limit();
+    let value = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// Strip module paths from a type name e.g. `alloc::vec::Vec<foo::Bar>` to `Vec<Bar>`
+#[cfg(any(feature = "binrw", feature = "deku"))]
+fn short_type_name(name: &str) -> String {
+    let mut short = String::new();
+    let mut start = 0;
+    for (i, c) in name.char_indices() {
+        if "<>(),;[]& ".contains(c) {
+            short.push_str(name[start..i].rsplit("::").next().unwrap());
+            short.push(c);
+            start = i + c.len_utf8();
+        }
+    }
+    short.push_str(name[start..].rsplit("::").next().unwrap());
+    short
+}
+impl<D: AsRef<[u8]> + From<Vec<u8>>> Trace<D> {
+    /// Load a trace from a file, upgrading traces written by older versions of the format
+    ///
+    /// Data is decoded directly from the file contents into `D`, so loading e.g. a
+    /// `Trace<Arc<[u8]>>` does not keep an intermediate copy around
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read(path)?;
+        let header = Header::parse(&json)?;
+        if header.data_chunks.is_some() {
+            return chunked::load(path, &json);
+        }
+        if header.data_file.is_some() {
+            return split::load(path, &json, |data_path| Ok(fs::read(data_path)?.into()));
+        }
+        Self::from_slice(&json)
+    }
+    /// Deserialize a trace from JSON, upgrading traces written by older versions of the format
+    pub fn from_slice(json: &[u8]) -> anyhow::Result<Self> {
+        let header = Header::parse(json)?;
+        if header.data_file.is_some() || header.data_chunks.is_some() {
+            anyhow::bail!("trace data is stored in a separate file, load it with Trace::load");
+        }
+        if header.segments.is_some() {
+            return sparse::load(json);
+        }
+        if header.strings.is_some() {
+            return strings::load(json);
+        }
+        let mut trace: Self = from_json(json)?;
+        trace.upgrade();
+        trace.verify_checksum()?;
+        Ok(trace)
+    }
+}
+impl Trace {
+    /// Combine multiple traces of the same underlying data into a single trace where each input
+    /// becomes a named child span of the root. The metadata of the inputs is combined, with
+    /// fields set to different values by different inputs being an error
+    pub fn merge<N: Into<std::borrow::Cow<'static, str>>>(
+        traces: impl IntoIterator<Item = (N, Trace)>,
+    ) -> anyhow::Result<Trace> {
+        let mut merged: Option<Trace> = None;
+        let mut actions = vec![];
+        for (name, trace) in traces {
+            let name = name.into();
+            match &mut merged {
+                Some(merged) if merged.data != trace.data => {
+                    anyhow::bail!("trace {name:?} was captured from different data")
+                }
+                Some(merged) => merged
+                    .metadata
+                    .merge(trace.metadata)
+                    .map_err(|e| e.context(format!("trace {name:?} has conflicting metadata")))?,
+                None => {
+                    merged = Some(Trace {
+                        version: TRACE_VERSION,
+                        data: trace.data,
+                        start_index: trace.start_index,
+                        root: Action::Seek(0.into()),
+                        metadata: trace.metadata,
+                    })
+                }
+            }
+
+            actions.push(Action::Seek(trace.start_index.into()));
+            actions.push(Action::Span(match trace.root {
+                Action::Span(TreeSpan(span)) => TreeSpan(ReadSpan { name, ..span }),
+                other => TreeSpan(ReadSpan {
+                    name,
+                    value: None,
+                    actions: vec![other],
+                    timings: vec![],
+                    location: None,
+                }),
+            }));
+        }
+        let Some(mut trace) = merged else {
+            anyhow::bail!("no traces to merge");
+        };
+        trace.root = Action::Span(TreeSpan(ReadSpan {
+            name: "root".into(),
+            value: None,
+            actions,
+            timings: vec![],
+            location: None,
+        }));
+        Ok(trace)
+    }
 }
 
 mod base64 {
     use base64::prelude::*;
-    use serde::{Deserialize, Serialize};
+    use serde::Serialize;
     use serde::{Deserializer, Serializer};
 
     pub fn serialize<V, S: Serializer>(v: V, s: S) -> Result<S::Ok, S::Error>
@@ -182,15 +1181,23 @@ mod base64 {
     }
 
     pub fn deserialize<'de, V: From<Vec<u8>>, D: Deserializer<'de>>(d: D) -> Result<V, D::Error> {
-        let base64 = String::deserialize(d)?;
-        BASE64_STANDARD
-            .decode(base64.as_bytes())
-            .map_err(serde::de::Error::custom)
-            .map(|v| v.into())
+        // decode straight from the (possibly borrowed) input instead of allocating a String
+        struct Visitor;
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a base64 string")
+            }
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                BASE64_STANDARD.decode(v).map_err(E::custom)
+            }
+        }
+        d.deserialize_str(Visitor).map(|v| v.into())
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[repr(transparent)]
 pub struct TreeSpan(pub ReadSpan);
 impl TreeSpan {
@@ -198,6 +1205,9 @@ impl TreeSpan {
         let read_span = spans.remove(&id).unwrap();
         Self(ReadSpan {
             name: read_span.name,
+            value: read_span.value,
+            timings: read_span.timings,
+            location: read_span.location,
             actions: read_span
                 .actions
                 .into_iter()
@@ -205,75 +1215,228 @@ impl TreeSpan {
                     Action::Read(i) => Action::Read(i),
                     Action::Seek(i) => Action::Seek(i),
                     Action::Span(id) => Action::Span(Self::into_tree(id, spans)),
+                    Action::Error(e) => Action::Error(e),
+                    Action::ReadData(d) => Action::ReadData(d),
+                    Action::SubTrace(t) => Action::SubTrace(t),
                 })
                 .collect(),
         })
     }
+    /// Recursively remove child spans which contain no actions
+    fn prune_empty(&mut self) {
+        let keep: Vec<bool> = self
+            .0
+            .actions
+            .iter_mut()
+            .map(|action| match action {
+                Action::Span(child) => {
+                    child.prune_empty();
+                    !child.0.actions.is_empty()
+                }
+                _ => true,
+            })
+            .collect();
+        let mut keep_actions = keep.iter();
+        self.0.actions.retain(|_| *keep_actions.next().unwrap());
+        let mut keep_timings = keep.iter();
+        self.0.timings.retain(|_| *keep_timings.next().unwrap());
+    }
 }
 
 impl Drop for CounterSubscriberInner {
     fn drop(&mut self) {
-        let tree = TreeSpan::into_tree(self.root_span.as_ref().cloned().unwrap(), &mut self.spans);
-        Trace {
-            data: std::mem::take(&mut self.data).into_inner(),
+        let mut tree =
+            TreeSpan::into_tree(self.root_span.as_ref().cloned().unwrap(), &mut self.spans);
+        if self.options.window.is_some() {
+            tree.prune_empty();
+        }
+        let segments = self.segments.take();
+        let data = match &segments {
+            // written as is rather than made dense
+            Some(_) if matches!(self.output, Output::File(_)) => vec![],
+            Some(segments) => segments.to_dense(),
+            None => std::mem::take(&mut self.data).into_inner(),
+        };
+        let mut trace = Trace {
+            version: TRACE_VERSION,
+            data,
             start_index: self.start_index,
             root: Action::Span(tree),
+            metadata: std::mem::take(&mut self.options.metadata)
+                .with_defaults("ser-hex", env!("CARGO_PKG_VERSION")),
+        };
+        trace.metadata.skipped_actions = self.skipped_actions;
+        if self.options.checksum {
+            match &segments {
+                Some(segments) => trace.metadata.data_sha256 = Some(segments.sha256_hex()),
+                None => trace.update_checksum(),
+            }
+        }
+        match &self.output {
+            Output::File(path) if segments.is_some() => {
+                sparse::save(path, &trace, segments.as_ref().unwrap()).unwrap()
+            }
+            Output::File(path) if self.options.chunk_data.is_some() => trace
+                .save_chunked(path, self.options.chunk_data.unwrap())
+                .unwrap(),
+            Output::File(path) if self.options.split_data => trace.save_split(path).unwrap(),
+            Output::File(path) => trace.save(path).unwrap(),
+            Output::Parent(parent) => parent.push_action(
+                Action::SubTrace(SubTrace::Inline(trace.into())),
+                parent.now(),
+            ),
         }
-        .save(&self.out_path)
-        .unwrap()
     }
 }
 
 #[derive(Clone)]
 struct CounterSubscriber {
     inner: Arc<Mutex<CounterSubscriberInner>>,
+    /// Start of the trace if timings are being recorded
+    epoch: Option<Instant>,
+    /// Previous default subscriber which spans and events are forwarded to, see
+    /// [`TraceOptions::tee`]
+    forward: Option<Dispatch>,
 }
 impl CounterSubscriber {
-    fn new(out_path: PathBuf, data: Cursor<Vec<u8>>) -> Self {
+    fn new(output: Output, data: Option<Cursor<Vec<u8>>>, options: TraceOptions) -> Self {
         Self {
-            inner: Arc::new(Mutex::new(CounterSubscriberInner::new(out_path, data))),
+            epoch: options.record_timings.then(Instant::now),
+            // never forward to an enclosing trace as nested traces are attached to it instead
+            forward: options
+                .tee
+                .then(|| tracing::dispatcher::get_default(Dispatch::clone))
+                .filter(|dispatch| !dispatch.is::<Self>()),
+            inner: Arc::new(Mutex::new(CounterSubscriberInner::new(
+                output, data, options,
+            ))),
         }
     }
-    fn read<'d, 't, 'r: 't, R: Read + 'r, P, F, T>(
-        out_path: P,
+    /// The ser-hex subscriber of the current thread's default dispatcher, if any
+    fn current() -> Option<Self> {
+        tracing::dispatcher::get_default(|dispatch| dispatch.downcast_ref::<Self>().cloned())
+    }
+    fn read<'d, 't, 'r: 't, R: Read + ?Sized + 'r, O, F, T>(
+        output: O,
         data: Option<Cursor<Vec<u8>>>,
+        options: TraceOptions,
         reader: &'r mut R,
         f: F,
     ) -> T
     where
         F: FnOnce(&mut TraceStream<&'r mut R>) -> T,
-        P: Into<PathBuf>,
+        O: Into<Output>,
     {
-        let sub = Self::new(out_path.into(), data.unwrap_or_default());
+        let sub = Self::new(output.into(), data, options);
         tracing::subscriber::with_default(sub.clone(), || {
             // must build TraceStream after defualt subscriber is set because it enters root span
             f(&mut TraceStream::new_internal(reader, sub, None))
         })
     }
-    fn read_action(&self, buf: &[u8], size: usize) {
+    /// The forwarded subscriber and its id for a span, if teeing
+    fn forwarded(&self, id: &Id) -> Option<(&Dispatch, Id)> {
+        let forward = self.forward.as_ref()?;
+        let id = self.inner.lock().unwrap().forwarded.get(id)?.clone();
+        Some((forward, id))
+    }
+    /// Time since the trace began if timings are being recorded
+    fn now(&self) -> Option<u64> {
+        self.epoch.map(|epoch| epoch.elapsed().as_nanos() as u64)
+    }
+    /// Rebase a sparse incremental trace onto stream offsets the first time the position of the
+    /// stream is known
+    fn anchor(&self, position: impl FnOnce() -> io::Result<u64>) {
+        {
+            let lock = self.inner.lock().unwrap();
+            if lock.anchored || lock.segments.is_none() {
+                return;
+            }
+        }
+        // queried without holding the lock in case the stream is itself traced
+        let position = position();
         let mut lock = self.inner.lock().unwrap();
-        let current = lock.stack.last().cloned().unwrap();
-        lock.data.write_all(&buf[..size]).unwrap();
-        lock.spans
-            .get_mut(&current)
-            .unwrap()
-            .actions
-            .push(Action::Read(size));
+        let inner = &mut *lock;
+        inner.anchored = true;
+        let (Ok(position), Some(segments)) = (position, &mut inner.segments) else {
+            return;
+        };
+        let Some(delta) = position.checked_sub(inner.data.position()) else {
+            return;
+        };
+        segments.shift(delta as usize);
+        inner.start_index += delta as usize;
+        inner.stored_position += delta;
+        inner.data.set_position(position);
     }
-    fn seek_action(&self, to: u64) {
+    fn read_action(&self, buf: &[u8], size: usize, start: Option<u64>) {
+        let end = self.now();
+        let mut lock = self.inner.lock().unwrap();
+        let pos = lock.data.position();
+        let mut stored = 0..match lock.options.max_data_size {
+            Some(max) => size.min((max as u64).saturating_sub(pos) as usize),
+            None => size,
+        };
+        if let Some(window) = &lock.options.window {
+            let offset = |i: usize| i.saturating_sub(pos as usize);
+            stored = offset(window.start).min(stored.end)..offset(window.end).min(stored.end);
+        }
+        if !stored.is_empty() {
+            let inner = &mut *lock;
+            match &mut inner.segments {
+                Some(segments) => segments.write(pos as usize + stored.start, &buf[stored]),
+                None => {
+                    inner.data.set_position(pos + stored.start as u64);
+                    inner.data.write_all(&buf[stored]).unwrap();
+                }
+            }
+        }
+        lock.data.set_position(pos + size as u64);
+        if !lock.in_window(pos..pos + size as u64) {
+            return;
+        }
+        lock.sync_position(pos, start.zip(end));
+        lock.stored_position = pos + size as u64;
+        let action = if lock.options.record_read_data {
+            Action::ReadData(buf[..size].to_vec())
+        } else {
+            Action::Read(size)
+        };
+        if lock.options.coalesce_reads && lock.coalesce_read(&action, start.zip(end)) {
+            return;
+        }
+        lock.push_action(action, start.zip(end));
+    }
+    fn seek_action(&self, to: u64, from: SeekFrom, start: Option<u64>) {
+        let end = self.now();
         let mut lock = self.inner.lock().unwrap();
-        let current = lock.stack.last().cloned().unwrap();
         lock.data.seek(SeekFrom::Start(to)).unwrap();
-        lock.spans
-            .get_mut(&current)
+        if !lock.in_window(to..to) {
+            return;
+        }
+        lock.stored_position = to;
+        let action = Action::Seek(SeekAction {
+            to: to as usize,
+            from: Some(from.into()),
+        });
+        lock.push_action(action, start.zip(end));
+    }
+    fn error_action(&self, error: &io::Error, size: usize, start: Option<u64>) {
+        self.push_action(Action::Error(ReadError::new(error, size)), start);
+    }
+    fn push_action(&self, action: Action<Id>, start: Option<u64>) {
+        let end = self.now();
+        self.inner
+            .lock()
             .unwrap()
-            .actions
-            .push(Action::Seek(to as usize));
+            .push_action(action, start.zip(end));
     }
 }
 
 impl Subscriber for CounterSubscriber {
-    fn register_callsite(&self, _meta: &Metadata<'_>) -> subscriber::Interest {
+    fn register_callsite(&self, meta: &'static Metadata<'static>) -> subscriber::Interest {
+        if let Some(forward) = &self.forward {
+            forward.register_callsite(meta);
+        }
         subscriber::Interest::always()
     }
 
@@ -286,14 +1449,41 @@ impl Subscriber for CounterSubscriber {
         let id = lock.last_id;
         let id = Id::from_u64(id);
 
-        lock.spans.insert(id.clone(), ReadSpan::new(name));
+        let mut span = ReadSpan::new(name);
+        let mut args = ArgsVisitor::default();
+        new_span.record(&mut args);
+        if let Some(name) = args.name {
+            span.name = name.into();
+        }
+        if !args.args.is_empty() {
+            span.name = format!("{}({})", span.name, args.args.join(", ")).into();
+        }
+        lock.spans.insert(id.clone(), span);
         lock.metadata.insert(id.clone(), metadata);
         assert_eq!(new_span.parent(), None);
         assert!(new_span.is_contextual());
         // TODO set root here if new_span.is_root()?
+        drop(lock);
+
+        if let Some(forward) = self.forward.as_ref().filter(|f| f.enabled(metadata)) {
+            let forwarded = forward.new_span(new_span);
+            let mut lock = self.inner.lock().unwrap();
+            lock.forwarded.insert(id.clone(), forwarded);
+        }
         id
     }
-    fn try_close(&self, _id: Id) -> bool {
+    fn clone_span(&self, id: &Id) -> Id {
+        if let Some((forward, forwarded)) = self.forwarded(id) {
+            forward.clone_span(&forwarded);
+        }
+        id.clone()
+    }
+    fn try_close(&self, id: Id) -> bool {
+        if let Some((forward, forwarded)) = self.forwarded(&id) {
+            if forward.try_close(forwarded) {
+                self.inner.lock().unwrap().forwarded.remove(&id);
+            }
+        }
         true
     }
     fn current_span(&self) -> Current {
@@ -306,30 +1496,80 @@ impl Subscriber for CounterSubscriber {
         }
     }
 
-    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
-    fn record(&self, _: &Id, _values: &span::Record<'_>) {}
-    fn event(&self, _event: &Event<'_>) {}
+    fn record_follows_from(&self, span: &Id, follows: &Id) {
+        if let (Some((forward, span)), Some((_, follows))) =
+            (self.forwarded(span), self.forwarded(follows))
+        {
+            forward.record_follows_from(&span, &follows);
+        }
+    }
+    fn record(&self, span: &Id, values: &span::Record<'_>) {
+        let mut args = ArgsVisitor::default();
+        values.record(&mut args);
+        if let Some(name) = args.name {
+            if let Some(span) = self.inner.lock().unwrap().spans.get_mut(span) {
+                span.name = name.into();
+            }
+        }
+
+        if let Some((forward, span)) = self.forwarded(span) {
+            forward.record(&span, values);
+        }
+    }
+    fn event(&self, event: &Event<'_>) {
+        if let Some(forward) = self
+            .forward
+            .as_ref()
+            .filter(|f| f.enabled(event.metadata()))
+        {
+            forward.event(event);
+        }
+
+        let mut visitor = ValueVisitor::default();
+        event.record(&mut visitor);
+        if let Some(value) = visitor.into_value() {
+            let mut lock = self.inner.lock().unwrap();
+            if let Some(current) = lock.stack.last().cloned() {
+                lock.spans.get_mut(&current).unwrap().value = Some(value);
+            }
+        }
+    }
 
     fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
         true
     }
 
     fn enter(&self, span: &Id) {
+        let now = self.now();
         let mut lock = self.inner.lock().unwrap();
-        if let Some(current) = lock.stack.last().cloned() {
-            lock.spans
-                .get_mut(&current)
-                .unwrap()
-                .actions
-                .push(Action::Span(span.clone()));
+        if !lock.stack.is_empty() {
+            // end is filled in on exit
+            lock.push_action(Action::Span(span.clone()), now.zip(now));
         } else {
             lock.root_span = Some(span.clone());
         }
         lock.stack.push(span.clone());
+        drop(lock);
+
+        if let Some((forward, span)) = self.forwarded(span) {
+            forward.enter(&span);
+        }
     }
     fn exit(&self, span: &Id) {
+        let now = self.now();
         let mut lock = self.inner.lock().unwrap();
         assert_eq!(&lock.stack.pop().unwrap(), span);
+        if let (Some(now), Some(parent)) = (now, lock.stack.last().cloned()) {
+            // the exited span is always the last action of its parent
+            if let Some(timing) = lock.spans.get_mut(&parent).unwrap().timings.last_mut() {
+                timing.end = now;
+            }
+        }
+        drop(lock);
+
+        if let Some((forward, span)) = self.forwarded(span) {
+            forward.exit(&span);
+        }
     }
 }
 
@@ -367,6 +1607,16 @@ mod test {
         reader
     }
 
+    #[crate::instrument(args(index))]
+    fn read_element<R: Read>(reader: &mut R, index: usize) -> Result<u8, Error> {
+        reader.read_u8()
+    }
+
+    #[instrument(name = "read_value", skip_all)]
+    fn read_value<R: Read>(reader: &mut R) -> Result<u32, Error> {
+        Ok(crate::value!(reader.read_u32::<LE>()?))
+    }
+
     #[test]
     fn test_trace_read() -> Result<(), Error> {
         read("trace_read.json", &mut new_reader(), |s| {
@@ -404,4 +1654,1246 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(1709251199), "2024-02-29 23:59:59 UTC");
+    }
+
+    #[test]
+    fn test_current_span_path() -> Result<(), Error> {
+        #[instrument(name = "outer", skip_all)]
+        fn outer() -> Vec<std::borrow::Cow<'static, str>> {
+            inner()
+        }
+        #[instrument(name = "inner", skip_all)]
+        fn inner() -> Vec<std::borrow::Cow<'static, str>> {
+            current_span_path()
+        }
+
+        assert!(current_span_path().is_empty());
+        let path = read_incremental("trace_span_path.json", &mut new_reader(), |_| outer());
+        assert_eq!(path, ["outer", "inner"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_data_size() -> Result<(), Error> {
+        let options = TraceOptions {
+            max_data_size: Some(4),
+            ..Default::default()
+        };
+        read_options(
+            "trace_max_data_size.json",
+            &mut new_reader(),
+            options,
+            read_stuff,
+        )?;
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_max_data_size.json")?)?;
+        assert_eq!(trace.data, [1, 2, 3, 4]);
+        assert_eq!(trace.iter_flat().last().unwrap().range, 8..9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_error() -> Result<(), Error> {
+        let mut s = TraceStream::new_incremental("trace_read_error.json", new_reader());
+        let mut buf = [0; 32];
+        assert!(s.read_exact(&mut buf).is_err());
+        drop(s);
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_read_error.json")?)?;
+        let error = trace.iter_flat().find_map(|f| match f.action {
+            Action::Error(e) => Some(e.clone()),
+            _ => None,
+        });
+        assert_eq!(
+            error.map(|e| (e.kind, e.size)),
+            Some(("UnexpectedEof".into(), 32))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_read_data() -> Result<(), Error> {
+        let options = TraceOptions {
+            record_read_data: true,
+            ..Default::default()
+        };
+        read_options(
+            "trace_read_data.json",
+            &mut new_reader(),
+            options,
+            read_stuff,
+        )?;
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_read_data.json")?)?;
+        let reads: Vec<_> = trace
+            .iter_flat()
+            .filter_map(|f| match f.action {
+                Action::ReadData(data) => Some((f.range, data.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            reads,
+            vec![
+                (2..3, vec![3]),
+                (3..7, vec![4, 5, 6, 7]),
+                (8..9, vec![9]),
+                (8..9, vec![9]),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_unversioned() -> anyhow::Result<()> {
+        let json = br#"{"data":"AQID","start_index":0,"root":{"Span":{"name":"root","actions":[{"Read":3},{"Seek":1}]}}}"#;
+        let trace: Trace = Trace::from_slice(json)?;
+        assert_eq!(trace.version, TRACE_VERSION);
+        assert_eq!(trace.data, [1, 2, 3]);
+        assert_eq!(trace.iter_flat().last().unwrap().range, 1..1);
+
+        let json = br#"{"version":4294967295,"data":"","start_index":0,"root":{"Read":0}}"#;
+        assert!(Trace::<Vec<u8>>::from_slice(json).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_interned() -> anyhow::Result<()> {
+        let trace = new_trace(
+            vec![0; 4],
+            0,
+            span(
+                "root",
+                vec![
+                    span("read_u8", vec![Action::Read(1)]),
+                    span("read_u8", vec![Action::Read(1)]),
+                    span("read_u16", vec![Action::Read(2)]),
+                ],
+            ),
+        );
+        trace.save_interned("trace_interned.json")?;
+
+        let json: serde_json::Value = serde_json::from_slice(&fs::read("trace_interned.json")?)?;
+        assert_eq!(json["version"], INTERNED_TRACE_VERSION);
+        assert_eq!(json["strings"], serde_json::json!(["read_u8"]));
+        let actions = &json["root"]["Span"]["actions"];
+        assert_eq!(actions[0]["Span"]["name"], 0);
+        assert_eq!(actions[2]["Span"]["name"], "read_u16");
+        let loaded = Trace::<Vec<u8>>::load("trace_interned.json")?;
+        assert_eq!(loaded.version, TRACE_VERSION);
+        let names: Vec<_> = loaded
+            .iter_flat()
+            .map(|f| f.path.last().unwrap().span.0.name.to_string())
+            .collect();
+        assert_eq!(names, ["read_u8", "read_u8", "read_u16"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_arc() -> anyhow::Result<()> {
+        let json = br#"{"version":1,"data":"AQID","start_index":0,"root":{"Read":3}}"#;
+        let trace = Trace::<Arc<[u8]>>::from_slice(json)?;
+        assert_eq!(&*trace.data, [1, 2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() {
+        let trace = new_trace(
+            vec![0; 8],
+            0,
+            span(
+                "root",
+                vec![
+                    Action::Read(4),
+                    span("empty", vec![]),
+                    Action::Seek(10.into()),
+                    Action::Read(2),
+                ],
+            ),
+        );
+        let problems: Vec<_> = trace.validate().into_iter().map(|p| p.kind).collect();
+        assert_eq!(
+            problems,
+            vec![
+                ProblemKind::SeekOutOfBounds { to: 10, len: 8 },
+                ProblemKind::ReadOutOfBounds {
+                    range: 10..12,
+                    len: 8
+                },
+                ProblemKind::EmptySpan {
+                    name: "empty".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_instrument_args() -> Result<(), Error> {
+        read_incremental("trace_instrument_args.json", &mut new_reader(), |s| {
+            read_element(s, 0)?;
+            read_element(s, 1)
+        })?;
+
+        let trace: Trace = Trace::load("trace_instrument_args.json").unwrap();
+        let names: Vec<_> = trace
+            .iter_flat()
+            .map(|f| f.path.last().unwrap().span.0.name.to_string())
+            .collect();
+        assert_eq!(names, ["read_element(index=0)", "read_element(index=1)"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_seek_origin() -> Result<(), Error> {
+        read_incremental("trace_seek_origin.json", &mut new_reader(), read_stuff)?;
+
+        let trace: Trace = Trace::load("trace_seek_origin.json").unwrap();
+        let seeks: Vec<_> = trace
+            .iter_flat()
+            .filter_map(|f| match f.action {
+                Action::Seek(seek) => Some(*seek),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            seeks,
+            [
+                SeekAction {
+                    to: 8,
+                    from: Some(SeekOrigin::Current(1))
+                },
+                SeekAction {
+                    to: 8,
+                    from: Some(SeekOrigin::Current(-1))
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_value() -> Result<(), Error> {
+        read_incremental("trace_value.json", &mut new_reader(), read_value)?;
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_value.json")?)?;
+        let Action::Span(root) = &trace.root else {
+            panic!("expected root span");
+        };
+        let Action::Span(span) = &root.0.actions[0] else {
+            panic!("expected read_value span");
+        };
+        assert_eq!(
+            span.0.value,
+            Some(Value {
+                type_name: "u32".into(),
+                value: "100992003".into(),
+            })
+        );
+        Ok(())
+    }
+
+    fn new_trace(data: Vec<u8>, start_index: usize, root: Action<TreeSpan>) -> Trace {
+        Trace {
+            version: TRACE_VERSION,
+            data,
+            start_index,
+            root,
+            metadata: Default::default(),
+        }
+    }
+
+    fn span(name: &'static str, actions: Vec<Action<TreeSpan>>) -> Action<TreeSpan> {
+        Action::Span(TreeSpan(ReadSpan {
+            name: name.into(),
+            value: None,
+            actions,
+            timings: vec![],
+            location: None,
+        }))
+    }
+
+    #[test]
+    fn test_iter_flat() {
+        let trace = new_trace(
+            vec![0; 16],
+            2,
+            span(
+                "root",
+                vec![
+                    Action::Read(1),
+                    span("nested", vec![Action::Read(4)]),
+                    Action::Seek(10.into()),
+                    Action::Read(2),
+                ],
+            ),
+        );
+        let flat: Vec<_> = trace
+            .iter_flat()
+            .map(|f| {
+                let path: Vec<_> = f.path.iter().map(|s| s.span.0.name.as_ref()).collect();
+                (f.range, path, f.index)
+            })
+            .collect();
+        assert_eq!(
+            flat,
+            vec![
+                (2..3, vec!["root"], 0),
+                (3..7, vec!["root", "nested"], 0),
+                (10..10, vec!["root"], 2),
+                (10..12, vec!["root"], 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge() -> anyhow::Result<()> {
+        let mut header = new_trace(vec![0; 16], 0, span("root", vec![Action::Read(4)]));
+        header.metadata.timestamp = Some(20);
+        header.metadata.user.insert("build".into(), "1".into());
+        let mut body = new_trace(vec![0; 16], 4, span("root", vec![Action::Read(12)]));
+        body.metadata.timestamp = Some(10);
+        body.metadata.source = Some("save.bin".into());
+        let mut conflicting = body.clone();
+        conflicting.metadata.user.insert("build".into(), "2".into());
+        assert!(Trace::merge([("header", header.clone()), ("body", conflicting)]).is_err());
+
+        let merged = Trace::merge([("header", header), ("body", body)])?;
+        assert_eq!(merged.metadata.timestamp, Some(10));
+        assert_eq!(merged.metadata.source.as_deref(), Some("save.bin"));
+        assert_eq!(merged.metadata.user["build"], "1");
+        let flat: Vec<_> = merged
+            .iter_flat()
+            .filter(|f| matches!(f.action, Action::Read(_)))
+            .map(|f| (f.range, f.path.last().unwrap().span.0.name.to_string()))
+            .collect();
+        assert_eq!(flat, vec![(0..4, "header".into()), (4..16, "body".into())]);
+
+        let other = new_trace(vec![1; 16], 0, span("root", vec![]));
+        assert!(Trace::merge([("a", merged), ("b", other)]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sub_trace() -> Result<(), Error> {
+        read_incremental("trace_sub_trace.json", &mut new_reader(), |reader| {
+            let mut blob = [0; 4];
+            reader.read_exact(&mut blob)?;
+            read_nested(&mut Cursor::new(blob), read_nested_stuff)?;
+            attach_sub_trace(SubTrace::File("other.json".into()));
+            Ok::<_, Error>(())
+        })?;
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_sub_trace.json")?)?;
+        let Action::Span(root) = &trace.root else {
+            panic!("expected root span");
+        };
+        let [Action::Read(4), Action::SubTrace(SubTrace::Inline(inner)), Action::SubTrace(SubTrace::File(path))] =
+            root.0.actions.as_slice()
+        else {
+            panic!("unexpected actions {:?}", root.0.actions);
+        };
+        assert_eq!(inner.data, vec![3, 4, 5, 6]);
+        assert_eq!(
+            inner.iter_flat().map(|f| f.range).collect::<Vec<_>>(),
+            vec![0..4]
+        );
+        assert_eq!(path, Path::new("other.json"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_timings() -> Result<(), Error> {
+        let options = TraceOptions {
+            record_timings: true,
+            ..Default::default()
+        };
+        read_options("trace_timings.json", &mut new_reader(), options, read_stuff)?;
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_timings.json")?)?;
+        fn check(span: &TreeSpan, bounds: Option<Timing>) {
+            assert_eq!(span.0.actions.len(), span.0.timings.len());
+            for (action, timing) in span.0.actions.iter().zip(&span.0.timings) {
+                assert!(timing.start <= timing.end);
+                if let Some(bounds) = bounds {
+                    assert!(bounds.start <= timing.start && timing.end <= bounds.end);
+                }
+                if let Action::Span(child) = action {
+                    check(child, Some(*timing));
+                }
+            }
+        }
+        let Action::Span(root) = &trace.root else {
+            panic!("expected root span");
+        };
+        check(root, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_disabled() -> Result<(), Error> {
+        let mut stream = TraceStream::disabled(new_reader());
+        assert!(!stream.is_enabled());
+        read_stuff(&mut stream)?;
+        assert_eq!(stream.stream_position()?, 9);
+
+        // nested streams outside of a trace are forwarded untraced
+        read_nested(&mut new_reader(), |stream| {
+            assert!(!stream.is_enabled());
+            read_stuff(stream)
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sampler() -> Result<(), Error> {
+        let sampler = Sampler::new().every(2).matching("*.bin");
+        let sampled: Vec<_> = ["a.bin", "b.txt", "c.bin", "d.bin", "bin"]
+            .into_iter()
+            .map(|name| sampler.sample(name))
+            .collect();
+        assert_eq!(sampled, [true, false, false, true, false]);
+
+        static SKIP_ALL: Sampler = Sampler::new().matching("?.bin");
+        let options = TraceOptions {
+            sampler: Some(&SKIP_ALL),
+            ..Default::default()
+        };
+        let stream = TraceStream::new_options("trace_sampler.json", new_reader(), options);
+        assert!(!stream.is_enabled());
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_data() -> anyhow::Result<()> {
+        let options = TraceOptions {
+            split_data: true,
+            ..Default::default()
+        };
+        read_options("trace_split.json", &mut new_reader(), options, read_stuff)?;
+        assert!(Path::new("trace_split.json.bin").exists());
+
+        let trace: Trace = Trace::load("trace_split.json")?;
+        assert_eq!(trace.data, new_reader().into_inner());
+        assert!(Trace::<Vec<u8>>::from_slice(&fs::read("trace_split.json")?).is_err());
+
+        let mapped = Trace::open_mmap("trace_split.json")?;
+        assert_eq!(&mapped.data[..], &trace.data[..]);
+
+        // regular traces are decoded into an anonymous mapping
+        trace.save("trace_unsplit.json")?;
+        let mapped = Trace::open_mmap("trace_unsplit.json")?;
+        assert_eq!(&mapped.data[..], &trace.data[..]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunked_data() -> anyhow::Result<()> {
+        let options = TraceOptions {
+            chunk_data: Some(ChunkOptions {
+                chunk_size: 4,
+                compress: true,
+            }),
+            ..Default::default()
+        };
+        read_options("trace_chunked.json", &mut new_reader(), options, read_stuff)?;
+        assert!(Path::new("trace_chunked.json.chunks").exists());
+
+        let trace: Trace = Trace::load("trace_chunked.json")?;
+        assert_eq!(trace.data, new_reader().into_inner());
+        assert!(Trace::<Vec<u8>>::from_slice(&fs::read("trace_chunked.json")?).is_err());
+
+        let chunked = ChunkedData::open("trace_chunked.json")?;
+        assert_eq!(chunked.len(), 19);
+        assert_eq!(chunked.chunk_count(), 5);
+        assert_eq!(chunked.read_chunk(4)?, &trace.data[16..]);
+        assert_eq!(chunked.read_range(3..9)?, &trace.data[3..9]);
+        assert!(chunked.read_range(3..20).is_err());
+
+        let mapped = Trace::open_mmap("trace_chunked.json")?;
+        assert_eq!(&mapped.data[..], &trace.data[..]);
+
+        trace.save_chunked(
+            "trace_chunked_raw.json",
+            ChunkOptions {
+                chunk_size: 8,
+                compress: false,
+            },
+        )?;
+        assert_eq!(fs::metadata("trace_chunked_raw.json.chunks")?.len(), 19);
+        assert_eq!(
+            Trace::<Vec<u8>>::load("trace_chunked_raw.json")?.data,
+            trace.data
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract() {
+        let trace = new_trace(
+            (0..16).collect(),
+            0,
+            span(
+                "root",
+                vec![
+                    Action::Read(2),
+                    span(
+                        "a",
+                        vec![Action::Read(2), Action::Seek(10.into()), Action::Read(4)],
+                    ),
+                    Action::Read(2),
+                ],
+            ),
+        );
+
+        let extracted = trace.extract(&[1]).unwrap();
+        assert_eq!(extracted.data, (2..14).collect::<Vec<u8>>());
+        assert_eq!(extracted.start_index, 0);
+        let ranges: Vec<_> = extracted.iter_flat().map(|f| f.range).collect();
+        assert_eq!(ranges, vec![0..2, 8..8, 8..12]);
+
+        let sliced = trace.slice(11..13).unwrap();
+        let Action::Span(root) = &sliced.root else {
+            panic!("expected root span");
+        };
+        assert_eq!(root.0.name, "a");
+        assert_eq!(sliced.data, extracted.data);
+
+        assert!(trace.extract(&[5]).is_none());
+        assert!(trace.slice(10..20).is_none());
+
+        let trace = new_trace(
+            (0..16).collect(),
+            0,
+            span(
+                "root",
+                vec![
+                    Action::Read(2),
+                    span(
+                        "a",
+                        vec![
+                            Action::Read(2),
+                            Action::Seek(SeekAction {
+                                to: 12,
+                                from: Some(SeekOrigin::End(-4)),
+                            }),
+                            Action::Read(2),
+                        ],
+                    ),
+                ],
+            ),
+        );
+        let extracted = trace.extract(&[1]).unwrap();
+        let seeks: Vec<_> = extracted
+            .iter_flat()
+            .filter_map(|f| match f.action {
+                Action::Seek(seek) => Some(*seek),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            seeks,
+            vec![SeekAction {
+                to: 10,
+                from: Some(SeekOrigin::Start(10)),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_schema() {
+        let trace = new_trace(
+            vec![1, 2],
+            0,
+            span(
+                "root",
+                vec![
+                    Action::Read(1),
+                    Action::Seek(SeekAction {
+                        to: 0,
+                        from: Some(SeekOrigin::Start(0)),
+                    }),
+                    Action::ReadData(vec![1]),
+                    Action::Error(ReadError {
+                        kind: "UnexpectedEof".into(),
+                        message: "eof".into(),
+                        size: 4,
+                    }),
+                    Action::SubTrace(SubTrace::File("inner.json".into())),
+                ],
+            ),
+        );
+        // changes to the serialized model must be reflected in json_schema
+        let expected = serde_json::json!({
+            "version": TRACE_VERSION,
+            "data": "AQI=",
+            "start_index": 0,
+            "root": { "Span": { "name": "root", "actions": [
+                { "Read": 1 },
+                { "Seek": { "to": 0, "from": { "Start": 0 } } },
+                { "ReadData": "AQ==" },
+                { "Error": { "kind": "UnexpectedEof", "message": "eof", "size": 4 } },
+                { "SubTrace": { "File": "inner.json" } }
+            ] } }
+        });
+        assert_eq!(serde_json::to_value(&trace).unwrap(), expected);
+
+        let schema = json_schema();
+        let variants: Vec<_> = schema["$defs"]["Action"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v["required"][0].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            variants,
+            ["Read", "Seek", "Span", "Error", "ReadData", "SubTrace"]
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut builder = TraceBuilder::new();
+        builder.seek(1);
+        builder.begin_span("header");
+        builder.read(&[1, 2]);
+        builder.begin_span("magic");
+        builder.read(&[3]);
+
+        let trace = builder.trace();
+        assert_eq!(trace.data, vec![0, 1, 2, 3]);
+        let flat: Vec<_> = trace
+            .iter_flat()
+            .map(|f| (f.range.clone(), f.index_path()))
+            .collect();
+        assert_eq!(
+            flat,
+            vec![(1..1, vec![0]), (1..3, vec![1, 0]), (3..4, vec![1, 1, 0])]
+        );
+        assert!(trace.validate().is_empty());
+    }
+
+    #[test]
+    fn test_coverage() {
+        let trace = new_trace(
+            vec![0; 16],
+            2,
+            span(
+                "root",
+                vec![
+                    Action::Read(4),
+                    Action::Seek(4.into()),
+                    Action::Read(4),
+                    Action::Seek(12.into()),
+                    Action::Read(2),
+                ],
+            ),
+        );
+        assert_eq!(trace.covered_ranges(), vec![2..8, 12..14]);
+        assert_eq!(trace.uncovered_ranges(), vec![0..2, 8..12, 14..16]);
+        assert_eq!(trace.coverage(), 0.5);
+    }
+
+    #[cfg(feature = "binrw")]
+    #[test]
+    fn test_binrw() -> Result<(), Error> {
+        // the extern crate rather than crate::binrw for paths emitted by the derive
+        use ::binrw::{self, BinRead};
+
+        use crate::binrw::TracedBinReaderExt;
+
+        #[crate::binrw::spans]
+        #[derive(BinRead)]
+        struct Header {
+            magic: u8,
+            size: u32,
+            #[br(calc = size + 1)]
+            end: u32,
+        }
+
+        let header: Header = read("trace_binrw.json", &mut new_reader(), |reader| {
+            reader.read_traced_le()
+        })
+        .unwrap();
+        assert_eq!(
+            (header.magic, header.size, header.end),
+            (3, 0x07060504, 0x07060505)
+        );
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_binrw.json")?)?;
+        let flat: Vec<_> = trace
+            .iter_flat()
+            .map(|f| {
+                let names: Vec<_> = f.path.iter().map(|s| s.span.0.name.to_string()).collect();
+                (f.range, names)
+            })
+            .collect();
+        assert_eq!(
+            flat,
+            vec![
+                (2..3, vec!["root".into(), "Header".into(), "magic".into()]),
+                (3..7, vec!["root".into(), "Header".into(), "size".into()]),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_serde() -> Result<(), Error> {
+        use ::serde::Deserialize;
+        use bincode::Options;
+
+        #[derive(Deserialize)]
+        struct Header {
+            magic: u8,
+            kind: Kind,
+        }
+        #[derive(Deserialize)]
+        enum Kind {
+            #[allow(unused)]
+            A,
+            B {
+                size: u16,
+            },
+        }
+
+        // variant index 1 as u32 followed by the u16
+        let mut reader = Cursor::new(vec![0xff, 1, 0, 0, 0, 0x34, 0x12]);
+        let header: Header = read_incremental("trace_serde.json", &mut reader, |reader| {
+            let mut de = bincode::Deserializer::with_reader(
+                reader,
+                bincode::options().with_fixint_encoding(),
+            );
+            Header::deserialize(crate::serde::TracedDeserializer::new(&mut de))
+        })
+        .unwrap();
+        assert_eq!(header.magic, 0xff);
+        assert!(matches!(header.kind, Kind::B { size: 0x1234 }));
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_serde.json")?)?;
+        let flat: Vec<_> = trace
+            .iter_flat()
+            .map(|f| {
+                let names: Vec<_> = f.path.iter().map(|s| s.span.0.name.to_string()).collect();
+                (f.range, names.join("/"))
+            })
+            .collect();
+        assert_eq!(
+            flat,
+            vec![
+                (0..1, "root/Header/magic".into()),
+                (1..5, "root/Header/kind/Kind".into()),
+                (5..7, "root/Header/kind/Kind/B/size".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "nom")]
+    #[test]
+    fn test_nom() -> Result<(), Error> {
+        use crate::nom::{spanned, Input};
+        use ::nom::{
+            branch::alt,
+            bytes::complete::tag,
+            number::complete::{be_u16, u8},
+            sequence::tuple,
+            IResult,
+        };
+
+        fn header(input: Input) -> IResult<Input, (u8, u16)> {
+            spanned("header", tuple((spanned("magic", u8), be_u16)))(input)
+        }
+        fn kind(input: Input) -> IResult<Input, (u8, Input)> {
+            // first branch fails after reading a byte so the second seeks back
+            alt((
+                spanned("a", tuple((spanned("x", u8), tag(&[0][..])))),
+                spanned("b", tuple((u8, tag(&[5][..])))),
+            ))(input)
+        }
+
+        let data = [1, 2, 3, 4, 5, 6];
+        let (rest, ((magic, size), _, last)) =
+            crate::nom::parse("trace_nom.json", &data, tuple((header, kind, u8))).unwrap();
+        assert_eq!((magic, size, last), (1, 0x0203, 6));
+        assert_eq!(rest.location(), 6);
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_nom.json")?)?;
+        let flat: Vec<_> = trace
+            .iter_flat()
+            .map(|f| {
+                let names: Vec<_> = f.path.iter().map(|s| s.span.0.name.to_string()).collect();
+                (f.range, names.join("/"))
+            })
+            .collect();
+        assert_eq!(
+            flat,
+            vec![
+                (0..1, "root/header/magic".into()),
+                (1..3, "root/header".into()),
+                (3..4, "root/a/x".into()),
+                (3..3, "root/b".into()),
+                (3..5, "root/b".into()),
+                (5..6, "root".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_scoped() -> Result<(), Error> {
+        let options = TraceOptions {
+            scoped: true,
+            ..Default::default()
+        };
+        {
+            let mut stream = TraceStream::new_options("trace_scoped.json", new_reader(), options);
+            assert!(!tracing::dispatcher::get_default(
+                |d| d.is::<CounterSubscriber>()
+            ));
+            {
+                let _span = tracing::info_span!("outside").entered();
+                stream.read_exact(&mut [0; 1])?;
+            }
+            stream.with_subscriber(|stream| {
+                let _span = tracing::info_span!("inside").entered();
+                stream.read_exact(&mut [0; 2])
+            })?;
+        }
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_scoped.json")?)?;
+        let flat: Vec<_> = trace
+            .iter_flat()
+            .map(|f| {
+                let names: Vec<_> = f.path.iter().map(|s| s.span.0.name.to_string()).collect();
+                (f.range, names.join("/"))
+            })
+            .collect();
+        assert_eq!(
+            flat,
+            vec![(2..3, "root".into()), (3..5, "root/inside".into())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tee() -> Result<(), Error> {
+        /// Subscriber logging the names of the spans and events it receives
+        #[derive(Clone, Default)]
+        struct Log(Arc<Mutex<Vec<String>>>);
+        impl Subscriber for Log {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &span::Attributes<'_>) -> Id {
+                let mut log = self.0.lock().unwrap();
+                log.push(format!("new {}", span.metadata().name()));
+                Id::from_u64(log.len() as u64)
+            }
+            fn record(&self, _span: &Id, _values: &span::Record<'_>) {}
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+            fn event(&self, _event: &Event<'_>) {
+                self.0.lock().unwrap().push("event".into());
+            }
+            fn enter(&self, span: &Id) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("enter {}", span.into_u64()));
+            }
+            fn exit(&self, span: &Id) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("exit {}", span.into_u64()));
+            }
+        }
+
+        let log = Log::default();
+        let _guard = tracing::subscriber::set_default(log.clone());
+        let options = TraceOptions {
+            tee: true,
+            ..Default::default()
+        };
+        read_options("trace_tee.json", &mut new_reader(), options, |stream| {
+            let _span = tracing::info_span!("outer").entered();
+            tracing::info!("hello");
+            stream.read_u8()
+        })?;
+        assert_eq!(
+            *log.0.lock().unwrap(),
+            [
+                "new root",
+                "enter 1",
+                "new outer",
+                "enter 3",
+                "event",
+                "exit 3",
+                "exit 1"
+            ]
+        );
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_tee.json")?)?;
+        let flat: Vec<_> = trace
+            .iter_flat()
+            .map(|f| {
+                let names: Vec<_> = f.path.iter().map(|s| s.span.0.name.to_string()).collect();
+                (f.range, names.join("/"))
+            })
+            .collect();
+        assert_eq!(flat, vec![(2..3, "root/outer".into())]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_span_name() -> Result<(), Error> {
+        read("trace_span_name.json", &mut new_reader(), |stream| {
+            for key in ["Health", "Armor"] {
+                let _span = tracing::info_span!("read_property", ser_hex.name = %key).entered();
+                stream.read_u8()?;
+            }
+            // name recorded after the span was created
+            let span = tracing::info_span!("read_property", ser_hex.name = tracing::field::Empty);
+            let _span = span.enter();
+            stream.read_u8()?;
+            span.record("ser_hex.name", "Speed");
+            Ok::<_, Error>(())
+        })?;
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_span_name.json")?)?;
+        let names: Vec<_> = trace
+            .iter_flat()
+            .map(|f| f.path.last().unwrap().span.0.name.to_string())
+            .collect();
+        assert_eq!(names, ["Health", "Armor", "Speed"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_coalesce_reads() -> Result<(), Error> {
+        let options = TraceOptions {
+            coalesce_reads: true,
+            ..Default::default()
+        };
+        read_options(
+            "trace_coalesce.json",
+            &mut new_reader(),
+            options,
+            |stream| {
+                for _ in 0..3 {
+                    stream.read_u8()?;
+                }
+                read_nested_stuff(stream)?;
+                stream.read_u8()?;
+                stream.read_u16::<LE>()
+            },
+        )?;
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_coalesce.json")?)?;
+        let Action::Span(root) = &trace.root else {
+            panic!("expected root span");
+        };
+        assert!(matches!(
+            root.0.actions[..],
+            [Action::Read(3), Action::Span(_), Action::Read(3)]
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_lazy_mirror() -> anyhow::Result<()> {
+        let options = TraceOptions {
+            lazy_mirror: true,
+            ..Default::default()
+        };
+        read_options("trace_lazy.json", &mut new_reader(), options, read_stuff)?;
+
+        // only the bytes read are captured
+        let json: serde_json::Value = serde_json::from_slice(&fs::read("trace_lazy.json")?)?;
+        assert!(json.get("data").is_none());
+        assert_eq!(json["segments"].as_array().unwrap().len(), 2);
+
+        let trace: Trace = Trace::load("trace_lazy.json")?;
+        assert_eq!(trace.start_index, 2);
+        assert_eq!(trace.data, [0, 0, 3, 4, 5, 6, 7, 0, 9]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay() -> anyhow::Result<()> {
+        read("trace_replay.json", &mut new_reader(), read_stuff)?;
+        let trace = Trace::<Vec<u8>>::load("trace_replay.json")?;
+        trace.replay(&mut new_reader())?;
+
+        let mut modified = new_reader();
+        modified.get_mut()[4] = 0xff;
+        let error = trace.replay(&mut modified).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "data at 0x4 differs from the trace (0xff != 0x05) in root > read_stuff > read_nested_stuff"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_redact() -> anyhow::Result<()> {
+        let options = TraceOptions {
+            record_read_data: true,
+            ..Default::default()
+        };
+        read_options("trace_redact.json", &mut new_reader(), options, read_stuff)?;
+        let mut trace = Trace::<Vec<u8>>::load("trace_redact.json")?;
+
+        trace.redact_spans("read_nested_stuff");
+        trace.redact([0..1, 1..2]);
+        assert_eq!(trace.metadata.redacted, [0..2, 3..7]);
+        assert_eq!(trace.data[..9], [0, 0, 3, 0, 0, 0, 0, 8, 9]);
+        let read_data: Vec<_> = trace
+            .iter_flat()
+            .filter_map(|f| match f.action {
+                Action::ReadData(data) => Some(data.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(read_data, [vec![3], vec![0, 0, 0, 0], vec![9], vec![9]]);
+
+        trace.replay(&mut new_reader())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum() -> anyhow::Result<()> {
+        let options = TraceOptions {
+            checksum: true,
+            ..Default::default()
+        };
+        read_options(
+            "trace_checksum.json",
+            &mut new_reader(),
+            options,
+            read_stuff,
+        )?;
+        let trace = Trace::<Vec<u8>>::load("trace_checksum.json")?;
+        let sha256 = sha256_hex(new_reader().get_ref());
+        assert_eq!(trace.metadata.data_sha256.as_ref(), Some(&sha256));
+        assert_eq!(trace.metadata.source_sha256.as_ref(), Some(&sha256));
+
+        let mut tampered = trace.clone();
+        tampered.data[0] ^= 1;
+        tampered.save("trace_checksum_tampered.json")?;
+        let error = Trace::<Vec<u8>>::load("trace_checksum_tampered.json").unwrap_err();
+        assert!(error.to_string().contains("checksum mismatch"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_window() -> Result<(), Error> {
+        let options = TraceOptions {
+            window: Some(4..8),
+            ..Default::default()
+        };
+        read_options("trace_window.json", &mut new_reader(), options, |stream| {
+            read_stuff(stream)?;
+            stream.seek(SeekFrom::Start(12))?;
+            read_stuff(stream)
+        })?;
+
+        let trace: Trace = serde_json::from_slice(&fs::read("trace_window.json")?)?;
+        assert_eq!(trace.data, [0, 0, 0, 0, 5, 6, 7, 8]);
+        // only the first read_stuff touches the window, its skipped first read is replaced by a
+        // seek to where the stored reads resume
+        let flat: Vec<_> = trace
+            .iter_flat()
+            .map(|f| {
+                let names: Vec<_> = f.path.iter().map(|s| s.span.0.name.to_string()).collect();
+                (f.range, names.join("/"))
+            })
+            .collect();
+        assert_eq!(
+            flat,
+            vec![
+                (3..3, "root/read_stuff/read_nested_stuff".to_string()),
+                (3..7, "root/read_stuff/read_nested_stuff".to_string())
+            ]
+        );
+        assert_eq!(trace.metadata.skipped_actions, 12);
+        Ok(())
+    }
+
+    #[test]
+    fn test_assert_coverage() -> anyhow::Result<()> {
+        read("trace_assert_coverage.json", &mut new_reader(), read_stuff)?;
+        let trace: Trace = Trace::load("trace_assert_coverage.json")?;
+
+        let gaps = [0..2, 7..8];
+        let unread = testing::unread_ranges(&trace, &gaps);
+        assert_eq!(unread.len(), 1);
+        assert_eq!(unread[0], 9..19);
+        let message = testing::check_coverage(&trace, &gaps)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            message,
+            "10 of 19 bytes were not read in 1 ranges:\n  0x9..0x13 (10 bytes)\n    previous read: root > read_stuff (0x8..0x9)\n    next read:     -"
+        );
+
+        testing::assert_coverage_with_gaps(&trace, &[0..2, 7..8, 9..19]);
+        assert!(std::panic::catch_unwind(|| testing::assert_full_coverage(&trace)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sparse_data() -> anyhow::Result<()> {
+        let options = TraceOptions {
+            sparse_data: true,
+            checksum: true,
+            ..Default::default()
+        };
+        let mut s =
+            TraceStream::new_incremental_options("trace_sparse.json", new_reader(), options);
+        read_stuff(&mut s)?;
+        read_stuff(&mut s)?;
+        drop(s);
+
+        let json: serde_json::Value = serde_json::from_slice(&fs::read("trace_sparse.json")?)?;
+        assert!(json.get("data").is_none());
+        assert_eq!(json["segments"].as_array().unwrap().len(), 3);
+
+        // rebased onto stream offsets by the first seek
+        let trace: Trace = Trace::load("trace_sparse.json")?;
+        assert_eq!(trace.start_index, 2);
+        assert_eq!(
+            trace.data,
+            [0, 0, 3, 4, 5, 6, 7, 0, 9, 10, 11, 12, 13, 14, 0, 17]
+        );
+        let ranges: Vec<_> = trace
+            .iter_flat()
+            .filter(|f| f.action.read_size().is_some())
+            .map(|f| f.range)
+            .collect();
+        assert_eq!(
+            ranges,
+            [2..3, 3..7, 8..9, 8..9, 9..10, 10..14, 15..16, 15..16]
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "deku")]
+    #[test]
+    fn test_deku() -> anyhow::Result<()> {
+        // the extern crate rather than crate::deku for paths emitted by the derive
+        use ::deku::{self, DekuRead};
+
+        use crate::deku::TracedDekuReaderExt;
+
+        #[crate::deku::spans]
+        #[derive(DekuRead)]
+        #[deku(endian = "little")]
+        struct Header {
+            magic: u8,
+            size: u32,
+            #[deku(skip, default = "0")]
+            extra: u8,
+        }
+
+        let header: Header = read("trace_deku.json", &mut new_reader(), |reader| {
+            reader.read_traced_deku()
+        })?;
+        assert_eq!(
+            (header.magic, header.size, header.extra),
+            (3, 0x07060504, 0)
+        );
+
+        let trace: Trace = Trace::load("trace_deku.json")?;
+        let flat: Vec<_> = trace
+            .iter_flat()
+            .map(|f| {
+                let names: Vec<_> = f.path.iter().map(|s| s.span.0.name.to_string()).collect();
+                (f.range, names.join("/"))
+            })
+            .collect();
+        assert_eq!(
+            flat,
+            vec![
+                (2..3, "root/Header/magic".to_string()),
+                (3..7, "root/Header/size".to_string()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_traced_read_ext() -> anyhow::Result<()> {
+        let mut data = vec![0xff, 4, 3, 2, 1, 0x12, 0x34, b'h', b'i', 0];
+        data.extend(1.5f64.to_le_bytes());
+        let values = read("trace_read_ext.json", &mut Cursor::new(data), |s| {
+            // byteorder's ReadBytesExt is also in scope
+            io::Result::Ok((
+                s.traced_u8()?,
+                s.traced_u32_le()?,
+                s.traced_u16_be()?,
+                s.traced_cstring()?,
+                s.traced_f64_le()?,
+            ))
+        })?;
+        assert_eq!(
+            values,
+            (0xff, 0x01020304, 0x1234, std::ffi::CString::new("hi")?, 1.5)
+        );
+
+        let trace: Trace = Trace::load("trace_read_ext.json")?;
+        let Action::Span(root) = &trace.root else {
+            unreachable!()
+        };
+        let spans: Vec<_> = root
+            .0
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::Span(span) => {
+                    let value = span.0.value.as_ref().unwrap();
+                    Some(format!(
+                        "{} = {} ({})",
+                        span.0.name, value.value, value.type_name
+                    ))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                "u8 = 255 (u8)",
+                "u32 = 16909060 (u32 LE)",
+                "u16 = 4660 (u16 BE)",
+                "cstring = \"hi\" (cstring)",
+                "f64 = 1.5 (f64 LE)",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_dyn_trace_stream() -> anyhow::Result<()> {
+        let inner: Box<dyn ReadSeek + Send> = Box::new(new_reader());
+        let mut stream = TraceStream::new("trace_dyn.json", inner).boxed();
+        read_stuff(&mut stream)?;
+        drop(stream);
+
+        let reader: &mut dyn ReadSeek = &mut new_reader();
+        read("trace_dyn_read.json", reader, read_stuff)?;
+
+        let ranges = |path| -> anyhow::Result<Vec<_>> {
+            let trace: Trace = Trace::load(path)?;
+            Ok(trace.iter_flat().map(|f| f.range).collect())
+        };
+        assert_eq!(ranges("trace_dyn.json")?, ranges("trace_dyn_read.json")?);
+        assert_eq!(ranges("trace_dyn.json")?.last(), Some(&(8..9)));
+        Ok(())
+    }
 }
@@ -0,0 +1,274 @@
+//! Spans for [nom](::nom) parsers
+//!
+//! nom parsers operate on byte slices rather than a [`Read`](std::io::Read) stream, so parsers
+//! take an [`Input`] which remembers its position in the full buffer. Bytes consumed by each
+//! [`spanned`] parser are recorded as reads (and seeks when a parser backtracks).
+//!
+//! ```ignore
+//! use ser_hex::nom::{spanned, Input};
+//!
+//! fn header(input: Input) -> IResult<Input, (u32, u16)> {
+//!     spanned("header", tuple((spanned("magic", be_u32), be_u16)))(input)
+//! }
+//!
+//! let (rest, header) = ser_hex::nom::parse("trace.json", &data, header)?;
+//! ```
+
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    io::{Cursor, SeekFrom},
+    ops::{Bound, Deref, RangeBounds},
+    path::Path,
+};
+
+use ::nom::{
+    error::{ErrorKind, ParseError},
+    Compare, CompareResult, FindSubstring, IResult, InputIter, InputLength, InputTake,
+    InputTakeAtPosition, Needed, Offset, Parser, Slice,
+};
+
+use crate::{CounterSubscriber, TraceOptions};
+
+/// Parser input tracking its position in the full buffer
+#[derive(Debug, Clone, Copy)]
+pub struct Input<'a> {
+    data: &'a [u8],
+    start: usize,
+    end: usize,
+}
+impl<'a> Input<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            start: 0,
+            end: data.len(),
+        }
+    }
+    /// Offset of the input in the full buffer
+    pub fn location(&self) -> usize {
+        self.start
+    }
+    /// Remaining bytes
+    pub fn fragment(&self) -> &'a [u8] {
+        &self.data[self.start..self.end]
+    }
+}
+impl Deref for Input<'_> {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        self.fragment()
+    }
+}
+
+impl InputLength for Input<'_> {
+    fn input_len(&self) -> usize {
+        self.end - self.start
+    }
+}
+impl Offset for Input<'_> {
+    fn offset(&self, second: &Self) -> usize {
+        second.start - self.start
+    }
+}
+impl<'a> InputTake for Input<'a> {
+    fn take(&self, count: usize) -> Self {
+        self.slice(..count)
+    }
+    fn take_split(&self, count: usize) -> (Self, Self) {
+        (self.slice(count..), self.slice(..count))
+    }
+}
+impl InputTakeAtPosition for Input<'_> {
+    type Item = u8;
+
+    fn split_at_position<P, E: ParseError<Self>>(&self, predicate: P) -> IResult<Self, Self, E>
+    where
+        P: Fn(u8) -> bool,
+    {
+        match self.position(predicate) {
+            Some(n) => Ok(self.take_split(n)),
+            None => Err(::nom::Err::Incomplete(Needed::new(1))),
+        }
+    }
+    fn split_at_position1<P, E: ParseError<Self>>(
+        &self,
+        predicate: P,
+        e: ErrorKind,
+    ) -> IResult<Self, Self, E>
+    where
+        P: Fn(u8) -> bool,
+    {
+        match self.position(predicate) {
+            Some(0) => Err(::nom::Err::Error(E::from_error_kind(*self, e))),
+            Some(n) => Ok(self.take_split(n)),
+            None => Err(::nom::Err::Incomplete(Needed::new(1))),
+        }
+    }
+    fn split_at_position_complete<P, E: ParseError<Self>>(
+        &self,
+        predicate: P,
+    ) -> IResult<Self, Self, E>
+    where
+        P: Fn(u8) -> bool,
+    {
+        let n = self.position(predicate).unwrap_or(self.input_len());
+        Ok(self.take_split(n))
+    }
+    fn split_at_position1_complete<P, E: ParseError<Self>>(
+        &self,
+        predicate: P,
+        e: ErrorKind,
+    ) -> IResult<Self, Self, E>
+    where
+        P: Fn(u8) -> bool,
+    {
+        match self.position(predicate).unwrap_or(self.input_len()) {
+            0 => Err(::nom::Err::Error(E::from_error_kind(*self, e))),
+            n => Ok(self.take_split(n)),
+        }
+    }
+}
+impl<'a> InputIter for Input<'a> {
+    type Item = u8;
+    type Iter = std::iter::Enumerate<Self::IterElem>;
+    type IterElem = std::iter::Copied<std::slice::Iter<'a, u8>>;
+
+    fn iter_indices(&self) -> Self::Iter {
+        self.iter_elements().enumerate()
+    }
+    fn iter_elements(&self) -> Self::IterElem {
+        self.fragment().iter().copied()
+    }
+    fn position<P: Fn(Self::Item) -> bool>(&self, predicate: P) -> Option<usize> {
+        self.fragment().iter().position(|&b| predicate(b))
+    }
+    fn slice_index(&self, count: usize) -> Result<usize, Needed> {
+        self.fragment().slice_index(count)
+    }
+}
+impl<'a, R: RangeBounds<usize>> Slice<R> for Input<'a>
+where
+    &'a [u8]: Slice<R>,
+{
+    fn slice(&self, range: R) -> Self {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let fragment = self.fragment().slice(range);
+        Self {
+            data: self.data,
+            start: self.start + start,
+            end: self.start + start + fragment.len(),
+        }
+    }
+}
+impl<'a, T> Compare<T> for Input<'a>
+where
+    &'a [u8]: Compare<T>,
+{
+    fn compare(&self, t: T) -> CompareResult {
+        self.fragment().compare(t)
+    }
+    fn compare_no_case(&self, t: T) -> CompareResult {
+        self.fragment().compare_no_case(t)
+    }
+}
+impl<'a, T> FindSubstring<T> for Input<'a>
+where
+    &'a [u8]: FindSubstring<T>,
+{
+    fn find_substring(&self, substr: T) -> Option<usize> {
+        self.fragment().find_substring(substr)
+    }
+}
+
+thread_local! {
+    /// Offset up to which the bytes consumed by each enclosing [`spanned`] parser are recorded
+    static FRAMES: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Record the bytes consumed by the innermost parser from its last recorded offset up to `to`
+fn flush(data: &[u8], to: usize) {
+    FRAMES.with_borrow_mut(|frames| {
+        let Some(pos) = frames.last_mut() else {
+            return;
+        };
+        if to > *pos {
+            if let Some(subscriber) = CounterSubscriber::current() {
+                let start = subscriber.now();
+                let position = subscriber.inner.lock().unwrap().data.position();
+                if position != *pos as u64 {
+                    subscriber.seek_action(*pos as u64, SeekFrom::Start(*pos as u64), start);
+                }
+                subscriber.read_action(&data[*pos..to], to - *pos, start);
+            }
+        }
+        *pos = to;
+    });
+}
+
+/// Run `parser` inside a span with the given name
+pub fn spanned<'a, O, E, P>(
+    name: impl Into<Cow<'static, str>>,
+    mut parser: P,
+) -> impl FnMut(Input<'a>) -> IResult<Input<'a>, O, E>
+where
+    P: Parser<Input<'a>, O, E>,
+{
+    let name = name.into();
+    move |input: Input<'a>| {
+        // bytes consumed by the enclosing parser before this one belong to its span
+        flush(input.data, input.start);
+
+        let _guard = tracing::info_span!("nom", ser_hex.name = %name).entered();
+
+        FRAMES.with_borrow_mut(|frames| frames.push(input.start));
+        let result = parser.parse(input);
+        if let Ok((rest, _)) = &result {
+            flush(input.data, rest.start);
+        }
+        FRAMES.with_borrow_mut(|frames| {
+            frames.pop();
+            if let (Ok((rest, _)), Some(pos)) = (&result, frames.last_mut()) {
+                *pos = rest.start;
+            }
+        });
+        result
+    }
+}
+
+/// Run a nom parser over `data`, writing a trace of the consumed bytes to `out_path`
+pub fn parse<'a, O, E, P>(
+    out_path: impl AsRef<Path>,
+    data: &'a [u8],
+    parser: P,
+) -> IResult<Input<'a>, O, E>
+where
+    P: Parser<Input<'a>, O, E>,
+{
+    parse_options(out_path, data, Default::default(), parser)
+}
+
+pub fn parse_options<'a, O, E, P>(
+    out_path: impl AsRef<Path>,
+    data: &'a [u8],
+    options: TraceOptions,
+    mut parser: P,
+) -> IResult<Input<'a>, O, E>
+where
+    P: Parser<Input<'a>, O, E>,
+{
+    crate::read_options(out_path, &mut Cursor::new(data), options, |_| {
+        let input = Input::new(data);
+        let outer = FRAMES.replace(vec![input.start]);
+        let result = parser.parse(input);
+        if let Ok((rest, _)) = &result {
+            flush(data, rest.start);
+        }
+        FRAMES.set(outer);
+        result
+    })
+}
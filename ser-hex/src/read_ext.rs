@@ -0,0 +1,75 @@
+use std::{ffi::CString, fmt::Debug, io};
+
+/// Read `T` inside a span with the given name, annotated with the decoded value
+fn traced<T: Debug>(
+    name: &'static str,
+    type_name: &'static str,
+    read: impl FnOnce() -> io::Result<T>,
+) -> io::Result<T> {
+    let _guard = tracing::info_span!("read", ser_hex.name = name).entered();
+    let value = read()?;
+    Ok(crate::value!(value, type_name))
+}
+
+macro_rules! primitives {
+    ($($fn:ident: $ty:ident, $from:ident, $type_name:literal;)*) => {$(
+        #[doc = concat!("Read a `", $type_name, "` inside a span named `", stringify!($ty), "`")]
+        fn $fn(&mut self) -> io::Result<$ty> {
+            traced(stringify!($ty), $type_name, || {
+                let mut buf = [0; std::mem::size_of::<$ty>()];
+                self.read_exact(&mut buf)?;
+                Ok($ty::$from(buf))
+            })
+        }
+    )*};
+}
+
+/// Primitive reads which each record a span named after the primitive along with the decoded
+/// [`Value`](crate::Value), so parsers get named nodes without a wrapper function per primitive.
+/// Prefixed with `traced_` to not clash with byteorder's `ReadBytesExt`
+///
+/// ```ignore
+/// use ser_hex::TracedReadExt;
+///
+/// let size = reader.traced_u32_le()?;
+/// let name = reader.traced_cstring()?;
+/// ```
+pub trait TracedReadExt: io::Read {
+    primitives! {
+        traced_u8: u8, from_le_bytes, "u8";
+        traced_i8: i8, from_le_bytes, "i8";
+        traced_u16_le: u16, from_le_bytes, "u16 LE";
+        traced_u16_be: u16, from_be_bytes, "u16 BE";
+        traced_i16_le: i16, from_le_bytes, "i16 LE";
+        traced_i16_be: i16, from_be_bytes, "i16 BE";
+        traced_u32_le: u32, from_le_bytes, "u32 LE";
+        traced_u32_be: u32, from_be_bytes, "u32 BE";
+        traced_i32_le: i32, from_le_bytes, "i32 LE";
+        traced_i32_be: i32, from_be_bytes, "i32 BE";
+        traced_u64_le: u64, from_le_bytes, "u64 LE";
+        traced_u64_be: u64, from_be_bytes, "u64 BE";
+        traced_i64_le: i64, from_le_bytes, "i64 LE";
+        traced_i64_be: i64, from_be_bytes, "i64 BE";
+        traced_f32_le: f32, from_le_bytes, "f32 LE";
+        traced_f32_be: f32, from_be_bytes, "f32 BE";
+        traced_f64_le: f64, from_le_bytes, "f64 LE";
+        traced_f64_be: f64, from_be_bytes, "f64 BE";
+    }
+
+    /// Read a nul terminated string inside a span named `cstring`, consuming the terminator
+    fn traced_cstring(&mut self) -> io::Result<CString> {
+        traced("cstring", "cstring", || {
+            let mut buf = vec![];
+            loop {
+                let mut byte = [0];
+                self.read_exact(&mut byte)?;
+                if byte[0] == 0 {
+                    break;
+                }
+                buf.push(byte[0]);
+            }
+            Ok(CString::new(buf).unwrap())
+        })
+    }
+}
+impl<R: io::Read + ?Sized> TracedReadExt for R {}
@@ -0,0 +1,70 @@
+use std::ops::Range;
+
+use crate::{Action, Trace, TreeSpan};
+
+/// Sort and merge overlapping or adjacent ranges
+pub(crate) fn normalize(ranges: &mut Vec<Range<usize>>) {
+    ranges.retain(|r| !r.is_empty());
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<usize>> = vec![];
+    for range in ranges.drain(..) {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    *ranges = merged;
+}
+
+/// Zero bytes of `data` located at `offset` which fall within any of the ranges
+fn mask(data: &mut [u8], offset: usize, ranges: &[Range<usize>]) {
+    for range in ranges {
+        let start = range.start.max(offset);
+        let end = range.end.min(offset + data.len());
+        if start < end {
+            data[start - offset..end - offset].fill(0);
+        }
+    }
+}
+
+/// Mask bytes recorded by [`Action::ReadData`] actions, returning the offset after the action
+fn redact_action(action: &mut Action<TreeSpan>, offset: usize, ranges: &[Range<usize>]) -> usize {
+    match action {
+        Action::Read(size) => offset + *size,
+        Action::ReadData(data) => {
+            mask(data, offset, ranges);
+            offset + data.len()
+        }
+        Action::Seek(seek) => seek.to,
+        Action::Error(_) | Action::SubTrace(_) => offset,
+        Action::Span(span) => span.0.actions.iter_mut().fold(offset, |offset, action| {
+            redact_action(action, offset, ranges)
+        }),
+    }
+}
+
+impl<D: AsRef<[u8]> + AsMut<[u8]>> Trace<D> {
+    /// Zero the given byte ranges of the data, including bytes recorded by
+    /// [`Action::ReadData`], and record them in [`TraceMetadata::redacted`] so viewers can display
+    /// them distinctly
+    ///
+    /// [`TraceMetadata::redacted`]: crate::TraceMetadata::redacted
+    pub fn redact(&mut self, ranges: impl IntoIterator<Item = Range<usize>>) {
+        let mut ranges: Vec<_> = ranges.into_iter().collect();
+        normalize(&mut ranges);
+        mask(self.data.as_mut(), 0, &ranges);
+        redact_action(&mut self.root, self.start_index, &ranges);
+
+        self.metadata.redacted.extend(ranges);
+        normalize(&mut self.metadata.redacted);
+    }
+    /// Redact all bytes read within spans with the given name
+    pub fn redact_spans(&mut self, name: &str) {
+        let ranges: Vec<_> = self
+            .iter_flat()
+            .filter(|flat| flat.path.iter().any(|s| s.span.0.name == name))
+            .map(|flat| flat.range)
+            .collect();
+        self.redact(ranges);
+    }
+}
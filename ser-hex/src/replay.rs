@@ -0,0 +1,58 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use anyhow::{bail, Context as _};
+
+use crate::{Action, Trace};
+
+impl<D: AsRef<[u8]>> Trace<D> {
+    /// Re-execute the recorded reads and seeks against `reader`, failing at the first read whose
+    /// bytes differ from the recorded data
+    ///
+    /// Bytes past the end of the recorded data (see [`TraceOptions::max_data_size`]) and bytes
+    /// zeroed by [`Trace::redact`] are not compared.
+    ///
+    /// [`TraceOptions::max_data_size`]: crate::TraceOptions::max_data_size
+    pub fn replay<R: Read + Seek>(&self, reader: &mut R) -> anyhow::Result<()> {
+        let data = self.data.as_ref();
+        let redacted = |offset: usize| self.metadata.redacted.iter().any(|r| r.contains(&offset));
+        reader.seek(SeekFrom::Start(self.start_index as u64))?;
+        let mut buf = vec![];
+        for flat in self.iter_flat() {
+            let path = || {
+                let names: Vec<_> = flat.path.iter().map(|s| s.span.0.name.as_ref()).collect();
+                names.join(" > ")
+            };
+            let recorded = match flat.action {
+                Action::Read(_) => {
+                    let end = flat.range.end.min(data.len());
+                    data.get(flat.range.start..end).unwrap_or_default()
+                }
+                Action::ReadData(recorded) => recorded.as_slice(),
+                Action::Seek(seek) => {
+                    reader.seek(SeekFrom::Start(seek.to as u64))?;
+                    continue;
+                }
+                Action::Error(_) | Action::SubTrace(_) | Action::Span(_) => continue,
+            };
+            buf.resize(flat.range.len(), 0);
+            reader
+                .read_exact(&mut buf)
+                .with_context(|| format!("failed to read {:?} in {}", flat.range, path()))?;
+            let differs = buf
+                .iter()
+                .zip(recorded)
+                .enumerate()
+                .position(|(i, (a, b))| a != b && !redacted(flat.range.start + i));
+            if let Some(i) = differs {
+                bail!(
+                    "data at {:#x} differs from the trace ({:#04x} != {:#04x}) in {}",
+                    flat.range.start + i,
+                    buf[i],
+                    recorded[i],
+                    path()
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Selects which invocations are traced when tracing from a hook called many times
+///
+/// Invocations which are not selected are forwarded untraced and produce no trace file.
+///
+/// ```ignore
+/// static SAMPLER: Sampler = Sampler::new().every(10).matching("*.uasset");
+///
+/// let options = TraceOptions {
+///     sampler: Some(&SAMPLER),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug)]
+pub struct Sampler {
+    every: usize,
+    pattern: Option<&'static str>,
+    count: AtomicUsize,
+}
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Sampler {
+    /// Sampler which selects every invocation
+    pub const fn new() -> Self {
+        Self {
+            every: 1,
+            pattern: None,
+            count: AtomicUsize::new(0),
+        }
+    }
+    /// Only select every nth matching invocation, starting with the first
+    pub const fn every(mut self, n: usize) -> Self {
+        self.every = if n == 0 { 1 } else { n };
+        self
+    }
+    /// Only select invocations whose name matches a glob pattern supporting `*` and `?`
+    pub const fn matching(mut self, pattern: &'static str) -> Self {
+        self.pattern = Some(pattern);
+        self
+    }
+    /// Whether an invocation with the given name should be traced. Each matching invocation
+    /// advances the counter
+    pub fn sample(&self, name: &str) -> bool {
+        if let Some(pattern) = self.pattern {
+            if !glob_match(pattern.as_bytes(), name.as_bytes()) {
+                return false;
+            }
+        }
+        self.count
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(self.every)
+    }
+}
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    // iterative matcher backtracking to the most recent `*`
+    let (mut p, mut n) = (0, 0);
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
@@ -0,0 +1,263 @@
+use serde_json::{json, Value};
+
+/// JSON Schema (draft 2020-12) describing the trace file format written by [`crate::Trace::save`]
+/// and [`crate::Trace::save_split`], for readers of traces in other languages
+///
+/// Enums are externally tagged: each action is an object with a single key naming the variant.
+/// Byte buffers are base64 encoded strings.
+pub fn json_schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "ser-hex trace",
+        "$ref": "#/$defs/Trace",
+        "$defs": {
+            "Trace": {
+                "type": "object",
+                "properties": {
+                    "version": {
+                        "description": "Format version, missing in traces written before versioning",
+                        "type": "integer",
+                        "minimum": 0,
+                        "maximum": crate::INTERNED_TRACE_VERSION,
+                        "default": 0
+                    },
+                    "data": {
+                        "description": "Mirror of the traced stream",
+                        "$ref": "#/$defs/Bytes"
+                    },
+                    "data_file": {
+                        "description": "Raw data sidecar file relative to the trace, replaces data",
+                        "type": "string"
+                    },
+                    "data_chunks": {
+                        "description": "Data stored as fixed-size chunks in a sidecar file, replaces data",
+                        "type": "object",
+                        "properties": {
+                            "file": {
+                                "description": "Sidecar file relative to the trace",
+                                "type": "string"
+                            },
+                            "size": {
+                                "description": "Total uncompressed size of the data",
+                                "$ref": "#/$defs/Offset"
+                            },
+                            "chunk_size": {
+                                "description": "Uncompressed size of each chunk, the last may be shorter",
+                                "type": "integer",
+                                "minimum": 1
+                            },
+                            "compression": { "enum": ["none", "deflate"] },
+                            "chunks": {
+                                "description": "Location of each stored chunk in the sidecar",
+                                "type": "array",
+                                "items": {
+                                    "type": "object",
+                                    "properties": {
+                                        "offset": { "type": "integer", "minimum": 0 },
+                                        "length": { "type": "integer", "minimum": 0 }
+                                    },
+                                    "required": ["offset", "length"]
+                                }
+                            }
+                        },
+                        "required": ["file", "size", "chunk_size", "compression", "chunks"]
+                    },
+                    "segments": {
+                        "description": "Captured bytes at their stream offsets, replaces data. Gaps between segments are zero",
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "offset": { "$ref": "#/$defs/Offset" },
+                                "data": { "$ref": "#/$defs/Bytes" }
+                            },
+                            "required": ["offset", "data"]
+                        }
+                    },
+                    "start_index": {
+                        "description": "Stream offset at which the first action starts",
+                        "$ref": "#/$defs/Offset"
+                    },
+                    "root": { "$ref": "#/$defs/Action" },
+                    "metadata": { "$ref": "#/$defs/TraceMetadata" },
+                    "strings": {
+                        "description": "Span names referenced by index, in traces saved with interned names. Names in inline sub-traces are not interned",
+                        "type": "array",
+                        "items": { "type": "string" }
+                    }
+                },
+                "required": ["start_index", "root"],
+                "oneOf": [
+                    { "required": ["data"] },
+                    { "required": ["data_file"] },
+                    { "required": ["data_chunks"] },
+                    { "required": ["segments"] }
+                ]
+            },
+            "Action": {
+                "oneOf": [
+                    variant("Read", json!({
+                        "description": "Number of bytes read",
+                        "$ref": "#/$defs/Offset"
+                    })),
+                    variant("Seek", json!({
+                        "oneOf": [
+                            { "description": "Destination offset (version 1)", "$ref": "#/$defs/Offset" },
+                            { "$ref": "#/$defs/SeekAction" }
+                        ]
+                    })),
+                    variant("Span", json!({ "$ref": "#/$defs/ReadSpan" })),
+                    variant("Error", json!({ "$ref": "#/$defs/ReadError" })),
+                    variant("ReadData", json!({
+                        "description": "Read along with the bytes it returned",
+                        "$ref": "#/$defs/Bytes"
+                    })),
+                    variant("SubTrace", json!({
+                        "description": "Trace of a nested stream decoded from the preceding read",
+                        "oneOf": [
+                            variant("File", json!({ "type": "string" })),
+                            variant("Inline", json!({ "$ref": "#/$defs/Trace" }))
+                        ]
+                    }))
+                ]
+            },
+            "SeekAction": {
+                "type": "object",
+                "properties": {
+                    "to": {
+                        "description": "Absolute destination offset",
+                        "$ref": "#/$defs/Offset"
+                    },
+                    "from": {
+                        "description": "Origin and offset the seek was requested with",
+                        "oneOf": [
+                            variant("Start", json!({ "type": "integer", "minimum": 0 })),
+                            variant("End", json!({ "type": "integer" })),
+                            variant("Current", json!({ "type": "integer" }))
+                        ]
+                    }
+                },
+                "required": ["to"]
+            },
+            "ReadSpan": {
+                "type": "object",
+                "properties": {
+                    "name": {
+                        "oneOf": [
+                            { "type": "string" },
+                            {
+                                "description": "Index into the strings of the trace",
+                                "type": "integer",
+                                "minimum": 0
+                            }
+                        ]
+                    },
+                    "value": { "$ref": "#/$defs/Value" },
+                    "actions": {
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/Action" }
+                    },
+                    "timings": {
+                        "description": "Timing of each action, parallel to actions",
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/Timing" }
+                    },
+                    "location": {
+                        "description": "Source location of the code within the span",
+                        "type": "object",
+                        "properties": {
+                            "file": { "type": "string" },
+                            "line": { "type": "integer", "minimum": 0 }
+                        },
+                        "required": ["file"]
+                    }
+                },
+                "required": ["name", "actions"]
+            },
+            "Value": {
+                "description": "Value decoded by the parser within a span",
+                "type": "object",
+                "properties": {
+                    "type": { "type": "string" },
+                    "value": { "type": "string" }
+                },
+                "required": ["type", "value"]
+            },
+            "Timing": {
+                "description": "Monotonic nanoseconds since the trace began",
+                "type": "object",
+                "properties": {
+                    "start": { "type": "integer", "minimum": 0 },
+                    "end": { "type": "integer", "minimum": 0 }
+                },
+                "required": ["start", "end"]
+            },
+            "ReadError": {
+                "type": "object",
+                "properties": {
+                    "kind": { "type": "string" },
+                    "message": { "type": "string" },
+                    "size": {
+                        "description": "Number of bytes requested",
+                        "$ref": "#/$defs/Offset"
+                    }
+                },
+                "required": ["kind", "message", "size"]
+            },
+            "TraceMetadata": {
+                "type": "object",
+                "properties": {
+                    "tool": { "type": "string" },
+                    "tool_version": { "type": "string" },
+                    "timestamp": {
+                        "description": "Capture time in seconds since the Unix epoch",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "source": { "type": "string" },
+                    "data_sha256": { "$ref": "#/$defs/Sha256" },
+                    "source_sha256": { "$ref": "#/$defs/Sha256" },
+                    "user": {
+                        "type": "object",
+                        "additionalProperties": { "type": "string" }
+                    },
+                    "redacted": {
+                        "description": "Byte ranges of the data which were masked",
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "start": { "$ref": "#/$defs/Offset" },
+                                "end": { "$ref": "#/$defs/Offset" }
+                            },
+                            "required": ["start", "end"]
+                        }
+                    },
+                    "skipped_actions": {
+                        "description": "Number of reads and seeks outside the traced window which were not recorded",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "modules": {
+                        "description": "Path of each module, keyed by file name, of unsymbolized module+0xoffset span names",
+                        "type": "object",
+                        "additionalProperties": { "type": "string" }
+                    }
+                }
+            },
+            "Offset": { "type": "integer", "minimum": 0 },
+            "Sha256": { "type": "string", "pattern": "^[0-9a-fA-F]{64}$" },
+            "Bytes": { "type": "string", "contentEncoding": "base64" }
+        }
+    })
+}
+
+/// Externally tagged enum variant
+fn variant(name: &str, schema: Value) -> Value {
+    json!({
+        "type": "object",
+        "properties": { name: schema },
+        "required": [name],
+        "additionalProperties": false
+    })
+}
@@ -0,0 +1,550 @@
+//! Spans for [serde](::serde) based binary formats
+//!
+//! ```ignore
+//! use serde::Deserialize;
+//!
+//! let mut de = bincode::Deserializer::with_reader(reader, bincode::options());
+//! // spans named after each struct, field and enum variant
+//! let value = Value::deserialize(ser_hex::serde::TracedDeserializer::new(&mut de))?;
+//! ```
+
+use std::{borrow::Cow, fmt};
+
+use ::serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+
+fn span(name: impl Into<Cow<'static, str>>) -> tracing::Span {
+    let name = name.into();
+    tracing::info_span!("serde", ser_hex.name = %name)
+}
+
+fn index_name(names: &'static [&'static str], index: u64) -> Cow<'static, str> {
+    match names.get(index as usize) {
+        Some(&name) => Cow::Borrowed(name),
+        None => Cow::Owned(index.to_string()),
+    }
+}
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*)),* $(,)?) => {$(
+        fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error> {
+            let (inner, visitor) = self.split(visitor);
+            inner.$method($($arg,)* visitor)
+        }
+    )*};
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty)),* $(,)?) => {$(
+        fn $method<E: de::Error>(self, v: $ty) -> Result<Self::Value, E> {
+            self.inner.$method(v)
+        }
+    )*};
+}
+
+/// Wraps a [`Deserializer`] to open a span for each struct, field and enum variant it reads
+pub struct TracedDeserializer<D> {
+    inner: D,
+}
+impl<D> TracedDeserializer<D> {
+    pub fn new(inner: D) -> Self {
+        Self { inner }
+    }
+    fn split<V>(self, visitor: V) -> (D, TracedVisitor<V>) {
+        (self.inner, TracedVisitor::new(visitor, &[]))
+    }
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for TracedDeserializer<D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_i128(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_u128(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_map(),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        span(name).in_scope(|| {
+            let (inner, visitor) = self.split(visitor);
+            inner.deserialize_newtype_struct(name, visitor)
+        })
+    }
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        span(name).in_scope(|| {
+            let (inner, visitor) = self.split(visitor);
+            inner.deserialize_tuple_struct(name, len, visitor)
+        })
+    }
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        span(name).in_scope(|| {
+            self.inner
+                .deserialize_struct(name, fields, TracedVisitor::new(visitor, fields))
+        })
+    }
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        span(name).in_scope(|| {
+            self.inner
+                .deserialize_enum(name, variants, TracedVisitor::new(visitor, variants))
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+/// Wraps nested deserializers handed to a visitor, naming struct fields and enum variants
+/// from `names`
+struct TracedVisitor<V> {
+    inner: V,
+    names: &'static [&'static str],
+}
+impl<V> TracedVisitor<V> {
+    fn new(inner: V, names: &'static [&'static str]) -> Self {
+        Self { inner, names }
+    }
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for TracedVisitor<V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool),
+        visit_i8(i8),
+        visit_i16(i16),
+        visit_i32(i32),
+        visit_i64(i64),
+        visit_i128(i128),
+        visit_u8(u8),
+        visit_u16(u16),
+        visit_u32(u32),
+        visit_u64(u64),
+        visit_u128(u128),
+        visit_f32(f32),
+        visit_f64(f64),
+        visit_char(char),
+        visit_str(&str),
+        visit_borrowed_str(&'de str),
+        visit_string(String),
+        visit_bytes(&[u8]),
+        visit_borrowed_bytes(&'de [u8]),
+        visit_byte_buf(Vec<u8>),
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.inner.visit_none()
+    }
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.inner.visit_unit()
+    }
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.inner.visit_some(TracedDeserializer::new(deserializer))
+    }
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        self.inner
+            .visit_newtype_struct(TracedDeserializer::new(deserializer))
+    }
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        self.inner.visit_seq(TracedSeq {
+            inner: seq,
+            names: self.names,
+            index: 0,
+        })
+    }
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.inner.visit_map(TracedMap {
+            inner: map,
+            names: self.names,
+            key: None,
+        })
+    }
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        self.inner.visit_enum(TracedEnum {
+            inner: data,
+            names: self.names,
+        })
+    }
+}
+
+/// Deserializes a value inside a span
+struct TracedSeed<T> {
+    inner: T,
+    name: Cow<'static, str>,
+}
+
+impl<'de, T: DeserializeSeed<'de>> DeserializeSeed<'de> for TracedSeed<T> {
+    type Value = T::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        span(self.name).in_scope(|| {
+            self.inner
+                .deserialize(TracedDeserializer::new(deserializer))
+        })
+    }
+}
+
+/// Sequence elements are named after the struct fields (for formats which encode structs as
+/// tuples) or their index
+struct TracedSeq<A> {
+    inner: A,
+    names: &'static [&'static str],
+    index: u64,
+}
+
+impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for TracedSeq<A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        let name = index_name(self.names, self.index);
+        self.index += 1;
+        self.inner
+            .next_element_seed(TracedSeed { inner: seed, name })
+    }
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+/// Map values are named after the preceding key
+struct TracedMap<A> {
+    inner: A,
+    names: &'static [&'static str],
+    key: Option<Cow<'static, str>>,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for TracedMap<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        self.inner.next_key_seed(KeySeed {
+            inner: seed,
+            names: self.names,
+            key: &mut self.key,
+        })
+    }
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let name = self.key.take().unwrap_or(Cow::Borrowed("value"));
+        self.inner.next_value_seed(TracedSeed { inner: seed, name })
+    }
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+struct TracedEnum<A> {
+    inner: A,
+    names: &'static [&'static str],
+}
+
+impl<'de, A: EnumAccess<'de>> EnumAccess<'de> for TracedEnum<A> {
+    type Error = A::Error;
+    type Variant = TracedVariant<A::Variant>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let mut key = None;
+        let (value, variant) = self.inner.variant_seed(KeySeed {
+            inner: seed,
+            names: self.names,
+            key: &mut key,
+        })?;
+        let name = key.unwrap_or(Cow::Borrowed("variant"));
+        Ok((
+            value,
+            TracedVariant {
+                inner: variant,
+                name,
+            },
+        ))
+    }
+}
+
+struct TracedVariant<A> {
+    inner: A,
+    name: Cow<'static, str>,
+}
+
+impl<'de, A: VariantAccess<'de>> VariantAccess<'de> for TracedVariant<A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.inner.unit_variant()
+    }
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        self.inner.newtype_variant_seed(TracedSeed {
+            inner: seed,
+            name: self.name,
+        })
+    }
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        span(self.name).in_scope(|| {
+            self.inner
+                .tuple_variant(len, TracedVisitor::new(visitor, &[]))
+        })
+    }
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        span(self.name).in_scope(|| {
+            self.inner
+                .struct_variant(fields, TracedVisitor::new(visitor, fields))
+        })
+    }
+}
+
+/// Records the name of a deserialized map key or enum variant
+struct KeySeed<'a, T> {
+    inner: T,
+    names: &'static [&'static str],
+    key: &'a mut Option<Cow<'static, str>>,
+}
+
+impl<'de, T: DeserializeSeed<'de>> DeserializeSeed<'de> for KeySeed<'_, T> {
+    type Value = T::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.inner.deserialize(KeyDeserializer {
+            inner: deserializer,
+            names: self.names,
+            key: self.key,
+        })
+    }
+}
+
+struct KeyDeserializer<'a, D> {
+    inner: D,
+    names: &'static [&'static str],
+    key: &'a mut Option<Cow<'static, str>>,
+}
+impl<'a, D> KeyDeserializer<'a, D> {
+    fn split<V>(self, visitor: V) -> (D, KeyVisitor<'a, V>) {
+        let visitor = KeyVisitor {
+            inner: visitor,
+            names: self.names,
+            key: self.key,
+        };
+        (self.inner, visitor)
+    }
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for KeyDeserializer<'_, D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_i128(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_u128(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+/// Key name for a visited value, either an index into the known names or the key itself
+trait KeyName {
+    fn key_name(&self, names: &'static [&'static str]) -> Cow<'static, str>;
+}
+macro_rules! impl_key_name_index {
+    ($($ty:ty),*) => {$(
+        impl KeyName for $ty {
+            fn key_name(&self, names: &'static [&'static str]) -> Cow<'static, str> {
+                index_name(names, (*self).into())
+            }
+        }
+    )*};
+}
+impl_key_name_index!(u8, u16, u32, u64);
+impl KeyName for &str {
+    fn key_name(&self, _names: &'static [&'static str]) -> Cow<'static, str> {
+        Cow::Owned(self.to_string())
+    }
+}
+impl KeyName for String {
+    fn key_name(&self, _names: &'static [&'static str]) -> Cow<'static, str> {
+        Cow::Owned(self.clone())
+    }
+}
+impl KeyName for &[u8] {
+    fn key_name(&self, _names: &'static [&'static str]) -> Cow<'static, str> {
+        Cow::Owned(String::from_utf8_lossy(self).into_owned())
+    }
+}
+impl KeyName for Vec<u8> {
+    fn key_name(&self, _names: &'static [&'static str]) -> Cow<'static, str> {
+        Cow::Owned(String::from_utf8_lossy(self).into_owned())
+    }
+}
+
+macro_rules! capture_visit {
+    ($($method:ident($ty:ty)),* $(,)?) => {$(
+        fn $method<E: de::Error>(self, v: $ty) -> Result<Self::Value, E> {
+            *self.key = Some(v.key_name(self.names));
+            self.inner.$method(v)
+        }
+    )*};
+}
+
+struct KeyVisitor<'a, V> {
+    inner: V,
+    names: &'static [&'static str],
+    key: &'a mut Option<Cow<'static, str>>,
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for KeyVisitor<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool),
+        visit_i8(i8),
+        visit_i16(i16),
+        visit_i32(i32),
+        visit_i64(i64),
+        visit_i128(i128),
+        visit_u128(u128),
+        visit_f32(f32),
+        visit_f64(f64),
+        visit_char(char),
+    }
+    capture_visit! {
+        visit_u8(u8),
+        visit_u16(u16),
+        visit_u32(u32),
+        visit_u64(u64),
+        visit_str(&str),
+        visit_borrowed_str(&'de str),
+        visit_string(String),
+        visit_bytes(&[u8]),
+        visit_borrowed_bytes(&'de [u8]),
+        visit_byte_buf(Vec<u8>),
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.inner.visit_none()
+    }
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.inner.visit_unit()
+    }
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.inner.visit_some(deserializer)
+    }
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        self.inner.visit_newtype_struct(deserializer)
+    }
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        self.inner.visit_seq(seq)
+    }
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.inner.visit_map(map)
+    }
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        self.inner.visit_enum(data)
+    }
+}
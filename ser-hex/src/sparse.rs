@@ -0,0 +1,147 @@
+use std::{fs, io, path::Path};
+
+use ::serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{from_json, Action, Trace, TraceMetadata, TreeSpan};
+
+/// Contiguous run of captured bytes at an offset of the stream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Segment {
+    offset: usize,
+    #[serde(with = "crate::base64")]
+    data: Vec<u8>,
+}
+impl Segment {
+    fn end(&self) -> usize {
+        self.offset + self.data.len()
+    }
+}
+
+/// Sorted, non-overlapping and non-adjacent segments of captured data
+#[derive(Debug, Default)]
+pub(crate) struct Segments(Vec<Segment>);
+impl Segments {
+    /// Store `bytes` at `offset`, replacing any previously stored bytes there
+    pub(crate) fn write(&mut self, offset: usize, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        let end = offset + bytes.len();
+        // segments overlapping or touching the written range
+        let first = self.0.partition_point(|s| s.end() < offset);
+        let last = self.0.partition_point(|s| s.offset <= end);
+
+        if first == last {
+            let data = bytes.to_vec();
+            self.0.insert(first, Segment { offset, data });
+        } else if last - first == 1 && self.0[first].offset <= offset {
+            // common case of extending or overwriting a single segment
+            let segment = &mut self.0[first];
+            let start = offset - segment.offset;
+            if segment.data.len() < start + bytes.len() {
+                segment.data.resize(start + bytes.len(), 0);
+            }
+            segment.data[start..start + bytes.len()].copy_from_slice(bytes);
+        } else {
+            let start = self.0[first].offset.min(offset);
+            let mut data = vec![0; self.0[last - 1].end().max(end) - start];
+            for segment in self.0.drain(first..last) {
+                data[segment.offset - start..segment.end() - start].copy_from_slice(&segment.data);
+            }
+            data[offset - start..end - start].copy_from_slice(bytes);
+            self.0.insert(
+                first,
+                Segment {
+                    offset: start,
+                    data,
+                },
+            );
+        }
+    }
+    /// Move all segments forward by `delta` bytes
+    pub(crate) fn shift(&mut self, delta: usize) {
+        for segment in &mut self.0 {
+            segment.offset += delta;
+        }
+    }
+    /// Size of the equivalent dense data
+    fn len(&self) -> usize {
+        self.0.last().map_or(0, Segment::end)
+    }
+    /// Dense data with the gaps between segments zeroed
+    pub(crate) fn to_dense(&self) -> Vec<u8> {
+        let mut data = vec![0; self.len()];
+        for segment in &self.0 {
+            data[segment.offset..segment.end()].copy_from_slice(&segment.data);
+        }
+        data
+    }
+    /// Lowercase hex encoded SHA-256 of the dense data, without materializing it
+    pub(crate) fn sha256_hex(&self) -> String {
+        let mut hasher = Sha256::new();
+        let mut offset = 0;
+        for segment in &self.0 {
+            let mut gap = segment.offset - offset;
+            while gap > 0 {
+                let zeros = [0; 4096];
+                let n = gap.min(zeros.len());
+                hasher.update(&zeros[..n]);
+                gap -= n;
+            }
+            hasher.update(&segment.data);
+            offset = segment.end();
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+/// Trace whose data is stored as a list of segments rather than a dense buffer
+#[derive(Serialize, Deserialize)]
+struct SparseTrace<S, A, M> {
+    version: u32,
+    segments: S,
+    start_index: usize,
+    root: A,
+    metadata: M,
+}
+
+/// Save a trace recorded with [`TraceOptions::sparse_data`](crate::TraceOptions::sparse_data),
+/// whose data is held in `segments` rather than [`Trace::data`]
+pub(crate) fn save<D: AsRef<[u8]>>(
+    path: &Path,
+    trace: &Trace<D>,
+    segments: &Segments,
+) -> Result<(), io::Error> {
+    let json = serde_json::to_string(&SparseTrace {
+        version: trace.version,
+        segments: &segments.0,
+        start_index: trace.start_index,
+        root: &trace.root,
+        metadata: &trace.metadata,
+    })?;
+    fs::write(path, json)
+}
+
+/// Deserialize a sparse trace, filling the gaps between segments with zeros
+pub(crate) fn load<D: AsRef<[u8]> + From<Vec<u8>>>(json: &[u8]) -> anyhow::Result<Trace<D>> {
+    let sparse: SparseTrace<Vec<Segment>, Action<TreeSpan>, TraceMetadata> = from_json(json)?;
+    let mut segments = Segments::default();
+    for segment in &sparse.segments {
+        segments.write(segment.offset, &segment.data);
+    }
+    let mut trace = Trace {
+        version: sparse.version,
+        data: segments.to_dense().into(),
+        start_index: sparse.start_index,
+        root: sparse.root,
+        metadata: sparse.metadata,
+    };
+    trace.upgrade();
+    trace.verify_checksum()?;
+    Ok(trace)
+}
@@ -0,0 +1,102 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use memmap2::{Mmap, MmapMut};
+use serde::{Deserialize, Serialize};
+
+use crate::{from_json, Action, Header, Trace, TraceMetadata, TreeSpan};
+
+/// JSON part of a trace whose data is stored in a raw sidecar file
+#[derive(Serialize, Deserialize)]
+struct SplitTrace<A, M> {
+    version: u32,
+    /// Sidecar file relative to the trace file
+    data_file: PathBuf,
+    start_index: usize,
+    root: A,
+    metadata: M,
+}
+
+pub(crate) fn data_path(path: &Path, data_file: &Path) -> PathBuf {
+    path.parent().unwrap_or(Path::new("")).join(data_file)
+}
+
+pub(crate) fn load<D: AsRef<[u8]>>(
+    path: &Path,
+    json: &[u8],
+    load_data: impl FnOnce(&Path) -> anyhow::Result<D>,
+) -> anyhow::Result<Trace<D>> {
+    let split: SplitTrace<Action<TreeSpan>, TraceMetadata> = from_json(json)?;
+    let mut trace = Trace {
+        version: split.version,
+        data: load_data(&data_path(path, &split.data_file))?,
+        start_index: split.start_index,
+        root: split.root,
+        metadata: split.metadata,
+    };
+    trace.upgrade();
+    trace.verify_checksum()?;
+    Ok(trace)
+}
+
+impl<D: AsRef<[u8]>> Trace<D> {
+    /// Save the trace with data stored raw in a sidecar file (`<path>.bin`) rather than base64
+    /// encoded in the JSON, allowing it to be memory mapped by [`Trace::open_mmap`]
+    pub fn save_split(&self, path: impl AsRef<Path>) -> Result<(), std::io::Error> {
+        let path = path.as_ref();
+        let mut data_file = path.file_name().unwrap_or_default().to_owned();
+        data_file.push(".bin");
+        let data_path = data_path(path, data_file.as_ref());
+
+        // replace rather than truncate the sidecar so existing mappings of it stay valid
+        let mut tmp_path = data_path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, self.data.as_ref())?;
+        fs::rename(&tmp_path, &data_path)?;
+
+        let json = serde_json::to_string(&SplitTrace {
+            version: self.version,
+            data_file: PathBuf::from(data_file),
+            start_index: self.start_index,
+            root: &self.root,
+            metadata: &self.metadata,
+        })?;
+        fs::write(path, json)
+    }
+}
+
+impl Trace<Mmap> {
+    /// Open a trace with its data memory mapped instead of read into memory
+    ///
+    /// The data of traces saved with [`Trace::save_split`] is mapped directly from the sidecar
+    /// file without a copy. Regular and chunked traces are decoded into an anonymous mapping.
+    pub fn open_mmap(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read(path)?;
+        let header = Header::parse(&json)?;
+        if header.data_file.is_some() {
+            return load(path, &json, |data_path| {
+                let file = fs::File::open(data_path)?;
+                // SAFETY: sidecars are replaced rather than modified in place by save_split
+                Ok(unsafe { Mmap::map(&file)? })
+            });
+        }
+
+        let trace: Trace = if header.data_chunks.is_some() {
+            crate::chunked::load(path, &json)?
+        } else {
+            Trace::from_slice(&json)?
+        };
+        let mut data = MmapMut::map_anon(trace.data.len())?;
+        data.copy_from_slice(&trace.data);
+        Ok(Trace {
+            version: trace.version,
+            data: data.make_read_only()?,
+            start_index: trace.start_index,
+            root: trace.root,
+            metadata: trace.metadata,
+        })
+    }
+}
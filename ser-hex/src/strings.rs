@@ -0,0 +1,154 @@
+//! Traces whose span names are stored once in a string table and referenced by index, see
+//! [`Trace::save_interned`]
+
+use std::{borrow::Cow, collections::HashMap, fs, io, path::Path};
+
+use ::serde::{Deserialize, Serialize};
+
+use crate::{
+    base64, from_json, Action, Location, ReadSpan, Timing, Trace, TraceMetadata, TreeSpan, Value,
+    INTERNED_TRACE_VERSION, TRACE_VERSION,
+};
+
+/// Span name of an interned trace
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Name {
+    /// Index into the string table of the trace
+    Index(usize),
+    Name(Cow<'static, str>),
+}
+
+/// [`ReadSpan`] as stored in an interned trace
+#[derive(Serialize, Deserialize)]
+struct InternedSpan {
+    name: Name,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<Value>,
+    actions: Vec<Action<InternedSpan>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    timings: Vec<Timing>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    location: Option<Location>,
+}
+
+/// Trace saved with [`Trace::save_interned`]. Inline sub-traces are stored as is
+#[derive(Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "D: AsRef<[u8]>, A: Serialize, M: Serialize, S: Serialize",
+    deserialize = "D: From<Vec<u8>>, A: Deserialize<'de>, M: Deserialize<'de>, S: Deserialize<'de>"
+))]
+struct InternedTrace<D, A, M, S> {
+    version: u32,
+    #[serde(
+        serialize_with = "base64::serialize",
+        deserialize_with = "base64::deserialize"
+    )]
+    data: D,
+    start_index: usize,
+    root: A,
+    metadata: M,
+    strings: S,
+}
+
+/// Names of spans occurring more than once, most frequent first
+fn repeated_names(root: &Action<TreeSpan>) -> Vec<&str> {
+    fn count<'a>(action: &'a Action<TreeSpan>, counts: &mut HashMap<&'a str, usize>) {
+        if let Action::Span(span) = action {
+            *counts.entry(&span.0.name).or_default() += 1;
+            for action in &span.0.actions {
+                count(action, counts);
+            }
+        }
+    }
+
+    let mut counts = HashMap::new();
+    count(root, &mut counts);
+    let mut names: Vec<_> = counts.into_iter().filter(|(_, n)| *n > 1).collect();
+    names.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    names.into_iter().map(|(name, _)| name).collect()
+}
+
+fn intern(action: &Action<TreeSpan>, indexes: &HashMap<&str, usize>) -> Action<InternedSpan> {
+    let span = match action {
+        Action::Span(span) => &span.0,
+        Action::Read(size) => return Action::Read(*size),
+        Action::Seek(seek) => return Action::Seek(*seek),
+        Action::Error(error) => return Action::Error(error.clone()),
+        Action::ReadData(data) => return Action::ReadData(data.clone()),
+        Action::SubTrace(sub) => return Action::SubTrace(sub.clone()),
+    };
+    Action::Span(InternedSpan {
+        name: match indexes.get(span.name.as_ref()) {
+            Some(&index) => Name::Index(index),
+            None => Name::Name(span.name.clone()),
+        },
+        value: span.value.clone(),
+        actions: span.actions.iter().map(|a| intern(a, indexes)).collect(),
+        timings: span.timings.clone(),
+        location: span.location.clone(),
+    })
+}
+
+fn resolve(action: Action<InternedSpan>, strings: &[String]) -> anyhow::Result<Action<TreeSpan>> {
+    let span = match action {
+        Action::Span(span) => span,
+        Action::Read(size) => return Ok(Action::Read(size)),
+        Action::Seek(seek) => return Ok(Action::Seek(seek)),
+        Action::Error(error) => return Ok(Action::Error(error)),
+        Action::ReadData(data) => return Ok(Action::ReadData(data)),
+        Action::SubTrace(sub) => return Ok(Action::SubTrace(sub)),
+    };
+    let name = match span.name {
+        Name::Index(index) => match strings.get(index) {
+            Some(name) => name.clone().into(),
+            None => anyhow::bail!("string index {index} out of range"),
+        },
+        Name::Name(name) => name,
+    };
+    Ok(Action::Span(TreeSpan(ReadSpan {
+        name,
+        value: span.value,
+        actions: span
+            .actions
+            .into_iter()
+            .map(|a| resolve(a, strings))
+            .collect::<anyhow::Result<_>>()?,
+        timings: span.timings,
+        location: span.location,
+    })))
+}
+
+/// Deserialize an interned trace, resolving its span names
+pub(crate) fn load<D: AsRef<[u8]> + From<Vec<u8>>>(json: &[u8]) -> anyhow::Result<Trace<D>> {
+    let interned: InternedTrace<D, Action<InternedSpan>, TraceMetadata, Vec<String>> =
+        from_json(json)?;
+    let trace = Trace {
+        version: TRACE_VERSION,
+        data: interned.data,
+        start_index: interned.start_index,
+        root: resolve(interned.root, &interned.strings)?,
+        metadata: interned.metadata,
+    };
+    trace.verify_checksum()?;
+    Ok(trace)
+}
+
+impl<D: AsRef<[u8]>> Trace<D> {
+    /// Save the trace with span names which occur more than once stored once in a string table
+    /// and referenced by index, shrinking traces with many repeated names such as those of
+    /// backtrace based tracers. Loaded by [`Trace::load`] like any other trace
+    pub fn save_interned(&self, path: impl AsRef<Path>) -> Result<(), io::Error> {
+        let strings = repeated_names(&self.root);
+        let indexes = strings.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+        let json = serde_json::to_string(&InternedTrace {
+            version: INTERNED_TRACE_VERSION,
+            data: self.data.as_ref(),
+            start_index: self.start_index,
+            root: intern(&self.root, &indexes),
+            metadata: &self.metadata,
+            strings: &strings,
+        })?;
+        fs::write(path, json)
+    }
+}
@@ -0,0 +1,113 @@
+//! Assertions for parser test suites
+//!
+//! ```ignore
+//! let trace = ser_hex::Trace::load("trace.json")?;
+//! ser_hex::testing::assert_full_coverage(&trace);
+//! ```
+
+use std::{fmt::Write as _, ops::Range};
+
+use crate::{FlatAction, Trace};
+
+/// Panic with a listing of the unread ranges and their surrounding spans unless every byte of
+/// the trace data was read
+#[track_caller]
+pub fn assert_full_coverage<D: AsRef<[u8]>>(trace: &Trace<D>) {
+    assert_coverage_with_gaps(trace, &[]);
+}
+
+/// Like [`assert_full_coverage`], but bytes within `gaps` (e.g. padding or reserved fields) may
+/// be left unread
+#[track_caller]
+pub fn assert_coverage_with_gaps<D: AsRef<[u8]>>(trace: &Trace<D>, gaps: &[Range<usize>]) {
+    if let Err(e) = check_coverage(trace, gaps) {
+        panic!("{e}");
+    }
+}
+
+/// Byte ranges of the data which were neither read nor within `gaps`
+pub fn unread_ranges<D: AsRef<[u8]>>(trace: &Trace<D>, gaps: &[Range<usize>]) -> Vec<Range<usize>> {
+    let mut gaps = gaps.to_vec();
+    gaps.sort_by_key(|g| g.start);
+
+    let mut unread = vec![];
+    for mut range in trace.uncovered_ranges() {
+        for gap in &gaps {
+            if gap.end <= range.start || gap.is_empty() {
+                continue;
+            }
+            if gap.start >= range.end {
+                break;
+            }
+            if gap.start > range.start {
+                unread.push(range.start..gap.start);
+            }
+            range.start = gap.end.min(range.end);
+        }
+        if !range.is_empty() {
+            unread.push(range);
+        }
+    }
+    unread
+}
+
+/// Fail with a listing of the unread ranges and their surrounding spans unless every byte of the
+/// trace data outside of `gaps` was read
+pub fn check_coverage<D: AsRef<[u8]>>(
+    trace: &Trace<D>,
+    gaps: &[Range<usize>],
+) -> anyhow::Result<()> {
+    let unread = unread_ranges(trace, gaps);
+    if unread.is_empty() {
+        return Ok(());
+    }
+
+    let reads: Vec<_> = trace
+        .iter_flat()
+        .filter(|flat| flat.action.read_size().is_some() && !flat.range.is_empty())
+        .collect();
+
+    let total: usize = unread.iter().map(|r| r.len()).sum();
+    let mut message = format!(
+        "{total} of {} bytes were not read in {} ranges:",
+        trace.data.as_ref().len(),
+        unread.len()
+    );
+    for range in &unread {
+        let before = reads
+            .iter()
+            .filter(|r| r.range.end <= range.start)
+            .max_by_key(|r| r.range.end);
+        let after = reads
+            .iter()
+            .filter(|r| r.range.start >= range.end)
+            .min_by_key(|r| r.range.start);
+        write!(
+            message,
+            "\n  {:#x}..{:#x} ({} bytes)\n    previous read: {}\n    next read:     {}",
+            range.start,
+            range.end,
+            range.len(),
+            describe(before),
+            describe(after)
+        )
+        .unwrap();
+    }
+    anyhow::bail!(message)
+}
+
+/// Span path and range of the read next to an unread range
+fn describe(flat: Option<&FlatAction>) -> String {
+    match flat {
+        Some(flat) => {
+            let names: Vec<_> = flat.path.iter().map(|s| s.span.0.name.as_ref()).collect();
+            format!(
+                "{} ({:#x}..{:#x})",
+                names.join(" > "),
+                flat.range.start,
+                flat.range.end
+            )
+        }
+        None => "-".into(),
+    }
+}
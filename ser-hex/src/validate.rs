@@ -0,0 +1,98 @@
+use std::{fmt, ops::Range};
+
+use crate::{Action, Trace, TreeSpan};
+
+/// An inconsistency found in a trace by [`Trace::validate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// Indices of the offending action starting from the children of the root span
+    pub path: Vec<usize>,
+    pub kind: ProblemKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProblemKind {
+    StartOutOfBounds { start_index: usize, len: usize },
+    ReadOutOfBounds { range: Range<usize>, len: usize },
+    SeekOutOfBounds { to: usize, len: usize },
+    EmptySpan { name: String },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ProblemKind::StartOutOfBounds { start_index, len } => {
+                write!(f, "start index {start_index} is past end of data ({len})")?
+            }
+            ProblemKind::ReadOutOfBounds { range, len } => {
+                write!(f, "read {range:?} is past end of data ({len})")?
+            }
+            ProblemKind::SeekOutOfBounds { to, len } => {
+                write!(f, "seek to {to} is past end of data ({len})")?
+            }
+            ProblemKind::EmptySpan { name } => write!(f, "span {name:?} is empty")?,
+        }
+        if !self.path.is_empty() {
+            write!(f, " at {:?}", self.path)?;
+        }
+        Ok(())
+    }
+}
+
+impl<D: AsRef<[u8]>> Trace<D> {
+    /// Check the trace for inconsistencies such as reads past the end of the data or empty spans
+    pub fn validate(&self) -> Vec<Problem> {
+        let len = self.data.as_ref().len();
+        let mut problems = vec![];
+
+        if self.start_index > len {
+            problems.push(Problem {
+                path: vec![],
+                kind: ProblemKind::StartOutOfBounds {
+                    start_index: self.start_index,
+                    len,
+                },
+            });
+        }
+
+        for flat in self.iter_flat() {
+            let kind = match flat.action {
+                Action::Read(_) | Action::ReadData(_) if flat.range.end > len => {
+                    ProblemKind::ReadOutOfBounds {
+                        range: flat.range.clone(),
+                        len,
+                    }
+                }
+                Action::Seek(seek) if seek.to > len => {
+                    ProblemKind::SeekOutOfBounds { to: seek.to, len }
+                }
+                _ => continue,
+            };
+            problems.push(Problem {
+                path: flat.index_path(),
+                kind,
+            });
+        }
+
+        fn empty_spans(action: &Action<TreeSpan>, path: &mut Vec<usize>, out: &mut Vec<Problem>) {
+            if let Action::Span(span) = action {
+                if span.0.actions.is_empty() {
+                    out.push(Problem {
+                        path: path.clone(),
+                        kind: ProblemKind::EmptySpan {
+                            name: span.0.name.to_string(),
+                        },
+                    });
+                }
+                for (i, action) in span.0.actions.iter().enumerate() {
+                    path.push(i);
+                    empty_spans(action, path, out);
+                    path.pop();
+                }
+            }
+        }
+        empty_spans(&self.root, &mut vec![], &mut problems);
+
+        problems
+    }
+}